@@ -0,0 +1,111 @@
+/* The C API of the chip8 library, built with `cargo build --release -p
+ * chip8-ffi` into target/release/libchip8_ffi.so (chip8_ffi.dll,
+ * libchip8_ffi.dylib). Regenerate with `cbindgen --config cbindgen.toml
+ * --output include/chip8.h` in ffi/ after changing src/lib.rs; the tests
+ * check that it was. */
+
+#ifndef CHIP8_H
+#define CHIP8_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Returned when a call did what was asked
+ */
+#define CHIP8_OK 0
+
+/**
+ * Returned by `chip8_step` when the program ran into 0000, which ends it
+ */
+#define CHIP8_EXITED 1
+
+/**
+ * Returned when a ROM can't be loaded or an instruction failed
+ */
+#define CHIP8_ERROR -1
+
+/**
+ * Returned when a pointer that must not be null was
+ */
+#define CHIP8_NULL -2
+
+/**
+ * A machine, with memory on a flat 4K bus
+ */
+typedef struct Chip8 Chip8;
+
+/**
+ * A machine in its power-on state with nothing loaded, to be freed with
+ * `chip8_free`
+ */
+Chip8 *chip8_new(void);
+
+/**
+ * Frees a machine from `chip8_new`. Null is ignored.
+ *
+ * # Safety
+ *
+ * `chip8` is null or came from `chip8_new` and hasn't been freed.
+ */
+void chip8_free(Chip8 *chip8);
+
+/**
+ * Resets the machine with the `len` bytes at `data` as the program.
+ * Returns `CHIP8_OK`, `CHIP8_ERROR` if they aren't a ROM that fits in
+ * memory, leaving the machine as it was, or `CHIP8_NULL`.
+ *
+ * # Safety
+ *
+ * `chip8` is null or came from `chip8_new`, and `data` is null or points
+ * to `len` bytes.
+ */
+int chip8_load_rom(Chip8 *chip8, const uint8_t *data, size_t len);
+
+/**
+ * Executes one instruction. Returns `CHIP8_OK`, `CHIP8_EXITED` if it was
+ * 0000, `CHIP8_ERROR` if it failed, or `CHIP8_NULL`. Exiting and failing
+ * both leave the program stopped.
+ *
+ * # Safety
+ *
+ * `chip8` is null or came from `chip8_new`.
+ */
+int chip8_step(Chip8 *chip8);
+
+/**
+ * Counts the timers down, to be called at 60 Hz. Null is ignored.
+ *
+ * # Safety
+ *
+ * `chip8` is null or came from `chip8_new`.
+ */
+void chip8_tick_timers(Chip8 *chip8);
+
+/**
+ * Copies the display into `out`, a byte for each pixel that is 1 when it
+ * is lit, in rows of 64. Returns the number of rows, 32 or 64 in hires
+ * mode, copying no more than `len` bytes, or 0 if `chip8` or `out` is
+ * null.
+ *
+ * # Safety
+ *
+ * `chip8` is null or came from `chip8_new`, and `out` is null or points
+ * to `len` writable bytes.
+ */
+size_t chip8_get_display(const Chip8 *chip8, uint8_t *out, size_t len);
+
+/**
+ * Presses or releases `key`, 0 to F. Other keys, and a null `chip8`, are
+ * ignored.
+ *
+ * # Safety
+ *
+ * `chip8` is null or came from `chip8_new`.
+ */
+void chip8_set_key(Chip8 *chip8, uint8_t key, bool pressed);
+
+#endif /* CHIP8_H */
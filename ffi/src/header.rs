@@ -0,0 +1,181 @@
+//! Keeps `include/chip8.h` in step with this crate. The header is what
+//! cbindgen makes of `lib.rs` with `cbindgen.toml`, and this test makes the
+//! same from the same two files for the few kinds of item the API uses, so
+//! a change to one without the other fails the build rather than drifting.
+//! `CHIP8_UPDATE_HEADER=1 cargo test -p chip8-ffi` rewrites the header
+//! where cbindgen isn't installed.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/// Longest line cbindgen writes a declaration on before wrapping it
+const LINE_LENGTH: usize = 100;
+
+#[test]
+fn header_is_up_to_date() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let config = fs::read_to_string(root.join("cbindgen.toml")).unwrap();
+    let generated = generate(&config, include_str!("lib.rs"));
+    let path = root.join("include/chip8.h");
+    if env::var_os("CHIP8_UPDATE_HEADER").is_some() {
+        fs::write(&path, &generated).unwrap();
+    }
+    let header = fs::read_to_string(&path).unwrap();
+    assert!(
+        header == generated,
+        "include/chip8.h is out of date with src/lib.rs: regenerate it with cbindgen, or run \
+         the tests with CHIP8_UPDATE_HEADER=1"
+    );
+}
+
+/// The header for the items in `source`: constants, then opaque structs,
+/// then functions, as cbindgen orders them
+fn generate(config: &str, source: &str) -> String {
+    let preamble = config
+        .split_once("header = \"\"\"")
+        .and_then(|(_, rest)| rest.split_once("\"\"\""))
+        .expect("cbindgen.toml has a header")
+        .0;
+    let guard = config
+        .lines()
+        .find_map(|line| line.strip_prefix("include_guard = "))
+        .expect("cbindgen.toml has an include guard")
+        .trim_matches('"');
+
+    let (mut constants, mut types, mut functions) = (Vec::new(), Vec::new(), Vec::new());
+    let mut docs: Vec<&str> = Vec::new();
+    let mut lines = source.lines().map(str::trim);
+    while let Some(line) = lines.next() {
+        if line == "mod tests {" {
+            // Nothing after the tests is exported
+            break;
+        }
+        if let Some(doc) = line.strip_prefix("///") {
+            docs.push(doc.strip_prefix(' ').unwrap_or(doc));
+            continue;
+        }
+        if line.starts_with("#[") {
+            continue;
+        }
+        let comment = comment(&docs);
+        docs.clear();
+        if let Some(constant) = line.strip_prefix("pub const ") {
+            let (name, value) = constant.split_once(':').expect("a typed constant");
+            let value = value.split_once(" = ").expect("a value").1;
+            constants.push(format!(
+                "{}#define {} {}",
+                comment,
+                name,
+                value.trim_end_matches(';')
+            ));
+        } else if let Some(name) = line.strip_prefix("pub struct ") {
+            let name = name.trim_end_matches(" {");
+            types.push(format!("{}typedef struct {} {};", comment, name, name));
+        } else if line.starts_with("pub extern \"C\" fn ")
+            || line.starts_with("pub unsafe extern \"C\" fn ")
+        {
+            let mut signature = line.to_string();
+            while !signature.ends_with('{') {
+                signature += lines.next().expect("a function body");
+            }
+            functions.push(format!("{}{}", comment, declaration(&signature)));
+        } else if line.starts_with("pub ") {
+            panic!(
+                "cbindgen would export `{}`, which this doesn't follow",
+                line
+            );
+        }
+    }
+
+    let mut header = format!(
+        "{}\n\n#ifndef {}\n#define {}\n\n#include <stdarg.h>\n#include <stdbool.h>\n\
+         #include <stddef.h>\n#include <stdint.h>\n#include <stdlib.h>\n",
+        preamble, guard, guard
+    );
+    for item in constants.iter().chain(&types).chain(&functions) {
+        header += "\n";
+        header += item;
+        header += "\n";
+    }
+    header += &format!("\n#endif /* {} */\n", guard);
+    header
+}
+
+/// Doc comment lines as a doxygen block, or nothing
+fn comment(docs: &[&str]) -> String {
+    if docs.is_empty() {
+        return String::new();
+    }
+    let mut comment = String::from("/**\n");
+    for doc in docs {
+        match doc.is_empty() {
+            true => comment += " *\n",
+            false => comment += &format!(" * {}\n", doc),
+        }
+    }
+    comment + " */\n"
+}
+
+/// The C declaration of the Rust function `signature`, up to its `{`
+fn declaration(signature: &str) -> String {
+    let (name, rest) = signature
+        .split_once(" fn ")
+        .expect("a function")
+        .1
+        .split_once('(')
+        .expect("arguments");
+    let (arguments, returns) = rest.rsplit_once(')').expect("arguments");
+    let returns = returns
+        .trim_end_matches('{')
+        .trim()
+        .strip_prefix("-> ")
+        .map_or("void".to_string(), c_type);
+    let arguments: Vec<String> = arguments
+        .split(',')
+        .map(str::trim)
+        .filter(|argument| !argument.is_empty())
+        .map(|argument| {
+            let (name, ty) = argument.split_once(": ").expect("a typed argument");
+            let ty = c_type(ty);
+            match ty.ends_with('*') {
+                true => format!("{}{}", ty, name),
+                false => format!("{} {}", ty, name),
+            }
+        })
+        .collect();
+    let arguments = match arguments.is_empty() {
+        true => "void".to_string(),
+        false => arguments.join(", "),
+    };
+    let separator = if returns.ends_with('*') { "" } else { " " };
+    let declaration = format!("{}{}{}({});", returns, separator, name, arguments);
+    assert!(
+        declaration.len() <= LINE_LENGTH,
+        "cbindgen would wrap `{}`, which this doesn't follow",
+        declaration
+    );
+    declaration
+}
+
+/// The C spelling of the Rust type `ty`, pointers ending in `*`
+fn c_type(ty: &str) -> String {
+    let ty = ty.trim();
+    if let Some(pointee) = ty.strip_prefix("*const ") {
+        return format!("const {} *", c_type(pointee));
+    }
+    if let Some(pointee) = ty.strip_prefix("*mut ") {
+        return format!("{} *", c_type(pointee));
+    }
+    match ty {
+        "c_int" => "int",
+        "bool" => "bool",
+        "u8" => "uint8_t",
+        "u16" => "uint16_t",
+        "u32" => "uint32_t",
+        "usize" => "size_t",
+        "Chip8" => "Chip8",
+        _ => panic!("no C type for `{}` here", ty),
+    }
+    .to_string()
+}
@@ -0,0 +1,201 @@
+//! A C API over the core, built into a shared library of its own with
+//! `cargo build --release -p chip8-ffi` and declared in `include/chip8.h`,
+//! so frontends in other languages can run the same machine. The machine
+//! is opaque to C and only handled through the pointer `chip8_new` gives
+//! out.
+
+#[cfg(test)]
+mod header;
+
+use chip8::Chip8Error;
+use std::os::raw::c_int;
+use std::slice;
+
+/// Returned when a call did what was asked
+pub const CHIP8_OK: c_int = 0;
+/// Returned by `chip8_step` when the program ran into 0000, which ends it
+pub const CHIP8_EXITED: c_int = 1;
+/// Returned when a ROM can't be loaded or an instruction failed
+pub const CHIP8_ERROR: c_int = -1;
+/// Returned when a pointer that must not be null was
+pub const CHIP8_NULL: c_int = -2;
+
+/// A machine, with memory on a flat 4K bus
+pub struct Chip8 {
+    machine: chip8::Chip8,
+}
+
+/// A machine in its power-on state with nothing loaded, to be freed with
+/// `chip8_free`
+#[no_mangle]
+pub extern "C" fn chip8_new() -> *mut Chip8 {
+    let machine = chip8::Chip8::builder()
+        .build()
+        .expect("nothing to load can fail");
+    Box::into_raw(Box::new(Chip8 { machine }))
+}
+
+/// Frees a machine from `chip8_new`. Null is ignored.
+///
+/// # Safety
+///
+/// `chip8` is null or came from `chip8_new` and hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_free(chip8: *mut Chip8) {
+    if !chip8.is_null() {
+        drop(Box::from_raw(chip8));
+    }
+}
+
+/// Resets the machine with the `len` bytes at `data` as the program.
+/// Returns `CHIP8_OK`, `CHIP8_ERROR` if they aren't a ROM that fits in
+/// memory, leaving the machine as it was, or `CHIP8_NULL`.
+///
+/// # Safety
+///
+/// `chip8` is null or came from `chip8_new`, and `data` is null or points
+/// to `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_rom(chip8: *mut Chip8, data: *const u8, len: usize) -> c_int {
+    if chip8.is_null() || data.is_null() {
+        return CHIP8_NULL;
+    }
+    let data = slice::from_raw_parts(data, len);
+    match (*chip8).machine.load_rom(data) {
+        Ok(()) => CHIP8_OK,
+        Err(_) => CHIP8_ERROR,
+    }
+}
+
+/// Executes one instruction. Returns `CHIP8_OK`, `CHIP8_EXITED` if it was
+/// 0000, `CHIP8_ERROR` if it failed, or `CHIP8_NULL`. Exiting and failing
+/// both leave the program stopped.
+///
+/// # Safety
+///
+/// `chip8` is null or came from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_step(chip8: *mut Chip8) -> c_int {
+    if chip8.is_null() {
+        return CHIP8_NULL;
+    }
+    match (*chip8).machine.step() {
+        Ok(()) => CHIP8_OK,
+        Err(Chip8Error::Exit { .. }) => CHIP8_EXITED,
+        Err(_) => CHIP8_ERROR,
+    }
+}
+
+/// Counts the timers down, to be called at 60 Hz. Null is ignored.
+///
+/// # Safety
+///
+/// `chip8` is null or came from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_tick_timers(chip8: *mut Chip8) {
+    if let Some(chip8) = chip8.as_mut() {
+        chip8.machine.tick_timers();
+    }
+}
+
+/// Copies the display into `out`, a byte for each pixel that is 1 when it
+/// is lit, in rows of 64. Returns the number of rows, 32 or 64 in hires
+/// mode, copying no more than `len` bytes, or 0 if `chip8` or `out` is
+/// null.
+///
+/// # Safety
+///
+/// `chip8` is null or came from `chip8_new`, and `out` is null or points
+/// to `len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_get_display(chip8: *const Chip8, out: *mut u8, len: usize) -> usize {
+    if chip8.is_null() || out.is_null() {
+        return 0;
+    }
+    let display = (*chip8).machine.display();
+    let out = slice::from_raw_parts_mut(out, len);
+    for (byte, (_, _, lit)) in out.iter_mut().zip(display.pixels()) {
+        *byte = lit as u8;
+    }
+    display.height()
+}
+
+/// Presses or releases `key`, 0 to F. Other keys, and a null `chip8`, are
+/// ignored.
+///
+/// # Safety
+///
+/// `chip8` is null or came from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_key(chip8: *mut Chip8, key: u8, pressed: bool) {
+    if let Some(state) = chip8
+        .as_mut()
+        .and_then(|chip8| chip8.machine.keys.get_mut(key as usize))
+    {
+        *state = pressed;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    #[test]
+    fn null_pointers_are_refused() {
+        unsafe {
+            let chip8 = chip8_new();
+            assert_eq!(chip8_load_rom(chip8, ptr::null(), 0), CHIP8_NULL);
+            assert_eq!(
+                chip8_load_rom(ptr::null_mut(), [0x00, 0xE0].as_ptr(), 2),
+                CHIP8_NULL
+            );
+            assert_eq!(chip8_step(ptr::null_mut()), CHIP8_NULL);
+            assert_eq!(chip8_get_display(chip8, ptr::null_mut(), 0), 0);
+            chip8_tick_timers(ptr::null_mut());
+            chip8_set_key(ptr::null_mut(), 1, true);
+            chip8_free(chip8);
+        }
+    }
+
+    #[test]
+    fn exit_is_returned_rather_than_ending_the_process() {
+        unsafe {
+            let chip8 = chip8_new();
+            let rom = [0x00, 0xE0, 0x00, 0x00];
+            assert_eq!(chip8_load_rom(chip8, rom.as_ptr(), rom.len()), CHIP8_OK);
+            assert_eq!(chip8_step(chip8), CHIP8_OK);
+            assert_eq!(chip8_step(chip8), CHIP8_EXITED);
+            chip8_free(chip8);
+        }
+    }
+
+    #[test]
+    fn failed_instructions_are_errors() {
+        unsafe {
+            let chip8 = chip8_new();
+            // RET with nothing on the stack
+            let rom = [0x00, 0xEE];
+            assert_eq!(chip8_load_rom(chip8, rom.as_ptr(), rom.len()), CHIP8_OK);
+            assert_eq!(chip8_step(chip8), CHIP8_ERROR);
+            chip8_free(chip8);
+        }
+    }
+
+    #[test]
+    fn display_is_copied_a_byte_a_pixel() {
+        unsafe {
+            let chip8 = chip8_new();
+            // Draw the font's 0 at the top left
+            let rom = [0xF0, 0x29, 0xD0, 0x05];
+            assert_eq!(chip8_load_rom(chip8, rom.as_ptr(), rom.len()), CHIP8_OK);
+            assert_eq!(chip8_step(chip8), CHIP8_OK);
+            assert_eq!(chip8_step(chip8), CHIP8_OK);
+            let mut out = [0xAA; 64 * 32];
+            assert_eq!(chip8_get_display(chip8, out.as_mut_ptr(), out.len()), 32);
+            assert_eq!(out[..5], [1, 1, 1, 1, 0]);
+            assert_eq!(out[64..69], [1, 0, 0, 1, 0]);
+            chip8_free(chip8);
+        }
+    }
+}
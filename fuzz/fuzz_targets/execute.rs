@@ -0,0 +1,28 @@
+//! Runs arbitrary bytes as a program. Any panic is a bug: bad programs
+//! have to end in a `Chip8Error`.
+
+#![no_main]
+
+use chip8::{rom, Chip8, PC_START};
+use libfuzzer_sys::fuzz_target;
+
+/// Enough for loops to wander around memory without making runs slow
+const MAX_STEPS: usize = 10_000;
+
+fuzz_target!(|data: &[u8]| {
+    let program = &data[..data.len().min(rom::max_size(PC_START))];
+    let mut chip8 = Chip8::builder()
+        .with_program(program)
+        .with_seed(0)
+        .build()
+        .unwrap();
+    for step in 0..MAX_STEPS {
+        if step % 16 == 0 {
+            chip8.tick_timers();
+        }
+        let result = chip8.fetch().and_then(|op| chip8.execute(op));
+        if result.is_err() {
+            break;
+        }
+    }
+});
@@ -0,0 +1,138 @@
+"""CHIP-8 machines for Python scripts and notebooks, run by the Rust core
+through its C API (`ffi/include/chip8.h`). Build the library first with
+`cargo build --release -p chip8-ffi`; it is looked for in
+`target/release`, or wherever `CHIP8_LIBRARY` points.
+
+    import chip8
+
+    machine = chip8.Chip8("roms/pong.ch8")
+    machine.run_frames(60)
+    machine.press(1)
+    print(machine)
+
+`display` is a bytes object of one byte per pixel in rows of 64, so
+`numpy.frombuffer(machine.display, numpy.uint8).reshape(machine.height, 64)`
+gives it as an array.
+"""
+
+import ctypes
+import os
+import sys
+
+_ROOT = os.path.dirname(os.path.dirname(os.path.abspath(__file__)))
+
+
+class Chip8Error(Exception):
+    """A ROM that can't be loaded, or an instruction that failed"""
+
+
+class Exited(Chip8Error):
+    """The program ran into 0000, which ends it"""
+
+
+# Codes returned by the C API
+_OK = 0
+_EXITED = 1
+
+
+def _load_library():
+    path = os.environ.get("CHIP8_LIBRARY")
+    if path is None:
+        name = {"win32": "chip8_ffi.dll", "darwin": "libchip8_ffi.dylib"}.get(
+            sys.platform, "libchip8_ffi.so"
+        )
+        path = os.path.join(_ROOT, "target", "release", name)
+    library = ctypes.CDLL(path)
+    machine = ctypes.c_void_p
+    signatures = {
+        "chip8_new": ([], machine),
+        "chip8_free": ([machine], None),
+        "chip8_load_rom": (
+            [machine, ctypes.c_char_p, ctypes.c_size_t],
+            ctypes.c_int,
+        ),
+        "chip8_step": ([machine], ctypes.c_int),
+        "chip8_tick_timers": ([machine], None),
+        "chip8_get_display": (
+            [machine, ctypes.c_char_p, ctypes.c_size_t],
+            ctypes.c_size_t,
+        ),
+        "chip8_set_key": ([machine, ctypes.c_uint8, ctypes.c_bool], None),
+    }
+    for name, (arguments, result) in signatures.items():
+        function = getattr(library, name)
+        function.argtypes = arguments
+        function.restype = result
+    return library
+
+
+_library = _load_library()
+
+
+class Chip8:
+    """A machine, optionally started with a ROM given as bytes or a path"""
+
+    def __init__(self, rom=None, speed=500):
+        self._machine = _library.chip8_new()
+        # Instructions per second, for run_frames
+        self.speed = speed
+        if rom is not None:
+            self.load(rom)
+
+    def __del__(self):
+        machine, self._machine = getattr(self, "_machine", None), None
+        if machine:
+            _library.chip8_free(machine)
+
+    def load(self, rom):
+        """Restarts with `rom`, bytes or the path of a ROM file"""
+        if not isinstance(rom, (bytes, bytearray)):
+            with open(rom, "rb") as file:
+                rom = file.read()
+        if _library.chip8_load_rom(self._machine, bytes(rom), len(rom)) != _OK:
+            raise Chip8Error("not a ROM that fits in memory")
+
+    def step(self, count=1):
+        """Executes `count` instructions"""
+        for _ in range(count):
+            result = _library.chip8_step(self._machine)
+            if result == _EXITED:
+                raise Exited("the program ran into 0000")
+            if result != _OK:
+                raise Chip8Error("the instruction failed, stopping the program")
+
+    def tick_timers(self):
+        """Counts the timers down by a 60th of a second"""
+        _library.chip8_tick_timers(self._machine)
+
+    def run_frames(self, frames=1):
+        """Runs `frames` 60ths of a second at `speed`"""
+        for _ in range(frames):
+            self.step(max(self.speed // 60, 1))
+            self.tick_timers()
+
+    def press(self, key):
+        """Holds down `key`, 0 to 15"""
+        _library.chip8_set_key(self._machine, key, True)
+
+    def release(self, key):
+        _library.chip8_set_key(self._machine, key, False)
+
+    @property
+    def display(self):
+        """A byte for each pixel, 1 when lit, in rows of 64"""
+        buffer = ctypes.create_string_buffer(64 * 64)
+        rows = _library.chip8_get_display(self._machine, buffer, len(buffer))
+        return buffer.raw[: rows * 64]
+
+    @property
+    def height(self):
+        """32, or 64 in hires mode"""
+        return len(self.display) // 64
+
+    def __str__(self):
+        display = self.display
+        return "\n".join(
+            "".join("#" if lit else "." for lit in display[row : row + 64])
+            for row in range(0, len(display), 64)
+        )
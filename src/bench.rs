@@ -0,0 +1,114 @@
+//! Headless benchmark that runs the core flat out, stopping at 60 Hz to tick
+//! the timers and publish the display like a normal frame does, so the
+//! numbers include the per-frame work. A thread reads the published frames
+//! the way `--stream` and `--record` do, and the time the core spent waiting
+//! for it to let go of the frame's lock is reported. With `--blocks` it runs
+//! from a `BlockCache` instead of stepping, to compare the two.
+
+use crate::frame::{Publisher, SharedFrame};
+use chip8::blocks::BlockCache;
+use chip8::{Chip8, Chip8Error};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Instructions between looks at the clock
+const STEPS: usize = 1000;
+
+/// How often the reader copies out the frame, faster than any real one
+const READ_EVERY: Duration = Duration::from_micros(500);
+
+pub fn run(mut chip8: Chip8, seconds: f64, blocks: bool) -> Result<(), Chip8Error> {
+    let frame_time = Duration::from_secs_f64(1.0 / 60.0);
+    let start = Instant::now();
+    let end = start + Duration::from_secs_f64(seconds);
+    let mut next_frame = start + frame_time;
+    let mut draws = 0u64;
+    let mut frames = 0u64;
+    let mut result = Ok(());
+    let mut cache = blocks.then(BlockCache::new);
+    let mut publisher = Publisher::new(&chip8);
+    let stop = Arc::new(AtomicBool::new(false));
+    let reader = {
+        let (frame, stop) = (publisher.shared(), stop.clone());
+        thread::spawn(move || read(&frame, &stop))
+    };
+    loop {
+        // Check the clock only now and then, it costs more than a step
+        let steps = match &mut cache {
+            Some(cache) => cache.run(&mut chip8, STEPS),
+            None => step(&mut chip8, &mut draws),
+        };
+        if let Err(err) = steps {
+            result = Err(err);
+            break;
+        }
+        let now = Instant::now();
+        if now >= end {
+            break;
+        }
+        if now >= next_frame {
+            next_frame += frame_time;
+            chip8.tick_timers();
+            let display_changed = chip8.display_dirty;
+            if display_changed {
+                chip8.display_dirty = false;
+                // Copy it out like the frontend does
+                std::hint::black_box(chip8.screen());
+                frames += 1;
+            }
+            publisher.publish(&chip8, display_changed);
+        }
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    stop.store(true, Ordering::Relaxed);
+    let reads = reader.join().unwrap_or_default();
+
+    println!(
+        "{} instructions in {:.2} s: {:.0} IPS",
+        chip8.instructions,
+        elapsed,
+        chip8.instructions as f64 / elapsed
+    );
+    if cache.is_some() {
+        // Blocks don't stop for each instruction to be counted
+        println!("{} frames picked up by the display", frames);
+    } else {
+        println!(
+            "{} draw calls, {} frames picked up by the display",
+            draws, frames
+        );
+    }
+    println!(
+        "{:.3} ms waiting for the frame lock over {} frames published, {} read",
+        publisher.waited.as_secs_f64() * 1000.0,
+        publisher.published,
+        reads
+    );
+    result
+}
+
+/// Copies out the frame until told to stop, like the threads that send it
+/// on, and returns how many times it did
+fn read(frame: &SharedFrame, stop: &AtomicBool) -> u64 {
+    let mut reads = 0;
+    while !stop.load(Ordering::Relaxed) {
+        std::hint::black_box(frame.lock().unwrap().display.clone());
+        reads += 1;
+        thread::sleep(READ_EVERY);
+    }
+    reads
+}
+
+/// Steps `STEPS` instructions, counting the draw calls among them
+fn step(chip8: &mut Chip8, draws: &mut u64) -> Result<(), Chip8Error> {
+    for _ in 0..STEPS {
+        let op = chip8.fetch()?;
+        if op == 0x00E0 || op & 0xF000 == 0xD000 {
+            *draws += 1;
+        }
+        chip8.execute(op)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,148 @@
+//! A faster way to run a program for the modes that run it flat out: the
+//! straight-line code at an address is decoded once into a block, and run
+//! from the block every time the program gets there again. Writes by the
+//! program into a block drop the cache so the new code is decoded.
+//!
+//! The machine ends up exactly as if it had been stepped, so this is only
+//! about speed.
+
+use crate::instruction::Instruction;
+use crate::{Chip8, Chip8Error};
+use std::ops::Range;
+
+/// Instructions in a block at most, so that a long run of code without
+/// jumps doesn't take up much memory for its tail
+const MAX_BLOCK: usize = 64;
+
+/// Decoded instructions, with their opcodes, at consecutive addresses
+type Block = Vec<(Instruction, u16)>;
+
+pub struct BlockCache {
+    /// Blocks by the address they start at
+    blocks: Vec<Option<Block>>,
+    /// Bytes of memory that are part of a cached block
+    code: Vec<bool>,
+}
+
+impl Default for BlockCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BlockCache {
+    pub fn new() -> Self {
+        Self {
+            blocks: vec![None; 4096],
+            code: vec![false; 4096],
+        }
+    }
+
+    /// Forgets every block, which is needed after memory is changed from
+    /// outside the program, like by a cheat or loading a save state
+    pub fn clear(&mut self) {
+        self.blocks.iter_mut().for_each(|block| *block = None);
+        self.code.iter_mut().for_each(|byte| *byte = false);
+    }
+
+    /// Runs `limit` instructions, a block at a time
+    pub fn run(&mut self, chip8: &mut Chip8, limit: usize) -> Result<(), Chip8Error> {
+        let mut ran = 0;
+        while ran < limit {
+            match self.decode(chip8, chip8.program_counter) {
+                Some(index) => ran += self.run_block(chip8, index, limit - ran)?,
+                None => {
+                    // Code that can't be put in a block, like 0000 or code at
+                    // the very end of memory, is stepped as usual
+                    chip8.step()?;
+                    ran += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs at most `limit` instructions of the block at `index`, up to the
+    /// first that goes elsewhere, returning how many ran
+    fn run_block(
+        &mut self,
+        chip8: &mut Chip8,
+        index: usize,
+        limit: usize,
+    ) -> Result<usize, Chip8Error> {
+        let block = self.blocks[index].as_deref().unwrap_or_default();
+        let mut ran = 0;
+        let mut next = index as u16;
+        let mut overwritten = false;
+        for &(instruction, op) in block.iter().take(limit) {
+            // What fetching the instruction would do
+            next += 2;
+            chip8.program_counter = next;
+            chip8.instructions += 1;
+            chip8.execute_decoded(instruction, op)?;
+            ran += 1;
+            if let Some(written) = writes(instruction, chip8.index) {
+                overwritten = written.into_iter().any(|byte| self.code[byte % 4096]);
+            }
+            if overwritten || chip8.program_counter != next {
+                break;
+            }
+        }
+        if overwritten {
+            self.clear();
+        }
+        Ok(ran)
+    }
+
+    /// Where the block at `address` is in `blocks`, decoding it if it isn't
+    /// cached, or `None` if the code there can't be put in a block
+    fn decode(&mut self, chip8: &Chip8, address: u16) -> Option<usize> {
+        let index = address as usize;
+        if index + 1 >= chip8.memory.len() {
+            return None;
+        }
+        if self.blocks[index].is_none() {
+            let mut block = Block::new();
+            let mut at = index;
+            while block.len() < MAX_BLOCK && at + 1 < chip8.memory.len() {
+                let op = u16::from_be_bytes([chip8.memory[at], chip8.memory[at + 1]]);
+                let instruction = match Instruction::decode(op) {
+                    // Unknown opcodes and 0000 end the program, so leave
+                    // them to the caller's own step
+                    None | Some(Instruction::System(0)) => break,
+                    Some(instruction) => instruction,
+                };
+                block.push((instruction, op));
+                self.code[at] = true;
+                self.code[at + 1] = true;
+                at += 2;
+                if matches!(
+                    instruction,
+                    Instruction::Jump(_)
+                        | Instruction::Call(_)
+                        | Instruction::Return
+                        | Instruction::JumpOffset(_)
+                        | Instruction::Exit
+                ) {
+                    break;
+                }
+            }
+            if block.is_empty() {
+                return None;
+            }
+            self.blocks[index] = Some(block);
+        }
+        Some(index)
+    }
+}
+
+/// The addresses `instruction` just wrote, if it writes memory, given I as it
+/// was left
+fn writes(instruction: Instruction, index: u16) -> Option<Range<usize>> {
+    let index = index as usize;
+    match instruction {
+        Instruction::Bcd(_) => Some(index..index + 3),
+        Instruction::Store(x) => Some(index..index + x + 1),
+        _ => None,
+    }
+}
@@ -0,0 +1,161 @@
+//! ROM picker shown when no ROM is given on the command line, so the
+//! emulator can be used from a couch with a keyboard or game controller
+
+use crate::{overlay, sdl_error};
+use chip8::Chip8Error;
+use sdl2::controller::Button;
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::Sdl;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Size of a font pixel
+const DOT: u32 = 2;
+
+/// ROMs in `dir`, sorted by name
+pub fn list(dir: &Path) -> Result<Vec<PathBuf>, Chip8Error> {
+    let entries = fs::read_dir(dir).map_err(|source| Chip8Error::Io {
+        path: dir.to_path_buf(),
+        source,
+    })?;
+    let mut roms: Vec<PathBuf> = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            path.extension().is_some_and(|ext| {
+                ext.eq_ignore_ascii_case("ch8") || ext.eq_ignore_ascii_case("zip")
+            })
+        })
+        .collect();
+    roms.sort();
+    Ok(roms)
+}
+
+/// Lets the user pick a ROM from `dir`, listed after the `recent` ones.
+/// Returns `None` if they quit instead.
+pub fn choose(sdl: &Sdl, dir: &Path, recent: &[PathBuf]) -> Result<Option<PathBuf>, Chip8Error> {
+    let mut roms = recent.to_vec();
+    roms.extend(
+        list(dir)?
+            .into_iter()
+            .filter(|rom| fs::canonicalize(rom).map_or(true, |rom| !recent.contains(&rom))),
+    );
+    if roms.is_empty() {
+        return Err(Chip8Error::Frontend(format!(
+            "No ROM given and no .ch8 files in {}",
+            dir.display()
+        )));
+    }
+    let names: Vec<String> = roms
+        .iter()
+        .enumerate()
+        .map(|(i, path)| {
+            let name = path.file_stem().unwrap_or_default().to_string_lossy();
+            if i < recent.len() {
+                format!("{} - RECENT", name)
+            } else {
+                name.into_owned()
+            }
+        })
+        .collect();
+
+    let video = sdl.video().map_err(sdl_error)?;
+    let window = video
+        .window("CHIP-8 - choose a ROM", 64 * 10, 32 * 10)
+        .resizable()
+        .build()
+        .map_err(sdl_error)?;
+    let mut canvas = window.into_canvas().build().map_err(sdl_error)?;
+    // Controllers stop sending events once they're dropped
+    let controllers = sdl.game_controller().map_err(sdl_error)?;
+    let _opened: Vec<_> = (0..controllers.num_joysticks().map_err(sdl_error)?)
+        .filter(|&index| controllers.is_game_controller(index))
+        .filter_map(|index| controllers.open(index).ok())
+        .collect();
+    let mut events = sdl.event_pump().map_err(sdl_error)?;
+
+    let mut selected = 0usize;
+    let mut top = 0;
+    loop {
+        let (_, height) = canvas.window().drawable_size();
+        // Help, blank line, then the list
+        let rows = ((height / (6 * DOT)) as usize).saturating_sub(4).max(1);
+        top = top.clamp(selected.saturating_sub(rows - 1), selected);
+
+        let mut lines = vec![
+            "ENTER OR A TO PLAY, ESC OR B TO QUIT".to_string(),
+            "R OR Y TO PLAY THE LAST ROM".to_string(),
+            String::new(),
+        ];
+        for (i, name) in names.iter().enumerate().skip(top).take(rows) {
+            let marker = if i == selected { ">" } else { " " };
+            lines.push(format!("{} {}", marker, name));
+        }
+        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        canvas.clear();
+        overlay::draw_text(&mut canvas, 0, 0, DOT, &lines).map_err(sdl_error)?;
+        canvas.present();
+
+        let last = names.len() - 1;
+        match events.wait_event() {
+            Event::Quit { .. }
+            | Event::KeyDown {
+                keycode: Some(Keycode::Escape),
+                ..
+            }
+            | Event::ControllerButtonDown {
+                button: Button::B | Button::Back,
+                ..
+            } => return Ok(None),
+            Event::KeyDown {
+                keycode: Some(Keycode::Return | Keycode::KpEnter),
+                ..
+            }
+            | Event::ControllerButtonDown {
+                button: Button::A | Button::Start,
+                ..
+            } => return Ok(Some(roms[selected].clone())),
+            Event::KeyDown {
+                keycode: Some(Keycode::R),
+                ..
+            }
+            | Event::ControllerButtonDown {
+                button: Button::Y, ..
+            } if !recent.is_empty() => return Ok(Some(recent[0].clone())),
+            Event::KeyDown {
+                keycode: Some(Keycode::Up),
+                ..
+            }
+            | Event::ControllerButtonDown {
+                button: Button::DPadUp,
+                ..
+            } => selected = selected.saturating_sub(1),
+            Event::KeyDown {
+                keycode: Some(Keycode::Down),
+                ..
+            }
+            | Event::ControllerButtonDown {
+                button: Button::DPadDown,
+                ..
+            } => selected = (selected + 1).min(last),
+            Event::KeyDown {
+                keycode: Some(Keycode::PageUp),
+                ..
+            } => selected = selected.saturating_sub(rows),
+            Event::KeyDown {
+                keycode: Some(Keycode::PageDown),
+                ..
+            } => selected = (selected + rows).min(last),
+            Event::KeyDown {
+                keycode: Some(Keycode::Home),
+                ..
+            } => selected = 0,
+            Event::KeyDown {
+                keycode: Some(Keycode::End),
+                ..
+            } => selected = last,
+            _ => (),
+        }
+    }
+}
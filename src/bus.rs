@@ -0,0 +1,44 @@
+//! What the core reads and writes memory through. A plain 4K array is the
+//! usual memory, and integrators map in peripherals, more RAM or banked
+//! ROM by implementing `Bus` themselves and switching to it with
+//! `Chip8::with_bus`.
+
+use std::ops::Range;
+
+pub trait Bus {
+    /// Number of addresses, past which accesses wrap or stop according to
+    /// the `MemoryPolicy`
+    fn size(&self) -> usize;
+
+    /// The byte at `address`, which is below `size`
+    fn read(&self, address: usize) -> u8;
+
+    /// Stores `value` at `address`, which is below `size`
+    fn write(&mut self, address: usize, value: u8);
+
+    /// The bytes in `range` as a slice, if they can be borrowed as they
+    /// are, which lets DXYN copy a sprite in one go
+    fn slice(&self, range: Range<usize>) -> Option<&[u8]> {
+        let _ = range;
+        None
+    }
+}
+
+/// Flat memory, as on the COSMAC VIP
+impl Bus for [u8; 4096] {
+    fn size(&self) -> usize {
+        self.len()
+    }
+
+    fn read(&self, address: usize) -> u8 {
+        self[address]
+    }
+
+    fn write(&mut self, address: usize, value: u8) {
+        self[address] = value;
+    }
+
+    fn slice(&self, range: Range<usize>) -> Option<&[u8]> {
+        self.get(range)
+    }
+}
@@ -0,0 +1,93 @@
+//! Cheats: bytes of memory forced to a value every frame, such as a lives
+//! counter, optionally only while another byte has a given value.
+//!
+//! A cheat file has one cheat per line, a name followed by its pokes and an
+//! optional condition, with addresses and values in hex. `#` starts a
+//! comment.
+//!
+//! ```text
+//! lives: 2F0=03
+//! shield: 2F4=FF 2F5=FF if 2F1=02
+//! ```
+
+use chip8::Chip8;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub struct Cheat {
+    pub name: String,
+    pokes: Vec<(u16, u8)>,
+    condition: Option<(u16, u8)>,
+    /// Cheats can be switched on and off while the game runs
+    pub enabled: AtomicBool,
+}
+
+impl Cheat {
+    /// Call once per frame
+    pub fn apply(&self, chip8: &mut Chip8) {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Some((address, value)) = self.condition {
+            if chip8.peek(address) != value {
+                return;
+            }
+        }
+        for &(address, value) in &self.pokes {
+            chip8.poke(address, value);
+        }
+    }
+}
+
+pub fn load(path: &Path) -> Result<Vec<Cheat>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| format!("Cannot read cheats {}: {}", path.display(), err))?;
+    let mut cheats = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let cheat = parse(line)
+            .map_err(|err| format!("{} line {}: {}", path.display(), number + 1, err))?;
+        cheats.push(cheat);
+    }
+    Ok(cheats)
+}
+
+fn parse(line: &str) -> Result<Cheat, String> {
+    let (name, rest) = line
+        .split_once(':')
+        .ok_or("expected a name, then `:` and the pokes")?;
+    let (pokes, condition) = match rest.split_once(" if ") {
+        Some((pokes, condition)) => (pokes, Some(assignment(condition.trim())?)),
+        None => (rest, None),
+    };
+    let pokes = pokes
+        .split_whitespace()
+        .map(assignment)
+        .collect::<Result<Vec<_>, _>>()?;
+    if pokes.is_empty() {
+        return Err("the cheat has no pokes".into());
+    }
+    Ok(Cheat {
+        name: name.trim().to_string(),
+        pokes,
+        condition,
+        enabled: AtomicBool::new(true),
+    })
+}
+
+/// Parses `ADDR=VV`
+fn assignment(text: &str) -> Result<(u16, u8), String> {
+    let (address, value) = text
+        .split_once('=')
+        .ok_or_else(|| format!("expected ADDRESS=VALUE, found `{}`", text))?;
+    let address = u16::from_str_radix(address, 16)
+        .ok()
+        .filter(|&address| address < 0x1000)
+        .ok_or_else(|| format!("invalid address `{}`", address))?;
+    let value = u8::from_str_radix(value, 16).map_err(|_| format!("invalid value `{}`", value))?;
+    Ok((address, value))
+}
@@ -0,0 +1,365 @@
+//! Settings read from TOML files. Top-level keys in the config file apply to
+//! every ROM, what the CHIP-8 database knows about the ROM overrides them,
+//! `[rom."pong.ch8"]` sections override that for one ROM, and a sidecar file
+//! next to the ROM (`pong.toml` for `pong.ch8`) overrides everything:
+//!
+//! ```toml
+//! speed = 700
+//!
+//! [rom."pong.ch8"]
+//! foreground = "#33FF66"
+//! ```
+//!
+//! Only the small part of TOML these files need is understood: tables,
+//! strings, integers and booleans.
+
+use crate::database;
+use chip8::{MemoryPolicy, Quirks, RandomSource};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Palette {
+    pub background: [u8; 3],
+    pub foreground: [u8; 3],
+}
+
+impl Palette {
+    /// Colour of a pixel lit at `level`, from 0 (off) to 1 (fully on)
+    pub fn shade(&self, level: f32) -> [u8; 3] {
+        let mut rgb = [0; 3];
+        for (i, channel) in rgb.iter_mut().enumerate() {
+            let (off, on) = (self.background[i] as f32, self.foreground[i] as f32);
+            *channel = (off + (on - off) * level).round() as u8;
+        }
+        rgb
+    }
+}
+
+/// Palettes that can be chosen by name with `--palette` or `palette = ...`.
+/// Other than the classic one, they keep the two colours far apart in
+/// brightness as well as hue, so they can be told apart with any of the
+/// common colour vision deficiencies, and on dim or washed-out screens.
+pub const PALETTES: [(&str, Palette); 5] = [
+    ("classic", Palette::new(0x000000, 0xFFFFFF)),
+    ("inverted", Palette::new(0xFFFFFF, 0x000000)),
+    ("high-contrast", Palette::new(0x000000, 0xFFFF00)),
+    // Okabe and Ito's yellow on navy, along the blue-yellow axis that
+    // red-green colour blindness leaves intact
+    ("blue-yellow", Palette::new(0x002855, 0xF0E442)),
+    ("amber", Palette::new(0x000000, 0xFFB000)),
+];
+
+impl Palette {
+    const fn new(background: u32, foreground: u32) -> Self {
+        Self {
+            background: rgb(background),
+            foreground: rgb(foreground),
+        }
+    }
+
+    /// The palette called `name` in `PALETTES`
+    pub fn named(name: &str) -> Result<Self, String> {
+        PALETTES
+            .iter()
+            .find(|(palette, _)| *palette == name)
+            .map(|&(_, palette)| palette)
+            .ok_or_else(|| {
+                let names: Vec<&str> = PALETTES.iter().map(|(name, _)| *name).collect();
+                format!(
+                    "unknown palette `{}`, expected one of {}",
+                    name,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// Settings after all files have been applied
+pub struct Settings {
+    /// Instructions per second
+    pub speed: u32,
+    pub palette: Palette,
+    /// What to do about reads and writes past the end of memory
+    pub memory_policy: MemoryPolicy,
+    pub quirks: Quirks,
+    /// Save the machine on quit and pick up from there next time
+    pub resume: bool,
+}
+
+#[derive(Clone, Default)]
+struct Overrides {
+    speed: Option<u32>,
+    background: Option<[u8; 3]>,
+    foreground: Option<[u8; 3]>,
+    memory: Option<MemoryPolicy>,
+    wait_for_release: Option<bool>,
+    display_wait: Option<bool>,
+    random: Option<RandomSource>,
+    resume: Option<bool>,
+}
+
+impl Overrides {
+    fn apply(&mut self, other: &Overrides) {
+        self.speed = other.speed.or(self.speed);
+        self.background = other.background.or(self.background);
+        self.foreground = other.foreground.or(self.foreground);
+        self.memory = other.memory.or(self.memory);
+        self.wait_for_release = other.wait_for_release.or(self.wait_for_release);
+        self.display_wait = other.display_wait.or(self.display_wait);
+        self.random = other.random.or(self.random);
+        self.resume = other.resume.or(self.resume);
+    }
+
+    fn set(&mut self, key: &str, value: Value) -> Result<(), String> {
+        match key {
+            "speed" => match value {
+                Value::Integer(speed) if speed > 0 && speed <= u32::MAX as i64 => {
+                    self.speed = Some(speed as u32)
+                }
+                _ => return Err("speed must be a positive number of instructions".into()),
+            },
+            "background" => self.background = Some(parse_color(value)?),
+            "foreground" => self.foreground = Some(parse_color(value)?),
+            "palette" => {
+                let Value::String(name) = value else {
+                    return Err("palette must be the name of a palette".into());
+                };
+                let palette = Palette::named(&name)?;
+                self.background = Some(palette.background);
+                self.foreground = Some(palette.foreground);
+            }
+            "memory" => {
+                let policy = match value {
+                    Value::String(policy) => policy.parse().ok(),
+                    _ => None,
+                };
+                self.memory = Some(policy.ok_or("memory must be \"wrap\" or \"trap\"")?);
+            }
+            "wait_for_release" => self.wait_for_release = Some(parse_bool(key, value)?),
+            "display_wait" => self.display_wait = Some(parse_bool(key, value)?),
+            "resume" => self.resume = Some(parse_bool(key, value)?),
+            "random" => {
+                let source = match value {
+                    Value::String(source) => source.parse().ok(),
+                    _ => None,
+                };
+                self.random = Some(source.ok_or("random must be \"modern\" or \"lfsr\"")?);
+            }
+            _ => return Err(format!("unknown setting `{}`", key)),
+        }
+        Ok(())
+    }
+}
+
+/// Reads the config file (`path`, or the default location if it exists) and
+/// the ROM's sidecar file, and resolves the settings for `rom_path` given
+/// its `database` entry
+pub fn load(
+    path: Option<&Path>,
+    rom_path: &str,
+    database: Option<&database::Entry>,
+) -> Result<Settings, String> {
+    let mut overrides = Overrides::default();
+
+    let path = match path {
+        Some(path) => Some(path.to_path_buf()),
+        None => config_dir()
+            .map(|dir| dir.join("config.toml"))
+            .filter(|path| path.exists()),
+    };
+    let mut rom_section = None;
+    if let Some(path) = path {
+        let (global, mut roms) = read(&path)?;
+        overrides.apply(&global);
+        let rom_name = Path::new(rom_path).file_name().unwrap_or_default();
+        rom_section = roms.remove(&*rom_name.to_string_lossy());
+    }
+    if let Some(entry) = database {
+        overrides.apply(&Overrides {
+            speed: entry.speed,
+            display_wait: entry.display_wait,
+            ..Overrides::default()
+        });
+    }
+    if let Some(rom) = rom_section {
+        overrides.apply(&rom);
+    }
+
+    let sidecar = Path::new(rom_path).with_extension("toml");
+    if sidecar.exists() {
+        let (sidecar_overrides, _) = read(&sidecar)?;
+        overrides.apply(&sidecar_overrides);
+    }
+
+    let quirks = Quirks::default();
+    Ok(Settings {
+        speed: overrides.speed.unwrap_or(500),
+        palette: Palette {
+            background: overrides.background.unwrap_or([0, 0, 0]),
+            foreground: overrides.foreground.unwrap_or([255, 255, 255]),
+        },
+        memory_policy: overrides.memory.unwrap_or(MemoryPolicy::Trap),
+        quirks: Quirks {
+            wait_for_release: overrides
+                .wait_for_release
+                .unwrap_or(quirks.wait_for_release),
+            display_wait: overrides.display_wait.unwrap_or(quirks.display_wait),
+            random: overrides.random.unwrap_or(quirks.random),
+        },
+        resume: overrides.resume.unwrap_or(false),
+    })
+}
+
+/// Per-user configuration directory, e.g. `~/.config/chip8`
+pub fn config_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))?;
+    Some(base.join("chip8"))
+}
+
+type Files = (Overrides, HashMap<String, Overrides>);
+
+fn read(path: &Path) -> Result<Files, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|err: io::Error| format!("Cannot read config file {}: {}", path.display(), err))?;
+    parse(&text).map_err(|(line, err)| format!("{}:{}: {}", path.display(), line, err))
+}
+
+enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+}
+
+fn parse(text: &str) -> Result<Files, (usize, String)> {
+    let mut global = Overrides::default();
+    let mut roms: HashMap<String, Overrides> = HashMap::new();
+    let mut section: Option<String> = None;
+    for (number, line) in text.lines().enumerate() {
+        let number = number + 1;
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[') {
+            let header = header
+                .strip_suffix(']')
+                .ok_or((number, "unterminated table header".to_string()))?
+                .trim();
+            let rom = header
+                .strip_prefix("rom.")
+                .ok_or((number, format!("unknown table `{}`", header)))?;
+            section = Some(parse_key(rom).map_err(|err| (number, err))?);
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or((number, "expected `key = value`".to_string()))?;
+        let key = parse_key(key.trim()).map_err(|err| (number, err))?;
+        let value = parse_value(value.trim()).map_err(|err| (number, err))?;
+        let overrides = match &section {
+            Some(rom) => roms.entry(rom.clone()).or_default(),
+            None => &mut global,
+        };
+        overrides.set(&key, value).map_err(|err| (number, err))?;
+    }
+    Ok((global, roms))
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => (),
+        }
+    }
+    line
+}
+
+fn parse_key(key: &str) -> Result<String, String> {
+    if key.starts_with('"') {
+        match parse_value(key)? {
+            Value::String(key) => Ok(key),
+            _ => unreachable!(),
+        }
+    } else if !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        Ok(key.to_string())
+    } else {
+        Err(format!("invalid key `{}`", key))
+    }
+}
+
+fn parse_value(value: &str) -> Result<Value, String> {
+    if let Some(rest) = value.strip_prefix('"') {
+        let mut string = String::new();
+        let mut chars = rest.chars();
+        loop {
+            match chars.next() {
+                Some('"') => break,
+                Some('\\') => match chars.next() {
+                    Some('"') => string.push('"'),
+                    Some('\\') => string.push('\\'),
+                    Some('n') => string.push('\n'),
+                    Some('t') => string.push('\t'),
+                    _ => return Err("unsupported escape sequence".into()),
+                },
+                Some(c) => string.push(c),
+                None => return Err("unterminated string".into()),
+            }
+        }
+        if !chars.as_str().trim().is_empty() {
+            return Err("unexpected text after string".into());
+        }
+        return Ok(Value::String(string));
+    }
+    match value {
+        "true" => return Ok(Value::Boolean(true)),
+        "false" => return Ok(Value::Boolean(false)),
+        _ => (),
+    }
+    let digits = value.replace('_', "");
+    let number = match digits.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16),
+        None => digits.parse(),
+    };
+    number
+        .map(Value::Integer)
+        .map_err(|_| format!("invalid value `{}`", value))
+}
+
+fn parse_bool(key: &str, value: Value) -> Result<bool, String> {
+    match value {
+        Value::Boolean(value) => Ok(value),
+        _ => Err(format!("{} must be true or false", key)),
+    }
+}
+
+fn parse_color(value: Value) -> Result<[u8; 3], String> {
+    let Value::String(color) = value else {
+        return Err("colours are written as \"#RRGGBB\"".into());
+    };
+    let hex = color
+        .strip_prefix('#')
+        .filter(|hex| hex.len() == 6)
+        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+        .ok_or_else(|| format!("invalid colour `{}`, expected \"#RRGGBB\"", color))?;
+    Ok(rgb(hex))
+}
+
+const fn rgb(hex: u32) -> [u8; 3] {
+    [(hex >> 16) as u8, (hex >> 8) as u8, hex as u8]
+}
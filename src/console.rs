@@ -0,0 +1,436 @@
+//! `--console`, which makes the emulator a small console for a TV or a
+//! cabinet: a boot screen, then the ROMs in `--rom-dir` as a grid with a
+//! picture of each, and demos of them when nobody has touched anything for
+//! a while. Games start as they would from the command line, with their
+//! per-ROM config, and pick up where they were left, which is what their
+//! pictures show. Nothing in the console quits it or touches files, so it
+//! runs until its window is closed.
+
+use crate::config::{self, Palette};
+use crate::pacing::Scheduler;
+use crate::{browser, headless, log, next_event, overlay, savestate, screenshot, sdl_error};
+use crate::{build_machine, play, read_rom, session_speed, symbols, Args, Ending};
+use chip8::{Chip8, Chip8Error};
+use sdl2::controller::Button;
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use sdl2::EventPump;
+use std::time::{Duration, Instant};
+
+/// Games across the grid
+const COLUMNS: usize = 4;
+/// A grid cell's width and height, in pixels of the pictures in it: the
+/// picture, its name and whether it resumes, and a gap
+const CELL: (u32, u32) = (72, 46);
+/// How long the boot screen stays up
+const SPLASH: Duration = Duration::from_millis(2500);
+/// How long the grid waits for input before showing demos
+const IDLE: Duration = Duration::from_secs(30);
+/// How long each demo runs
+const DEMO: Duration = Duration::from_secs(20);
+/// Instructions run to get a picture of a game that hasn't been played, a
+/// few seconds in at the usual speeds
+const PICTURE_AFTER: u64 = 300_000;
+
+/// A game in the library
+struct Game {
+    path: String,
+    name: String,
+    /// The display shown for it, in rows of 64
+    picture: Vec<bool>,
+    palette: Palette,
+    /// Whether playing it picks up from where it was left
+    resumes: bool,
+}
+
+impl Game {
+    fn open(args: &Args, path: String) -> Result<Game, Chip8Error> {
+        let rom = read_rom(&path)?;
+        let settings =
+            config::load(args.config.as_deref(), &path, None).map_err(Chip8Error::Config)?;
+        let resumed = savestate::resume_path(&rom)
+            .filter(|state| state.exists())
+            .and_then(|state| savestate::read(&state, &rom).ok());
+        let resumes = resumed.is_some();
+        let chip8 = match resumed {
+            Some(chip8) => chip8,
+            None => {
+                let speed = session_speed(args, &settings);
+                let mut chip8 = build_machine(args, &rom, &settings, speed, None, Some(0))?;
+                // A program that stops early still leaves something to show
+                let _ = headless::run(&mut chip8, PICTURE_AFTER, speed, |_| ());
+                chip8
+            }
+        };
+        Ok(Game {
+            name: screenshot::rom_name(&path).to_uppercase(),
+            path,
+            picture: chip8.screen(),
+            palette: args.palette.unwrap_or(settings.palette),
+            resumes,
+        })
+    }
+}
+
+/// What the grid was left for
+enum Choice {
+    Play(usize),
+    Demo,
+    Closed,
+}
+
+pub fn run(args: &Args) -> Result<(), Chip8Error> {
+    let mut games: Vec<Game> = browser::list(&args.rom_dir)?
+        .into_iter()
+        .filter_map(|path| {
+            let path = path.to_string_lossy().into_owned();
+            Game::open(args, path.clone())
+                .map_err(|err| log::warn!("Leaving out {}: {}", path, err))
+                .ok()
+        })
+        .collect();
+    if games.is_empty() {
+        return Err(Chip8Error::Frontend(format!(
+            "No ROMs to play in {}",
+            args.rom_dir.display()
+        )));
+    }
+
+    let sdl = sdl2::init().map_err(sdl_error)?;
+    let video = sdl.video().map_err(sdl_error)?;
+    let window = video
+        .window("CHIP-8", 64 * 10, 32 * 10)
+        .fullscreen_desktop()
+        .build()
+        .map_err(sdl_error)?;
+    sdl.mouse().show_cursor(false);
+    let mut canvas = window.into_canvas().build().map_err(sdl_error)?;
+    // Controllers stop sending events once they're dropped
+    let controllers = sdl.game_controller().map_err(sdl_error)?;
+    let _opened: Vec<_> = (0..controllers.num_joysticks().map_err(sdl_error)?)
+        .filter(|&index| controllers.is_game_controller(index))
+        .filter_map(|index| controllers.open(index).ok())
+        .collect();
+
+    if !splash(
+        &mut canvas,
+        &mut sdl.event_pump().map_err(sdl_error)?,
+        games.len(),
+    )? {
+        return Ok(());
+    }
+    let mut selected = 0;
+    loop {
+        // Games take their own event pump, and there can only be one
+        let choice = {
+            let mut events = sdl.event_pump().map_err(sdl_error)?;
+            match grid(&mut canvas, &mut events, &games, &mut selected)? {
+                Choice::Demo => match demo(args, &mut canvas, &mut events, &games, selected)? {
+                    Some(shown) => {
+                        selected = shown;
+                        continue;
+                    }
+                    None => Choice::Closed,
+                },
+                choice => choice,
+            }
+        };
+        let Choice::Play(index) = choice else {
+            return Ok(());
+        };
+        let path = games[index].path.clone();
+        match play(args, path.clone(), Some(&sdl), symbols::Symbols::default()) {
+            Ok(Ending::Closed) => return Ok(()),
+            Ok(_) => (),
+            Err(err) => log::warn!("{}", err),
+        }
+        // Its picture is now where it will resume
+        match Game::open(args, path) {
+            Ok(game) => games[index] = game,
+            Err(err) => log::warn!("{}", err),
+        }
+        canvas.window_mut().raise();
+    }
+}
+
+/// Shows the boot screen, spelling out CHIP-8 a letter at a time, until it
+/// is over or skipped. Returns false if the window was closed.
+fn splash(
+    canvas: &mut Canvas<Window>,
+    events: &mut EventPump,
+    games: usize,
+) -> Result<bool, Chip8Error> {
+    const TITLE: &str = "CHIP-8";
+    let start = Instant::now();
+    let mut scheduler = Scheduler::new(60.0, start);
+    loop {
+        while let Some(event) = next_event(events, scheduler.deadline()) {
+            match event {
+                Event::Quit { .. } => return Ok(false),
+                Event::KeyDown { .. } | Event::ControllerButtonDown { .. } => return Ok(true),
+                _ => (),
+            }
+        }
+        scheduler.due(Instant::now());
+        let elapsed = start.elapsed();
+        if elapsed >= SPLASH {
+            return Ok(true);
+        }
+
+        let (width, height) = canvas.output_size().map_err(sdl_error)?;
+        // The title across half the screen, and the rest a quarter its size
+        let dot = (width / 2 / (TITLE.len() as u32 * 4 + 1)).max(1);
+        let small = (dot / 4).max(1);
+        let letters = (elapsed.as_millis() / 150) as usize;
+        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        canvas.clear();
+        let x = width.saturating_sub((TITLE.len() as u32 * 4 + 1) * dot) / 2;
+        let y = (height / 2).saturating_sub(6 * dot);
+        overlay::draw_text(
+            canvas,
+            x as i32,
+            y as i32,
+            dot,
+            &[TITLE[..letters.min(TITLE.len())].to_string()],
+        )
+        .map_err(sdl_error)?;
+        if letters > TITLE.len() {
+            let line = format!("{} GAMES", games);
+            let x = width.saturating_sub((line.len() as u32 * 4 + 1) * small) / 2;
+            overlay::draw_text(canvas, x as i32, (y + 7 * dot) as i32, small, &[line])
+                .map_err(sdl_error)?;
+        }
+        canvas.present();
+    }
+}
+
+/// Lets the player move through the games until they pick one, or until
+/// nothing has happened for `IDLE`
+fn grid(
+    canvas: &mut Canvas<Window>,
+    events: &mut EventPump,
+    games: &[Game],
+    selected: &mut usize,
+) -> Result<Choice, Chip8Error> {
+    let last = games.len() - 1;
+    let mut idle_since = Instant::now();
+    loop {
+        draw_grid(canvas, games, *selected).map_err(sdl_error)?;
+        let Some(event) = next_event(events, idle_since + IDLE) else {
+            return Ok(Choice::Demo);
+        };
+        match event {
+            Event::Quit { .. } => return Ok(Choice::Closed),
+            Event::KeyDown {
+                keycode: Some(Keycode::Return | Keycode::KpEnter),
+                ..
+            }
+            | Event::ControllerButtonDown {
+                button: Button::A | Button::Start,
+                ..
+            } => return Ok(Choice::Play(*selected)),
+            Event::KeyDown {
+                keycode: Some(Keycode::Left),
+                ..
+            }
+            | Event::ControllerButtonDown {
+                button: Button::DPadLeft,
+                ..
+            } => *selected = selected.saturating_sub(1),
+            Event::KeyDown {
+                keycode: Some(Keycode::Right),
+                ..
+            }
+            | Event::ControllerButtonDown {
+                button: Button::DPadRight,
+                ..
+            } => *selected = (*selected + 1).min(last),
+            Event::KeyDown {
+                keycode: Some(Keycode::Up),
+                ..
+            }
+            | Event::ControllerButtonDown {
+                button: Button::DPadUp,
+                ..
+            } => *selected = selected.saturating_sub(COLUMNS),
+            Event::KeyDown {
+                keycode: Some(Keycode::Down),
+                ..
+            }
+            | Event::ControllerButtonDown {
+                button: Button::DPadDown,
+                ..
+            } => *selected = (*selected + COLUMNS).min(last),
+            // Redrawn on the way round, without putting off the demos
+            _ => continue,
+        }
+        idle_since = Instant::now();
+    }
+}
+
+fn draw_grid(canvas: &mut Canvas<Window>, games: &[Game], selected: usize) -> Result<(), String> {
+    let (width, height) = canvas.output_size()?;
+    let scale = (width / (COLUMNS as u32 * CELL.0)).max(1);
+    let dot = (scale / 2).max(1);
+    let help = vec![
+        format!("{} GAMES", games.len()),
+        "ARROWS OR D-PAD TO CHOOSE, ENTER OR A TO PLAY, ESC TO COME BACK".to_string(),
+    ];
+    let top = (help.len() as u32 * 6 + 3) * dot;
+    let rows = (height.saturating_sub(top) / (CELL.1 * scale)).max(1) as usize;
+    // Scrolled just far enough to show the selected game
+    let first_row = (selected / COLUMNS).saturating_sub(rows - 1);
+
+    canvas.set_draw_color(Color::RGB(0, 0, 0));
+    canvas.clear();
+    overlay::draw_text(canvas, 0, 0, dot, &help)?;
+    let columns = (64 * scale / (4 * dot)) as usize;
+    for (i, game) in games
+        .iter()
+        .enumerate()
+        .skip(first_row * COLUMNS)
+        .take(rows * COLUMNS)
+    {
+        let x = ((i % COLUMNS) as u32 * CELL.0 + 4) * scale;
+        let y = top + (i / COLUMNS - first_row) as u32 * CELL.1 * scale;
+        if i == selected {
+            canvas.set_draw_color(Color::RGB(255, 255, 0));
+            canvas.fill_rect(Rect::new(
+                (x - scale) as i32,
+                (y - scale) as i32,
+                66 * scale,
+                34 * scale,
+            ))?;
+        }
+        draw_screen(
+            canvas,
+            x as i32,
+            y as i32,
+            64 * scale,
+            &game.picture,
+            &game.palette,
+        )?;
+        let mut label = vec![game.name.chars().take(columns).collect()];
+        if game.resumes {
+            label.push("RESUME".to_string());
+        }
+        overlay::draw_text(canvas, x as i32, (y + 33 * scale) as i32, dot, &label)?;
+    }
+    canvas.present();
+    Ok(())
+}
+
+/// Runs each game in turn from `selected` for `DEMO`, with nobody
+/// playing, until a key or button is pressed. Returns the game showing
+/// then, or `None` if the window was closed.
+fn demo(
+    args: &Args,
+    canvas: &mut Canvas<Window>,
+    events: &mut EventPump,
+    games: &[Game],
+    selected: usize,
+) -> Result<Option<usize>, Chip8Error> {
+    let mut shown = selected;
+    loop {
+        let game = &games[shown];
+        let started = Instant::now();
+        let mut scheduler = Scheduler::new(60.0, started);
+        // Demos start from the beginning and leave the high scores alone
+        let mut machine = demo_machine(args, &game.path)
+            .map_err(|err| log::warn!("Cannot show a demo of {}: {}", game.path, err))
+            .ok();
+        while started.elapsed() < DEMO {
+            while let Some(event) = next_event(events, scheduler.deadline()) {
+                match event {
+                    Event::Quit { .. } => return Ok(None),
+                    Event::KeyDown { .. } | Event::ControllerButtonDown { .. } => {
+                        return Ok(Some(shown))
+                    }
+                    _ => (),
+                }
+            }
+            let frames = scheduler.due(Instant::now());
+            let Some(chip8) = &mut machine else { break };
+            let per_frame = (chip8.speed as u64 / 60).max(1);
+            for _ in 0..frames {
+                if headless::run(chip8, per_frame, chip8.speed, |_| ()).is_err() {
+                    machine = None;
+                    break;
+                }
+            }
+            let Some(chip8) = &machine else { break };
+
+            let (width, height) = canvas.output_size().map_err(sdl_error)?;
+            let screen_width = width.min(height * 2);
+            let dot = (screen_width / 256).max(1);
+            canvas.set_draw_color(Color::RGB(0, 0, 0));
+            canvas.clear();
+            draw_screen(
+                canvas,
+                ((width - screen_width) / 2) as i32,
+                ((height - screen_width / 2) / 2) as i32,
+                screen_width,
+                &chip8.screen(),
+                &game.palette,
+            )
+            .map_err(sdl_error)?;
+            let line = format!("{} - PRESS ANY KEY", game.name);
+            let y = height.saturating_sub(7 * dot) as i32;
+            overlay::draw_text(canvas, 0, y, dot, &[line]).map_err(sdl_error)?;
+            canvas.present();
+        }
+        shown = (shown + 1) % games.len();
+    }
+}
+
+fn demo_machine(args: &Args, path: &str) -> Result<Chip8, Chip8Error> {
+    let rom = read_rom(path)?;
+    let settings = config::load(args.config.as_deref(), path, None).map_err(Chip8Error::Config)?;
+    build_machine(
+        args,
+        &rom,
+        &settings,
+        session_speed(args, &settings),
+        None,
+        None,
+    )
+}
+
+/// Draws `screen`, in rows of 64, `width` pixels across and half as high
+/// with its top-left corner at (`x`, `y`), squeezing hires displays to fit
+fn draw_screen(
+    canvas: &mut Canvas<Window>,
+    x: i32,
+    y: i32,
+    width: u32,
+    screen: &[bool],
+    palette: &Palette,
+) -> Result<(), String> {
+    let rows = (screen.len() / 64).max(1) as u32;
+    let [r, g, b] = palette.background;
+    canvas.set_draw_color(Color::RGB(r, g, b));
+    canvas.fill_rect(Rect::new(x, y, width, width / 2))?;
+    let lit: Vec<Rect> = screen
+        .iter()
+        .enumerate()
+        .filter(|(_, &on)| on)
+        .map(|(i, _)| {
+            let (column, row) = ((i % 64) as u32, (i / 64) as u32);
+            let (left, right) = (column * width / 64, (column + 1) * width / 64);
+            let (top, bottom) = (row * width / 2 / rows, (row + 1) * width / 2 / rows);
+            Rect::new(
+                x + left as i32,
+                y + top as i32,
+                (right - left).max(1),
+                (bottom - top).max(1),
+            )
+        })
+        .collect();
+    let [r, g, b] = palette.foreground;
+    canvas.set_draw_color(Color::RGB(r, g, b));
+    canvas.fill_rects(&lit)
+}
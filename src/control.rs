@@ -0,0 +1,400 @@
+//! Remote control over TCP, for driving the emulator from integration tests
+//! or a stream overlay. Clients send one JSON command per line and get one
+//! JSON reply per line, with `"ok": false` and an `error` when a command
+//! fails:
+//!
+//! ```text
+//! {"command": "pause"}                         also "resume"
+//! {"command": "press", "key": 5}               also "release"
+//! {"command": "state"}                         registers, timers, display
+//! {"command": "screenshot"}                    saves a PNG, replies its path
+//! {"command": "reset"}                         restarts the ROM
+//! {"command": "load", "path": "game.ch8"}      switches to another ROM
+//! {"command": "save", "path": "game.c8s"}      saves a save state
+//! {"command": "restore", "path": "game.c8s"}   restores one
+//! ```
+//!
+//! In netplay or with an input movie only `state`, `screenshot` and `save`
+//! are taken, since anything else would change one machine and not the
+//! other, or not the replay.
+
+use crate::config::Settings;
+use crate::json::{self, Json};
+use crate::log;
+use crate::savestate;
+use crate::screenshot;
+use chip8::Chip8;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+/// What commands need from the running session, besides the machine
+pub struct Session {
+    pub settings: Settings,
+    /// The ROM running, which save states are checked against
+    pub rom: Vec<u8>,
+    pub rom_path: String,
+    pub screenshot_dir: PathBuf,
+}
+
+/// A command waiting for the main loop, which owns the machine, to run it
+/// with `Session::handle`
+pub struct Request {
+    command: Json,
+    reply: Sender<Json>,
+}
+
+pub fn spawn(port: u16) -> std::io::Result<Receiver<Request>> {
+    // Only local clients, since anyone connected can load files
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    let (requests, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { continue };
+            let requests = requests.clone();
+            thread::spawn(move || {
+                if let Err(err) = serve(stream, &requests) {
+                    log::warn!("Control connection closed: {}", err);
+                }
+            });
+        }
+    });
+    Ok(receiver)
+}
+
+fn serve(stream: TcpStream, requests: &Sender<Request>) -> std::io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match json::parse(&line) {
+            Ok(command) => {
+                let (reply, receiver) = mpsc::channel();
+                if requests.send(Request { command, reply }).is_err() {
+                    return Ok(());
+                }
+                match receiver.recv() {
+                    Ok(reply) => reply,
+                    Err(_) => return Ok(()),
+                }
+            }
+            Err(err) => reply(Err(err)),
+        };
+        writeln!(writer, "{}", reply)?;
+    }
+    Ok(())
+}
+
+type Members = Vec<(String, Json)>;
+
+fn reply(result: Result<Members, String>) -> Json {
+    match result {
+        Ok(mut members) => {
+            members.insert(0, ("ok".into(), Json::Bool(true)));
+            Json::Object(members)
+        }
+        Err(err) => Json::Object(vec![
+            ("ok".into(), Json::Bool(false)),
+            ("error".into(), Json::String(err)),
+        ]),
+    }
+}
+
+/// A `load` command, left to the main loop since everything kept per ROM
+/// changes with it: the settings, the high scores and where to save on quit
+pub struct Load {
+    pub path: String,
+    reply: Sender<Json>,
+}
+
+impl Load {
+    /// Answers the client with how loading went
+    pub fn finish(self, result: Result<(), String>) {
+        let _ = self.reply.send(reply(result.map(|()| Vec::new())));
+    }
+}
+
+/// What the main loop has left to do after a command
+pub enum Handled {
+    Answered,
+    /// The machine was reset or restored, so it starts over as far as
+    /// everything counting its instructions is concerned
+    Restarted,
+    Load(Load),
+}
+
+/// Commands that change the machine, which netplay or an input movie can't
+/// allow since only the players' keys may do that
+const LOCKED: [&str; 7] = [
+    "pause", "resume", "press", "release", "reset", "restore", "load",
+];
+
+/// Runs a command against the machine and answers the client, or hands a
+/// `load` back to be carried out and answered with `Load::finish`.
+/// `locked` refuses the commands that change the machine.
+pub fn handle(
+    request: Request,
+    session: &mut Session,
+    chip8: &mut Chip8,
+    paused: &mut bool,
+    locked: bool,
+) -> Handled {
+    let name = request.command.get("command").and_then(Json::as_str);
+    if locked && name.is_some_and(|name| LOCKED.contains(&name)) {
+        let err = format!(
+            "\"{}\" can't be used in netplay or with input movies",
+            name.unwrap_or_default()
+        );
+        let _ = request.reply.send(reply(Err(err)));
+        return Handled::Answered;
+    }
+    if name == Some("load") {
+        match request.command.get("path").and_then(Json::as_str) {
+            Some(path) => {
+                return Handled::Load(Load {
+                    path: path.into(),
+                    reply: request.reply,
+                })
+            }
+            None => {
+                let _ = request.reply.send(reply(Err("expected a \"path\"".into())));
+                return Handled::Answered;
+            }
+        }
+    }
+    let result = run(&request.command, session, chip8, paused);
+    let restarted = result.is_ok() && matches!(name, Some("reset" | "restore"));
+    let _ = request.reply.send(reply(result));
+    match restarted {
+        true => Handled::Restarted,
+        false => Handled::Answered,
+    }
+}
+
+fn run(
+    command: &Json,
+    session: &mut Session,
+    chip8: &mut Chip8,
+    paused: &mut bool,
+) -> Result<Members, String> {
+    let name = command
+        .get("command")
+        .and_then(Json::as_str)
+        .ok_or("expected an object with a \"command\"")?;
+    match name {
+        "pause" | "resume" => {
+            *paused = name == "pause";
+            Ok(Vec::new())
+        }
+        "press" | "release" => {
+            let key = command
+                .get("key")
+                .and_then(Json::as_f64)
+                .filter(|key| (0.0..16.0).contains(key))
+                .ok_or("expected a \"key\" from 0 to 15")?;
+            chip8.keys[key as usize] = name == "press";
+            Ok(Vec::new())
+        }
+        "state" => Ok(state(chip8, *paused)),
+        "screenshot" => {
+            let path = screenshot::save(
+                &session.screenshot_dir,
+                &session.rom_path,
+                &chip8.screen(),
+                &session.settings.palette,
+            )
+            .map_err(|err| format!("cannot save screenshot: {}", err))?;
+            Ok(vec![(
+                "path".into(),
+                Json::String(path.to_string_lossy().into_owned()),
+            )])
+        }
+        "reset" => {
+            chip8.reset();
+            Ok(Vec::new())
+        }
+        "save" => {
+            let path = command
+                .get("path")
+                .and_then(Json::as_str)
+                .ok_or("expected a \"path\"")?;
+            savestate::write(Path::new(path), chip8, &session.rom)
+                .map_err(|err| format!("cannot save state: {}", err))?;
+            Ok(Vec::new())
+        }
+        "restore" => {
+            let path = command
+                .get("path")
+                .and_then(Json::as_str)
+                .ok_or("expected a \"path\"")?;
+            let restored =
+                savestate::read(Path::new(path), &session.rom).map_err(|err| err.to_string())?;
+            replace(chip8, restored)?;
+            Ok(Vec::new())
+        }
+        _ => Err(format!("unknown command {}", name)),
+    }
+}
+
+fn state(chip8: &Chip8, paused: bool) -> Members {
+    let number = |value: u64| Json::Number(value as f64);
+    let rows = chip8
+        .screen()
+        .chunks(64)
+        .map(|row| Json::String(row.iter().map(|&on| if on { '#' } else { '.' }).collect()))
+        .collect();
+    vec![
+        ("paused".into(), Json::Bool(paused)),
+        ("instructions".into(), number(chip8.instructions)),
+        ("pc".into(), number(chip8.program_counter as u64)),
+        ("i".into(), number(chip8.index as u64)),
+        (
+            "registers".into(),
+            Json::Array(chip8.registers.iter().map(|&v| number(v as u64)).collect()),
+        ),
+        (
+            "stack".into(),
+            Json::Array(
+                chip8.stack[..chip8.stack_pointer as usize]
+                    .iter()
+                    .map(|&address| number(address as u64))
+                    .collect(),
+            ),
+        ),
+        ("delay_timer".into(), number(chip8.delay_timer as u64)),
+        ("sound_timer".into(), number(chip8.sound_timer as u64)),
+        (
+            "display_hash".into(),
+            Json::String(format!("{:016x}", chip8.display_hash())),
+        ),
+        ("display".into(), Json::Array(rows)),
+    ]
+}
+
+/// Swaps in another machine, if the window can show its display
+pub fn replace(chip8: &mut Chip8, mut replacement: Chip8) -> Result<(), String> {
+    if replacement.height() != chip8.height() {
+        return Err(
+            "the machine needs a display of another size; start it from the command line".into(),
+        );
+    }
+    replacement.display_dirty = true;
+    *chip8 = replacement;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PALETTES;
+    use chip8::{MemoryPolicy, Quirks};
+
+    fn session() -> Session {
+        Session {
+            settings: Settings {
+                speed: 700,
+                palette: PALETTES[0].1,
+                memory_policy: MemoryPolicy::Wrap,
+                quirks: Quirks::default(),
+                resume: false,
+            },
+            rom: vec![0x12, 0x00],
+            rom_path: "loop.ch8".into(),
+            screenshot_dir: PathBuf::new(),
+        }
+    }
+
+    /// Handles `command` and returns the reply, if there was one yet, and
+    /// what was left to do
+    fn send(command: &str, chip8: &mut Chip8, locked: bool) -> (Option<Json>, Handled) {
+        let (reply, receiver) = mpsc::channel();
+        let request = Request {
+            command: json::parse(command).unwrap(),
+            reply,
+        };
+        let handled = handle(request, &mut session(), chip8, &mut false, locked);
+        (receiver.try_recv().ok(), handled)
+    }
+
+    #[test]
+    fn load_is_left_to_the_main_loop() {
+        let mut chip8 = Chip8::builder()
+            .with_program(&[0x12, 0x00])
+            .build()
+            .unwrap();
+        let (reply, handled) = send(
+            r#"{"command": "load", "path": "pong.ch8"}"#,
+            &mut chip8,
+            false,
+        );
+        assert!(reply.is_none());
+        let Handled::Load(load) = handled else {
+            panic!("the load wasn't handed back");
+        };
+        assert_eq!(load.path, "pong.ch8");
+        let (reply, receiver) = mpsc::channel();
+        Load { reply, ..load }.finish(Err("no such file".into()));
+        let reply = receiver.recv().unwrap();
+        assert_eq!(reply.to_string(), r#"{"ok":false,"error":"no such file"}"#);
+    }
+
+    #[test]
+    fn load_needs_a_path() {
+        let mut chip8 = Chip8::builder()
+            .with_program(&[0x12, 0x00])
+            .build()
+            .unwrap();
+        let (reply, handled) = send(r#"{"command": "load"}"#, &mut chip8, false);
+        assert!(matches!(handled, Handled::Answered));
+        assert!(reply.unwrap().to_string().starts_with(r#"{"ok":false"#));
+    }
+
+    #[test]
+    fn other_commands_are_answered_here() {
+        let mut chip8 = Chip8::builder()
+            .with_program(&[0x12, 0x00])
+            .build()
+            .unwrap();
+        let (reply, handled) = send(r#"{"command": "press", "key": 5}"#, &mut chip8, false);
+        assert!(matches!(handled, Handled::Answered));
+        assert_eq!(reply.unwrap().to_string(), r#"{"ok":true}"#);
+        assert!(chip8.keys[5]);
+    }
+
+    #[test]
+    fn reset_restarts_the_session() {
+        let mut chip8 = Chip8::builder()
+            .with_program(&[0x12, 0x00])
+            .build()
+            .unwrap();
+        let (reply, handled) = send(r#"{"command": "reset"}"#, &mut chip8, false);
+        assert!(matches!(handled, Handled::Restarted));
+        assert_eq!(reply.unwrap().to_string(), r#"{"ok":true}"#);
+    }
+
+    #[test]
+    fn locked_machines_only_take_commands_that_leave_them_alone() {
+        let mut chip8 = Chip8::builder()
+            .with_program(&[0x12, 0x00])
+            .build()
+            .unwrap();
+        for command in [
+            r#"{"command": "pause"}"#,
+            r#"{"command": "press", "key": 5}"#,
+            r#"{"command": "reset"}"#,
+            r#"{"command": "restore", "path": "game.c8s"}"#,
+            r#"{"command": "load", "path": "pong.ch8"}"#,
+        ] {
+            let (reply, handled) = send(command, &mut chip8, true);
+            assert!(matches!(handled, Handled::Answered));
+            assert!(reply.unwrap().to_string().starts_with(r#"{"ok":false"#));
+        }
+        assert!(!chip8.keys[5]);
+        let (reply, _) = send(r#"{"command": "state"}"#, &mut chip8, true);
+        assert!(reply.unwrap().to_string().starts_with(r#"{"ok":true"#));
+    }
+}
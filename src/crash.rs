@@ -0,0 +1,163 @@
+//! Crash dumps, written when a program hits an error the machine can't go
+//! on from, to attach to bug reports. A dump is a text file with the error,
+//! the registers, the quirks in effect and the last instructions executed
+//! with what each changed, ending in the whole machine as hex on a `state:`
+//! line so it can be loaded again. The state includes the ROM, so a machine
+//! loaded from a dump can also be reset and run from the start.
+
+use crate::history::History;
+use crate::screenshot;
+use chip8::{Chip8, Chip8Error};
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Writes a dump of `chip8`, which failed with `err`, as
+/// `<rom name>-<timestamp>.dump` in `dir` and returns where it went
+pub fn write(
+    dir: &Path,
+    rom_path: &str,
+    err: &Chip8Error,
+    chip8: &mut Chip8,
+    history: &mut History,
+) -> io::Result<PathBuf> {
+    history.settle(chip8);
+    let fault = err
+        .address()
+        .unwrap_or_else(|| chip8.program_counter.wrapping_sub(2));
+    let mut dump = String::new();
+    let _ = writeln!(dump, "CHIP-8 crash dump");
+    let _ = writeln!(dump, "error: {}", err);
+    let _ = writeln!(dump, "rom: {}", rom_path);
+    let _ = writeln!(dump, "fault: {:03X}", fault);
+    let _ = writeln!(
+        dump,
+        "pc: {:03X}  i: {:03X}  dt: {}  st: {}  instructions: {}",
+        chip8.program_counter,
+        chip8.index,
+        chip8.delay_timer,
+        chip8.sound_timer,
+        chip8.instructions
+    );
+    let registers: Vec<String> = chip8
+        .registers
+        .iter()
+        .map(|value| format!("{:02X}", value))
+        .collect();
+    let _ = writeln!(dump, "v: {}", registers.join(" "));
+    let stack: Vec<String> = chip8.stack[..chip8.stack_pointer as usize]
+        .iter()
+        .map(|address| format!("{:03X}", address))
+        .collect();
+    let _ = writeln!(dump, "stack: {}", stack.join(" "));
+    let quirks = chip8.quirks;
+    let _ = writeln!(
+        dump,
+        "quirks: wait_for_release={} display_wait={} random={} memory={}",
+        quirks.wait_for_release, quirks.display_wait, quirks.random, chip8.memory_policy
+    );
+    let _ = writeln!(dump, "\nlast instructions, oldest first:");
+    for line in history.lines(usize::MAX) {
+        let _ = writeln!(dump, "  {}", line);
+    }
+    let state: String = chip8
+        .save_state()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    let _ = writeln!(dump, "\nstate: {}", state);
+    let path = screenshot::output_path(dir, rom_path, "dump")?;
+    fs::write(&path, dump)?;
+    Ok(path)
+}
+
+/// Loads the machine from a dump, stopped at the instruction that failed
+pub fn read(path: &Path) -> Result<Chip8, Chip8Error> {
+    let error = |message: &str| Chip8Error::State(format!("{}: {}", path.display(), message));
+    let text = fs::read_to_string(path).map_err(|err| error(&err.to_string()))?;
+    let field = |name: &str| {
+        text.lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(": "))
+            .ok_or_else(|| error("this is not a complete crash dump"))
+    };
+    let fault = u16::from_str_radix(field("fault")?, 16)
+        .map_err(|_| error("the fault address is damaged"))?;
+    let hex = field("state")?;
+    let state = (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+        })
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(|| error("the machine state is damaged"))?;
+    let mut chip8 = Chip8::from_state(&state)?;
+    chip8.program_counter = fault;
+    Ok(chip8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8::MemoryPolicy;
+    use std::env;
+
+    /// Runs `rom` until it fails and reads back the dump that writes
+    fn dump_and_read(rom: &[u8], name: &str) -> (Chip8Error, Chip8) {
+        let mut chip8 = Chip8::builder()
+            .with_program(rom)
+            .with_memory_policy(MemoryPolicy::Trap)
+            .build()
+            .unwrap();
+        let mut history = History::default();
+        let err = loop {
+            let address = chip8.program_counter;
+            if let Err(err) = chip8.fetch().and_then(|op| {
+                history.record(address, op, &chip8);
+                chip8.execute(op)
+            }) {
+                break err;
+            }
+        };
+        let dir = env::temp_dir().join(format!("chip8-crash-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = write(&dir, name, &err, &mut chip8, &mut history).unwrap();
+        let loaded = read(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        (err, loaded)
+    }
+
+    #[test]
+    fn fault_is_the_failed_instruction() {
+        // RET with nothing on the stack, after a CLS
+        let (err, chip8) = dump_and_read(&[0x00, 0xE0, 0x00, 0xEE], "underflow");
+        assert!(matches!(err, Chip8Error::StackUnderflow { address: 0x202 }));
+        assert_eq!(chip8.program_counter, 0x202);
+    }
+
+    #[test]
+    fn fetch_fault_is_where_the_fetch_was() {
+        // Jump to the last byte of memory, whose instruction runs off the end
+        let (err, chip8) = dump_and_read(&[0x1F, 0xFF], "fetch");
+        assert!(matches!(
+            err,
+            Chip8Error::MemoryOutOfBounds {
+                op: None,
+                address: 0xFFF,
+                ..
+            }
+        ));
+        assert_eq!(chip8.program_counter, 0xFFF);
+    }
+
+    #[test]
+    fn dumped_machine_can_be_reset() {
+        let rom = [0x00, 0xE0, 0x00, 0xEE];
+        let (_, mut chip8) = dump_and_read(&rom, "reset");
+        assert_eq!(chip8.rom, rom);
+        chip8.reset();
+        assert_eq!(chip8.program_counter, chip8::PC_START);
+        assert_eq!(chip8.memory[0x200..0x204], rom);
+    }
+}
@@ -0,0 +1,153 @@
+//! Looks ROMs up in the community CHIP-8 database
+//! (<https://github.com/chip-8/chip-8-database>) by SHA-1, to pick the
+//! speed and quirks they were written for. The database isn't bundled: put
+//! its `sha1-hashes.json`, `programs.json` and `platforms.json` in the
+//! `database` folder of the config directory to use it.
+//!
+//! Of the quirks it lists only `vblank` is a setting here; the others are
+//! fixed in the interpreter, so an entry that needs them otherwise only
+//! gets a warning.
+
+use crate::config;
+use crate::json::{self, Json};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What the database says about one ROM
+pub struct Entry {
+    pub title: String,
+    /// Platform the ROM is listed for first, e.g. `originalChip8`
+    pub platform: Option<String>,
+    /// Instructions per second
+    pub speed: Option<u32>,
+    pub display_wait: Option<bool>,
+    /// What the entry's quirks ask for that the interpreter doesn't do
+    pub missing: Vec<&'static str>,
+}
+
+/// Platforms this interpreter can run. The COSMAC VIP ones aren't, since
+/// they shift, load and store differently.
+pub const SUPPORTED_PLATFORMS: &[&str] = &["modernChip8"];
+
+/// The database's quirks the interpreter can't change: each one's name, the
+/// value the interpreter always behaves as, and what the other value asks
+/// for
+const FIXED_QUIRKS: [(&str, bool, &str); 6] = [
+    ("shift", true, "8XY6 and 8XYE shifting VY into VX"),
+    ("memoryIncrementByX", false, "FX55 and FX65 adding X to I"),
+    (
+        "memoryLeaveIUnchanged",
+        true,
+        "FX55 and FX65 moving I past the registers",
+    ),
+    ("logic", false, "8XY1, 8XY2 and 8XY3 resetting VF"),
+    ("jump", false, "BXNN jumping to XNN plus VX"),
+    ("wrap", true, "sprites cut off at the edges of the screen"),
+];
+
+pub fn dir() -> Option<PathBuf> {
+    Some(config::config_dir()?.join("database"))
+}
+
+/// Looks up the ROM with the given SHA-1 (in lowercase hex). Returns `None`
+/// if the database isn't installed or doesn't have the ROM.
+pub fn lookup(sha1: &str) -> Result<Option<Entry>, String> {
+    let Some(dir) = dir().filter(|dir| dir.join("sha1-hashes.json").exists()) else {
+        return Ok(None);
+    };
+    let hashes = read(&dir.join("sha1-hashes.json"))?;
+    let Some(index) = hashes.get(sha1).and_then(Json::as_f64) else {
+        return Ok(None);
+    };
+    let programs = read(&dir.join("programs.json"))?;
+    let Some(program) = programs.as_array().get(index as usize) else {
+        return Ok(None);
+    };
+    let rom = program.get("roms").and_then(|roms| roms.get(sha1));
+    let platform = rom
+        .and_then(|rom| rom.get("platforms"))
+        .and_then(|platforms| platforms.as_array().first())
+        .and_then(Json::as_str)
+        .map(str::to_string);
+
+    // Quirks the ROM is known to need on its platform beat the platform's
+    // own, and the ROM's tick rate beats the platform's default
+    let platforms = read(&dir.join("platforms.json")).unwrap_or(Json::Null);
+    let platform_info = platform.as_deref().and_then(|id| {
+        platforms
+            .as_array()
+            .iter()
+            .find(|info| info.get("id").and_then(Json::as_str) == Some(id))
+    });
+    let quirk = |name: &str| {
+        let rom_quirks = rom
+            .and_then(|rom| rom.get("quirkyPlatforms"))
+            .and_then(|quirky| quirky.get(platform.as_deref()?));
+        rom_quirks
+            .and_then(|quirks| quirks.get(name))
+            .or_else(|| platform_info?.get("quirks")?.get(name))
+            .and_then(Json::as_bool)
+    };
+    let tickrate = rom
+        .and_then(|rom| rom.get("tickrate"))
+        .or_else(|| platform_info?.get("defaultTickrate"))
+        .and_then(Json::as_f64);
+
+    let display_wait = quirk("vblank");
+    let missing = missing(quirk);
+    Ok(Some(Entry {
+        title: program
+            .get("title")
+            .and_then(Json::as_str)
+            .unwrap_or("untitled")
+            .to_string(),
+        platform,
+        speed: tickrate
+            .map(|tickrate| (tickrate * 60.0) as u32)
+            .filter(|&speed| speed > 0),
+        display_wait,
+        missing,
+    }))
+}
+
+/// What the quirks given by `quirk` ask for that the interpreter can't do
+fn missing(quirk: impl Fn(&str) -> Option<bool>) -> Vec<&'static str> {
+    FIXED_QUIRKS
+        .iter()
+        .filter(|&&(name, fixed, _)| quirk(name).is_some_and(|value| value != fixed))
+        .map(|&(_, _, asked)| asked)
+        .collect()
+}
+
+fn read(path: &Path) -> Result<Json, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| format!("Cannot read {}: {}", path.display(), err))?;
+    json::parse(&text).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_quirks_the_interpreter_lacks_are_missing() {
+        // The COSMAC VIP's, apart from vblank
+        let vip = |name: &str| match name {
+            "shift" | "memoryIncrementByX" | "memoryLeaveIUnchanged" | "wrap" | "jump" => {
+                Some(false)
+            }
+            "logic" | "vblank" => Some(true),
+            _ => None,
+        };
+        assert_eq!(
+            missing(vip),
+            [
+                "8XY6 and 8XYE shifting VY into VX",
+                "FX55 and FX65 moving I past the registers",
+                "8XY1, 8XY2 and 8XY3 resetting VF",
+                "sprites cut off at the edges of the screen",
+            ]
+        );
+        assert!(missing(|_| None).is_empty());
+    }
+}
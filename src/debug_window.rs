@@ -0,0 +1,158 @@
+//! A second window with the registers, the code around the program counter
+//! and the memory at I, opened and closed with W, so they can be watched
+//! while the game keeps its own window at its own aspect ratio.
+
+use crate::overlay;
+use chip8::instruction::Instruction;
+use chip8::Chip8;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use sdl2::VideoSubsystem;
+
+/// Characters in the longest line
+const COLUMNS: u32 = 32;
+/// Lines of text
+const LINES: u32 = 25;
+/// Size of a font pixel when the window opens
+const DOT: u32 = 3;
+/// Instructions shown before and after the one at the program counter
+const CODE_AROUND: u16 = 4;
+/// Rows of 8 bytes of memory shown from I
+const MEMORY_ROWS: u16 = 4;
+/// Innermost return addresses shown
+const STACK_SHOWN: usize = 6;
+
+pub struct DebugWindow {
+    canvas: Canvas<Window>,
+    /// What was drawn last, so unchanged frames aren't drawn again
+    shown: Vec<String>,
+}
+
+impl DebugWindow {
+    pub fn open(video: &VideoSubsystem) -> Result<Self, String> {
+        let window = video
+            .window(
+                "CHIP-8 - debugger",
+                (COLUMNS * 4 + 1) * DOT,
+                (LINES * 6 + 1) * DOT,
+            )
+            .resizable()
+            .build()
+            .map_err(|err| err.to_string())?;
+        let canvas = window
+            .into_canvas()
+            .build()
+            .map_err(|err| err.to_string())?;
+        Ok(Self {
+            canvas,
+            shown: Vec::new(),
+        })
+    }
+
+    /// Takes the events meant for this window, returning whether it is to be
+    /// closed, by closing it or with Escape or W. Other keys go on to the
+    /// game as usual.
+    pub fn handle(&mut self, event: &Event) -> bool {
+        let id = self.canvas.window().id();
+        match *event {
+            Event::Window {
+                window_id,
+                win_event: WindowEvent::Close,
+                ..
+            } => window_id == id,
+            Event::Window { window_id, .. } if window_id == id => {
+                // Resized or uncovered
+                self.shown.clear();
+                false
+            }
+            Event::KeyDown {
+                window_id,
+                keycode: Some(Keycode::Escape | Keycode::W),
+                ..
+            } => window_id == id,
+            _ => false,
+        }
+    }
+
+    /// Draws the machine as it is now, if anything shown has changed
+    pub fn draw(&mut self, chip8: &Chip8) -> Result<(), String> {
+        let lines = lines(chip8);
+        if lines == self.shown {
+            return Ok(());
+        }
+        let (width, height) = self.canvas.window().drawable_size();
+        let dot = (width / (COLUMNS * 4 + 1))
+            .min(height / (LINES * 6 + 1))
+            .max(1);
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        self.canvas.clear();
+        overlay::draw_text(&mut self.canvas, 0, 0, dot, &lines)?;
+        self.canvas.present();
+        self.shown = lines;
+        Ok(())
+    }
+}
+
+/// PC, I, SP, the timers and V0 - VF, four to a line, which the HUD shows
+/// too
+pub fn registers(chip8: &Chip8) -> Vec<String> {
+    let mut lines = vec![
+        format!(
+            "PC {:03X}  I {:03X}  SP {:X}",
+            chip8.program_counter, chip8.index, chip8.stack_pointer
+        ),
+        format!("DT {:3}  ST {:3}", chip8.delay_timer, chip8.sound_timer),
+    ];
+    for (row, values) in chip8.registers.chunks(4).enumerate() {
+        let registers: Vec<String> = values
+            .iter()
+            .enumerate()
+            .map(|(n, value)| format!("V{:X} {:02X}", row * 4 + n, value))
+            .collect();
+        lines.push(registers.join("  "));
+    }
+    lines
+}
+
+fn lines(chip8: &Chip8) -> Vec<String> {
+    let pc = chip8.program_counter;
+    let mut lines = registers(chip8);
+    lines.insert(2, String::new());
+    let stack = &chip8.stack[..chip8.stack_pointer as usize];
+    let mut shown: Vec<String> = stack
+        .iter()
+        .skip(stack.len().saturating_sub(STACK_SHOWN))
+        .map(|address| format!("{:03X}", address))
+        .collect();
+    if stack.len() > STACK_SHOWN {
+        shown.insert(0, "..".into());
+    }
+    if shown.is_empty() {
+        shown.push("-".into());
+    }
+    lines.push(format!("STACK {}", shown.join(" ")));
+    lines.push(String::new());
+    for n in 0..=CODE_AROUND * 2 {
+        let address = pc.wrapping_add(n * 2).wrapping_sub(CODE_AROUND * 2);
+        let op = u16::from_be_bytes([chip8.peek(address), chip8.peek(address.wrapping_add(1))]);
+        lines.push(format!(
+            "{} {:03X} {:04X} {}",
+            if address == pc { ">" } else { " " },
+            address % 4096,
+            op,
+            Instruction::decode(op).map_or(String::new(), |op| op.to_string())
+        ));
+    }
+    lines.push(String::new());
+    for row in 0..MEMORY_ROWS {
+        let address = chip8.index.wrapping_add(row * 8);
+        let bytes: Vec<String> = (0..8)
+            .map(|n| format!("{:02X}", chip8.peek(address.wrapping_add(n))))
+            .collect();
+        lines.push(format!("{:03X} {}", address % 4096, bytes.join(" ")));
+    }
+    lines
+}
@@ -0,0 +1,622 @@
+//! A debugger on the terminal, for stepping through a program and looking
+//! at the machine between instructions. It runs headless, ticking the
+//! timers every `speed / 60` instructions like the other headless modes, and
+//! prints the display as text when asked.
+//!
+//! Commands, most with a one-letter form:
+//!
+//! ```text
+//! step [N]       s   run one or N instructions
+//! next           n   step, running a whole subroutine on a CALL
+//! out            o   run until the current subroutine returns
+//! continue       c   run until a breakpoint, a watchpoint, an error, a
+//!                    halt or the program writing over code it ran
+//! reverse-step [N]
+//!                rs  go back one or N instructions
+//! reverse-continue
+//!                rc  go back to the last breakpoint or watchpoint hit
+//! break ADDR     b   set or clear a breakpoint, in hex or by name
+//! watch A[-B] [r|w|rw]
+//!                w   set or clear a watchpoint on memory A to B, stopping
+//!                    when an instruction writes (the default) or reads it
+//! regs           r   show the registers
+//! history [N]    y   show the last N instructions (default 16) and what
+//!                    each changed
+//! calls          t   show the call stack, innermost first
+//! symbols FILE       name addresses from a symbol file, see `symbols`
+//! mem ADDR [N]   m   show N bytes of memory (default 16)
+//! screen         d   show the display
+//! key K          k   press or release key K, in hex
+//! set X = EXPR       change a register, I, PC, SP, DT, ST or `[ADDR]`
+//! show EXPR      v   show an expression like `[I+1]` after every step
+//! hide N         -   stop showing expression N
+//! quit           q
+//! ```
+//!
+//! Going back works from snapshots of the machine taken every
+//! `SNAPSHOT_EVERY` instructions and whenever a key is pressed or released:
+//! the machine is restored from the last snapshot before the point wanted
+//! and run forward to it, which gives the same result as the first time
+//! because nothing from outside changes in between.
+
+use crate::halt;
+use crate::history::History;
+use crate::selfmod::CodeTracker;
+use crate::symbols::Symbols;
+use crate::watch::Expr;
+use chip8::instruction::Instruction;
+use chip8::{Access, Chip8, Chip8Error};
+use std::collections::{BTreeSet, VecDeque};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::Path;
+
+const HELP: &str = "step [N], next, out, continue, reverse-step [N], reverse-continue,
+break ADDR, watch A[-B] [r|w|rw],
+regs, history [N], calls, symbols FILE, mem ADDR [N], screen, key K, set X = EXPR, show EXPR, hide N, quit";
+
+/// Instructions between snapshots for going back
+const SNAPSHOT_EVERY: u64 = 1000;
+/// Snapshots kept, which at about 4 KB each is a few megabytes and a
+/// million instructions back
+const SNAPSHOTS: usize = 1000;
+
+/// The machine after `executed` instructions
+struct Snapshot {
+    executed: u64,
+    state: Vec<u8>,
+}
+
+/// A range of memory to stop on access to, inclusive
+#[derive(Clone, Copy, PartialEq)]
+struct Watchpoint {
+    start: u16,
+    end: u16,
+    read: bool,
+    write: bool,
+}
+
+impl Watchpoint {
+    fn parse(range: &str, kind: Option<&str>) -> Option<Self> {
+        let (start, end) = range.split_once('-').unwrap_or((range, range));
+        let start = u16::from_str_radix(start, 16).ok()?;
+        let end = u16::from_str_radix(end, 16).ok()?;
+        let (read, write) = match kind.unwrap_or("w") {
+            "r" => (true, false),
+            "w" => (false, true),
+            "rw" => (true, true),
+            _ => return None,
+        };
+        (start <= end).then_some(Self {
+            start,
+            end,
+            read,
+            write,
+        })
+    }
+
+    fn range(&self) -> String {
+        if self.start == self.end {
+            format!("{:03X}", self.start)
+        } else {
+            format!("{:03X}-{:03X}", self.start, self.end)
+        }
+    }
+
+    fn matches(&self, access: &Access) -> bool {
+        (self.start..=self.end).contains(&access.address)
+            && if access.write { self.write } else { self.read }
+    }
+}
+
+struct Debugger {
+    chip8: Chip8,
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+    symbols: Symbols,
+    history: History,
+    code: CodeTracker,
+    /// Executed code the last instruction wrote to, and the instruction
+    overwritten: Option<(u16, u16)>,
+    snapshots: VecDeque<Snapshot>,
+    /// Expressions shown after every step, with their text and last value
+    shown: Vec<(String, Expr, Option<i64>)>,
+    /// Instructions between timer ticks
+    per_frame: u64,
+    executed: u64,
+}
+
+pub fn run(mut chip8: Chip8, speed: u32, symbols: Symbols) -> Result<(), Chip8Error> {
+    // Recorded always, for self-modifying code as well as watchpoints
+    chip8.accesses = Some(Vec::new());
+    let mut debugger = Debugger {
+        chip8,
+        breakpoints: BTreeSet::new(),
+        watchpoints: Vec::new(),
+        symbols,
+        history: History::default(),
+        code: CodeTracker::new(),
+        overwritten: None,
+        snapshots: VecDeque::new(),
+        shown: Vec::new(),
+        per_frame: (speed as u64 / 60).max(1),
+        executed: 0,
+    };
+    debugger.snapshot();
+    println!("Type help for the commands");
+    debugger.show_next();
+    let stdin = io::stdin();
+    loop {
+        print!("> ");
+        let _ = io::stdout().flush();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            return Ok(());
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, args)) = words.split_first() else {
+            continue;
+        };
+        match (command, args) {
+            ("s" | "step", []) => debugger.step_and_show(1),
+            ("s" | "step", [count]) => match count.parse() {
+                Ok(count) => debugger.step_and_show(count),
+                Err(_) => println!("Expected a number of instructions"),
+            },
+            ("n" | "next", []) => debugger.step_over(),
+            ("o" | "out", []) => debugger.step_out(),
+            ("c" | "continue", []) => debugger.run_until(|_| false),
+            ("rs" | "reverse-step", []) => debugger.reverse_step(1),
+            ("rs" | "reverse-step", [count]) => match count.parse() {
+                Ok(count) => debugger.reverse_step(count),
+                Err(_) => println!("Expected a number of instructions"),
+            },
+            ("rc" | "reverse-continue", []) => debugger.reverse_continue(),
+            ("b" | "break", [address]) => {
+                let address = (debugger.symbols.address(address))
+                    .or_else(|| u16::from_str_radix(address, 16).ok());
+                match address {
+                    Some(address) if debugger.breakpoints.remove(&address) => {
+                        println!("Cleared the breakpoint at {}", debugger.describe(address))
+                    }
+                    Some(address) => {
+                        debugger.breakpoints.insert(address);
+                        println!("Breakpoint at {}", debugger.describe(address));
+                    }
+                    None => println!("Expected an address in hex or a symbol"),
+                }
+            }
+            ("w" | "watch", [range, kind @ ..]) if kind.len() <= 1 => {
+                match Watchpoint::parse(range, kind.first().copied()) {
+                    Some(watchpoint) => debugger.toggle_watchpoint(watchpoint),
+                    None => println!("Expected an address or range in hex, then r, w or rw"),
+                }
+            }
+            ("r" | "regs", []) => debugger.show_registers(),
+            ("y" | "history", []) => debugger.show_history(16),
+            ("y" | "history", [count]) => match count.parse() {
+                Ok(count) => debugger.show_history(count),
+                Err(_) => println!("Expected a number of instructions"),
+            },
+            ("t" | "calls", []) => debugger.show_calls(),
+            ("symbols", [path]) => match Symbols::read(Path::new(path)) {
+                Ok(symbols) => {
+                    println!("Read {} symbols", symbols.len());
+                    debugger.symbols = symbols;
+                }
+                Err(err) => println!("{}", err),
+            },
+            ("m" | "mem", [address, rest @ ..]) if rest.len() <= 1 => {
+                let address = u16::from_str_radix(address, 16);
+                let len = rest.first().map_or(Ok(16), |len| len.parse());
+                match (address, len) {
+                    (Ok(address), Ok(len)) => debugger.show_memory(address, len),
+                    _ => println!("Expected an address in hex and a number of bytes"),
+                }
+            }
+            ("d" | "screen", []) => debugger.show_screen(),
+            ("k" | "key", [key]) => match usize::from_str_radix(key, 16) {
+                Ok(key) if key < 16 => {
+                    let held = &mut debugger.chip8.keys[key];
+                    *held = !*held;
+                    let state = if *held { "down" } else { "up" };
+                    println!("Key {:X} is {}", key, state);
+                    debugger.snapshot();
+                }
+                _ => println!("Expected a key from 0 to F"),
+            },
+            ("set", [_, ..]) => {
+                let text = args.join(" ");
+                let parsed = text
+                    .split_once('=')
+                    .and_then(|(target, value)| Some((Expr::parse(target)?, Expr::parse(value)?)));
+                match parsed {
+                    Some((target, value)) => debugger.set(&target, &value),
+                    None => println!("Expected something like set V0 = 5 or set [I+1] = V2"),
+                }
+            }
+            ("v" | "show", [_, ..]) => {
+                let text = args.join(" ");
+                match Expr::parse(&text) {
+                    Some(expr) => {
+                        debugger.shown.push((text, expr, None));
+                        debugger.show_expressions();
+                    }
+                    None => println!("Expected an expression like VA, [I+1] or V0*10+V1"),
+                }
+            }
+            ("hide", [n]) => match n.parse::<usize>() {
+                Ok(n) if (1..=debugger.shown.len()).contains(&n) => {
+                    debugger.shown.remove(n - 1);
+                }
+                _ => println!("Expected the number of a shown expression"),
+            },
+            ("q" | "quit", []) => return Ok(()),
+            ("h" | "help", []) => println!("{}", HELP),
+            _ => println!("Unknown command, type help for the commands"),
+        }
+    }
+}
+
+impl Debugger {
+    /// Sets `target` to `value`, as the machine is now
+    fn set(&mut self, target: &Expr, value: &Expr) {
+        let value = value.eval(&self.chip8);
+        match target.assign(&mut self.chip8, value) {
+            Ok(()) => {
+                // Everything after this point is a different run now
+                self.snapshot();
+                self.show_next();
+            }
+            Err(err) => println!("{}", err),
+        }
+    }
+
+    fn toggle_watchpoint(&mut self, watchpoint: Watchpoint) {
+        if let Some(i) = self.watchpoints.iter().position(|&w| w == watchpoint) {
+            self.watchpoints.remove(i);
+            println!("Cleared the watchpoint on {}", watchpoint.range());
+        } else {
+            self.watchpoints.push(watchpoint);
+            println!("Watchpoint on {}", watchpoint.range());
+        }
+    }
+
+    /// Runs one instruction, returning the first access it made to a
+    /// watchpoint. On an error the program counter is moved back to the
+    /// instruction, so it can be looked at and stepped again.
+    fn execute(&mut self) -> Result<Option<Access>, Chip8Error> {
+        let address = self.chip8.program_counter;
+        let result = self.chip8.fetch().and_then(|op| {
+            self.history.record(address, op, &self.chip8);
+            self.chip8.execute(op)
+        });
+        if let Err(err) = result {
+            self.chip8.program_counter = address;
+            return Err(err);
+        }
+        self.executed += 1;
+        if self.executed.is_multiple_of(self.per_frame) {
+            self.chip8.tick_timers();
+        }
+        let accesses = self.chip8.accesses.as_deref().unwrap_or_default();
+        if let Some(target) = self.code.check(address, accesses) {
+            self.overwritten = Some((target, address));
+        }
+        let hit = self.chip8.accesses.as_mut().and_then(|accesses| {
+            accesses
+                .drain(..)
+                .find(|access| self.watchpoints.iter().any(|w| w.matches(access)))
+        });
+        let snapshots = &self.snapshots;
+        match snapshots.binary_search_by_key(&self.executed, |snapshot| snapshot.executed) {
+            // Having gone back, pick up the snapshot taken here the first
+            // time, for the keys pressed then, which running forward
+            // doesn't reproduce
+            Ok(i) => self.restore(i),
+            Err(i) if i == snapshots.len() && self.executed.is_multiple_of(SNAPSHOT_EVERY) => {
+                self.snapshot()
+            }
+            Err(_) => {}
+        }
+        Ok(hit)
+    }
+
+    /// Runs one instruction, returning whether it touched a watchpoint or
+    /// overwrote code that ran before
+    fn step(&mut self) -> Result<bool, Chip8Error> {
+        let address = self.chip8.program_counter;
+        self.overwritten = None;
+        let hit = self.execute()?;
+        if let Some(access) = hit {
+            let kind = if access.write { "Write to" } else { "Read of" };
+            println!("{} {:03X} by {:03X}", kind, access.address, address);
+        }
+        if let Some((target, by)) = self.overwritten {
+            println!(
+                "Self-modifying code: {:03X} wrote to {:03X}, which ran before",
+                by, target
+            );
+        }
+        Ok(hit.is_some() || self.overwritten.is_some())
+    }
+
+    /// Takes a snapshot of the machine as it is now, replacing any taken at
+    /// this point or later, which going back and changing a key makes stale
+    fn snapshot(&mut self) {
+        while self
+            .snapshots
+            .back()
+            .is_some_and(|snapshot| snapshot.executed >= self.executed)
+        {
+            self.snapshots.pop_back();
+        }
+        if self.snapshots.len() == SNAPSHOTS {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(Snapshot {
+            executed: self.executed,
+            state: self.chip8.save_state(),
+        });
+    }
+
+    /// Restores snapshot `i`, leaving access recording as it is
+    fn restore(&mut self, i: usize) {
+        let snapshot = &self.snapshots[i];
+        let accesses = self.chip8.accesses.take();
+        // The state was made by `save_state`, so it always loads
+        self.chip8 = Chip8::from_state(&snapshot.state).expect("snapshot loads");
+        self.chip8.accesses = accesses;
+        self.executed = snapshot.executed;
+    }
+
+    /// Puts the machine back as it was after `target` instructions, or as
+    /// far back as the snapshots go
+    fn rewind_to(&mut self, target: u64) {
+        let i = self
+            .snapshots
+            .iter()
+            .rposition(|snapshot| snapshot.executed <= target);
+        let Some(i) = i else {
+            println!("Can't go back further than the oldest snapshot");
+            self.restore(0);
+            self.history = History::default();
+            return;
+        };
+        self.restore(i);
+        self.history = History::default();
+        let accesses = self.chip8.accesses.take();
+        while self.executed < target {
+            if let Err(err) = self.execute() {
+                println!("{}", err);
+                break;
+            }
+        }
+        self.chip8.accesses = accesses;
+    }
+
+    fn reverse_step(&mut self, count: u64) {
+        self.rewind_to(self.executed.saturating_sub(count));
+        self.show_next();
+    }
+
+    /// Goes back to the last time a breakpoint was reached or a watchpoint
+    /// touched, replaying the snapshots from the newest back to find it
+    fn reverse_continue(&mut self) {
+        let now = self.executed;
+        let mut end = now;
+        for i in (0..self.snapshots.len()).rev() {
+            if self.snapshots[i].executed >= end {
+                continue;
+            }
+            self.restore(i);
+            let mut found = None;
+            while self.executed < end {
+                if self.breakpoints.contains(&self.chip8.program_counter) {
+                    found = Some(self.executed);
+                }
+                match self.execute() {
+                    Ok(Some(_)) if self.executed < now => found = Some(self.executed),
+                    Ok(_) => {}
+                    Err(_) => break,
+                }
+            }
+            end = self.snapshots[i].executed;
+            if let Some(found) = found {
+                self.rewind_to(found);
+                return self.show_next();
+            }
+        }
+        println!("Reached the oldest snapshot");
+        self.rewind_to(end);
+        self.show_next();
+    }
+
+    fn step_and_show(&mut self, count: u64) {
+        for _ in 0..count {
+            match self.step() {
+                Ok(false) => {}
+                Ok(true) => break,
+                Err(err) => {
+                    println!("{}", err);
+                    break;
+                }
+            }
+        }
+        self.show_next();
+    }
+
+    /// Runs until a breakpoint, an error, a halt or `stop` says so. `stop`
+    /// sees the machine before each instruction after the first.
+    fn run_until(&mut self, mut stop: impl FnMut(&Chip8) -> bool) {
+        let mut watchdog = halt::Watchdog::new((self.per_frame * 60) as u32);
+        let mut first = true;
+        loop {
+            let address = self.chip8.program_counter;
+            if !first && (self.breakpoints.contains(&address) || stop(&self.chip8)) {
+                break;
+            }
+            first = false;
+            let op = self.op_at(address);
+            // The watchdog expects the program counter past the instruction
+            self.chip8.program_counter = address.wrapping_add(2);
+            let halted = watchdog.check(&self.chip8, op);
+            self.chip8.program_counter = address;
+            if halted {
+                println!("The program has halted");
+                break;
+            }
+            match self.step() {
+                Ok(false) => {}
+                Ok(true) => break,
+                Err(err) => {
+                    println!("{}", err);
+                    break;
+                }
+            }
+            if self.chip8.blocked_on_key {
+                println!("The program is waiting for a key");
+                break;
+            }
+        }
+        self.show_next();
+    }
+
+    /// Steps, but a 2NNN CALL runs until it returns to the next instruction
+    fn step_over(&mut self) {
+        let address = self.chip8.program_counter;
+        if self.op_at(address) & 0xF000 != 0x2000 {
+            return self.step_and_show(1);
+        }
+        let depth = self.chip8.stack_pointer;
+        let back = address.wrapping_add(2);
+        // Checking the depth too keeps a recursive call from stopping early
+        self.run_until(|chip8| chip8.program_counter == back && chip8.stack_pointer == depth);
+    }
+
+    fn step_out(&mut self) {
+        let depth = self.chip8.stack_pointer;
+        if depth == 0 {
+            println!("Not in a subroutine");
+            return;
+        }
+        self.run_until(|chip8| chip8.stack_pointer < depth);
+    }
+
+    fn op_at(&self, address: u16) -> u16 {
+        (self.chip8.peek(address) as u16) << 8 | self.chip8.peek(address.wrapping_add(1)) as u16
+    }
+
+    fn show_next(&mut self) {
+        let address = self.chip8.program_counter;
+        let op = self.op_at(address);
+        let text = Instruction::decode(op).map_or("unknown".into(), |op| op.to_string());
+        if let Some(name) = self.symbols.name(address) {
+            println!("{}:", name);
+        }
+        match Instruction::decode(op)
+            .and_then(Instruction::address)
+            .and_then(|target| self.symbols.name(target))
+        {
+            Some(name) => println!("{:03X}  {:04X}  {}  ({})", address, op, text, name),
+            None => println!("{:03X}  {:04X}  {}", address, op, text),
+        }
+        self.show_expressions();
+    }
+
+    /// Shows the expressions, marking the ones that changed since they were
+    /// last shown, in bold too on a terminal
+    fn show_expressions(&mut self) {
+        let bold = io::stdout().is_terminal();
+        for (n, (text, expr, last)) in self.shown.iter_mut().enumerate() {
+            let value = expr.eval(&self.chip8);
+            let changed = last.is_some_and(|last| last != value);
+            *last = Some(value);
+            let line = format!("{:>3}  {} = {:X} ({})", n + 1, text, value, value);
+            match (changed, bold) {
+                (false, _) => println!("  {}", line),
+                (true, false) => println!("* {}", line),
+                (true, true) => println!("* \x1b[1m{}\x1b[0m", line),
+            }
+        }
+    }
+
+    fn describe(&self, address: u16) -> String {
+        self.symbols.describe(address)
+    }
+
+    /// Shows where each frame is, from the program counter out through the
+    /// CALL before each return address on the stack, and the subroutine it's
+    /// in, taken from the CALL that entered it
+    fn show_calls(&self) {
+        let returns = &self.chip8.stack[..self.chip8.stack_pointer as usize];
+        let mut frames = vec![self.chip8.program_counter];
+        frames.extend(returns.iter().rev().map(|address| address.wrapping_sub(2)));
+        for (depth, &address) in frames.iter().enumerate() {
+            let inside = match frames.get(depth + 1) {
+                Some(&call) => match self.op_at(call) {
+                    op if op & 0xF000 == 0x2000 => self.describe(op & 0x0FFF),
+                    op => format!("? ({:04X} at {:03X} is not a CALL)", op, call),
+                },
+                None => "the top level".into(),
+            };
+            println!("#{:<2} {:<16} in {}", depth, self.describe(address), inside);
+        }
+    }
+
+    fn show_history(&mut self, count: usize) {
+        self.history.settle(&self.chip8);
+        for line in self.history.lines(count) {
+            println!("{}", line);
+        }
+    }
+
+    fn show_registers(&self) {
+        let chip8 = &self.chip8;
+        for (row, registers) in chip8.registers.chunks(8).enumerate() {
+            let values: Vec<String> = registers
+                .iter()
+                .enumerate()
+                .map(|(i, value)| format!("V{:X}={:02X}", row * 8 + i, value))
+                .collect();
+            println!("{}", values.join(" "));
+        }
+        let stack: Vec<String> = chip8.stack[..chip8.stack_pointer as usize]
+            .iter()
+            .map(|address| format!("{:03X}", address))
+            .collect();
+        println!(
+            "PC={:03X} I={:03X} DT={:02X} ST={:02X} stack=[{}]",
+            chip8.program_counter,
+            chip8.index,
+            chip8.delay_timer,
+            chip8.sound_timer,
+            stack.join(" ")
+        );
+    }
+
+    fn show_memory(&self, address: u16, len: usize) {
+        let bytes: Vec<u8> = (0..len)
+            .map(|i| self.chip8.peek(address.wrapping_add(i as u16)))
+            .collect();
+        for (row, chunk) in bytes.chunks(16).enumerate() {
+            let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02X}", byte)).collect();
+            let row_address = address as usize + row * 16;
+            println!(
+                "{:03X}  {}",
+                row_address % self.chip8.memory.len(),
+                hex.join(" ")
+            );
+        }
+    }
+
+    fn show_screen(&self) {
+        for row in self.chip8.screen().chunks(64) {
+            println!(
+                "{}",
+                row.iter()
+                    .map(|&on| if on { '#' } else { '.' })
+                    .collect::<String>()
+            );
+        }
+    }
+}
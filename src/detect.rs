@@ -0,0 +1,71 @@
+//! What running ROMs showed about the platform they need and looking at them
+//! didn't: the SUPER-CHIP instruction they first reached, often from code
+//! that static analysis can't follow, such as a jump through a table. Kept
+//! in the data directory as one line per ROM, its CRC-32, then the opcode
+//! and its address, so loading the ROM again can warn before it fails.
+
+use crate::{flags, png};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+fn path() -> Option<PathBuf> {
+    Some(flags::data_dir()?.join("super-chip.txt"))
+}
+
+/// The SUPER-CHIP opcode `rom` reached, and where, in an earlier session
+pub fn super_chip(rom: &[u8]) -> Option<(u16, u16)> {
+    let text = fs::read_to_string(path()?).ok()?;
+    find(&text, png::crc32(rom))
+}
+
+/// Remembers that `rom` reached the SUPER-CHIP opcode `op` at `address`
+pub fn record_super_chip(rom: &[u8], op: u16, address: u16) -> io::Result<()> {
+    let Some(path) = path() else {
+        return Ok(());
+    };
+    let crc = png::crc32(rom);
+    let mut text = fs::read_to_string(&path).unwrap_or_default();
+    if find(&text, crc).is_some() {
+        return Ok(());
+    }
+    text.push_str(&format!("{:08x} {:04X} {:03X}\n", crc, op, address));
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, text)
+}
+
+fn find(text: &str, crc: u32) -> Option<(u16, u16)> {
+    text.lines().find_map(|line| {
+        let mut fields = line.split_whitespace();
+        if u32::from_str_radix(fields.next()?, 16).ok()? != crc {
+            return None;
+        }
+        let op = u16::from_str_radix(fields.next()?, 16).ok()?;
+        let address = u16::from_str_radix(fields.next()?, 16).ok()?;
+        Some((op, address))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_line_for_the_rom() {
+        let text = "0000abcd 00FF 200\n12345678 00FE 2A4\n";
+        assert_eq!(find(text, 0x1234_5678), Some((0x00FE, 0x2A4)));
+        assert_eq!(find(text, 0xABCD), Some((0x00FF, 0x200)));
+        assert_eq!(find(text, 0x1111_1111), None);
+    }
+
+    #[test]
+    fn damaged_lines_are_skipped() {
+        assert_eq!(find("12345678 00FE\n", 0x1234_5678), None);
+        assert_eq!(
+            find("nonsense\n12345678 00FE 2A4\n", 0x1234_5678),
+            Some((0x00FE, 0x2A4))
+        );
+    }
+}
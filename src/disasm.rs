@@ -0,0 +1,65 @@
+//! Finding which parts of a ROM the program can reach, for tools that look
+//! at ROMs without running them. Opcodes are decoded by
+//! [`Instruction::decode`].
+
+use crate::instruction::Instruction;
+use std::collections::BTreeSet;
+
+/// Size in bytes of the instruction that starts with `op`
+pub fn len(op: u16) -> u16 {
+    if op == 0xF000 {
+        // The address follows in the next two bytes
+        4
+    } else {
+        2
+    }
+}
+
+/// Addresses of the instructions reachable from `start` in a ROM loaded at
+/// `load_address`, following jumps, calls and both sides of skips.
+/// Computed jumps (BNNN) can't be followed, so code reached only through
+/// them is missed.
+pub fn reachable(rom: &[u8], load_address: u16, start: u16) -> BTreeSet<u16> {
+    walk(rom, load_address, start, true)
+}
+
+/// Like `reachable`, but stepping over calls rather than into them, which
+/// leaves the code of one subroutine, or of the top level of the program
+pub fn reachable_without_calls(rom: &[u8], load_address: u16, start: u16) -> BTreeSet<u16> {
+    walk(rom, load_address, start, false)
+}
+
+fn walk(rom: &[u8], load_address: u16, start: u16, into_calls: bool) -> BTreeSet<u16> {
+    let fetch = |address: u16| {
+        let offset = address.checked_sub(load_address)? as usize;
+        let bytes = rom.get(offset..offset + 2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    };
+    let mut seen = BTreeSet::new();
+    let mut pending = vec![start];
+    while let Some(address) = pending.pop() {
+        let Some(op) = fetch(address) else {
+            continue;
+        };
+        if !seen.insert(address) {
+            continue;
+        }
+        let next = address + len(op);
+        let skipped = fetch(next).map_or(next + 2, |next_op| next + len(next_op));
+        match Instruction::decode(op) {
+            Some(Instruction::Return | Instruction::Exit | Instruction::JumpOffset(_)) => (),
+            Some(Instruction::Jump(target)) => pending.push(target),
+            Some(Instruction::Call(target)) if into_calls => pending.extend([target, next]),
+            Some(
+                Instruction::SkipIfEqual { .. }
+                | Instruction::SkipIfNotEqual { .. }
+                | Instruction::SkipIfRegistersEqual { .. }
+                | Instruction::SkipIfRegistersNotEqual { .. }
+                | Instruction::SkipIfKey(_)
+                | Instruction::SkipIfNotKey(_),
+            ) => pending.extend([next, skipped]),
+            _ => pending.push(next),
+        }
+    }
+    seen
+}
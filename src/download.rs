@@ -0,0 +1,23 @@
+//! Fetching ROMs over HTTP(S), through curl so no TLS stack has to be
+//! built in
+
+use chip8::Chip8Error;
+use std::process::Command;
+
+pub fn fetch(url: &str) -> Result<Vec<u8>, Chip8Error> {
+    let output = Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location"])
+        // Anything bigger can't be a CHIP-8 ROM anyway
+        .args(["--max-filesize", "65536"])
+        .arg(url)
+        .output()
+        .map_err(|err| Chip8Error::Download(format!("Cannot run curl: {}", err)))?;
+    if !output.status.success() {
+        return Err(Chip8Error::Download(format!(
+            "Cannot download {}: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(output.stdout)
+}
@@ -0,0 +1,220 @@
+//! `chip8 edit`: a window for drawing a sprite in a ROM pixel by pixel with
+//! the mouse. S writes it back into the ROM file and D prints it as `DB`
+//! lines in the form `chip8 disasm` lists sprites, to paste into source.
+
+use crate::{listing, log, overlay, sdl_error};
+use chip8::{disasm, rom, Chip8Error, PC_START};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use std::fs;
+use std::path::PathBuf;
+
+/// Size of a font pixel
+const DOT: u32 = 2;
+/// Height of the help text under the sprite
+const TEXT_HEIGHT: u32 = 3 * 6 * DOT + DOT;
+
+#[derive(clap::Args)]
+pub struct EditArgs {
+    rom: PathBuf,
+
+    /// Address of the sprite, in hex
+    #[arg(value_parser = parse_address)]
+    address: u16,
+
+    /// Rows in the sprite, by default the rows the code draws there, or 8
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=15))]
+    height: Option<u8>,
+
+    /// Edit a 16x16 SUPER-CHIP sprite, two bytes a row
+    #[arg(long, conflicts_with = "height")]
+    wide: bool,
+}
+
+fn parse_address(text: &str) -> Result<u16, String> {
+    u16::from_str_radix(text.trim_start_matches("0x"), 16)
+        .map_err(|_| format!("{} is not a hex address", text))
+}
+
+pub fn run(args: EditArgs) -> Result<(), Chip8Error> {
+    let mut data = rom::read(&args.rom)?;
+    let (width, height) = match (args.wide, args.height) {
+        (true, _) => (16, 16),
+        (false, Some(height)) => (8, height as usize),
+        (false, None) => (8, drawn_height(&data, args.address).unwrap_or(8)),
+    };
+    let bytes = width / 8 * height;
+    let offset = (args.address as usize)
+        .checked_sub(PC_START as usize)
+        .filter(|offset| offset + bytes <= data.len())
+        .ok_or_else(|| {
+            Chip8Error::Sprites(format!(
+                "A {}x{} sprite at {:03X} is not inside the ROM, which is {:03X}-{:03X}",
+                width,
+                height,
+                args.address,
+                PC_START,
+                PC_START as usize + data.len() - 1
+            ))
+        })?;
+    let range = offset..offset + bytes;
+
+    let sdl = sdl2::init().map_err(sdl_error)?;
+    let video = sdl.video().map_err(sdl_error)?;
+    let window = video
+        .window("CHIP-8 - sprite editor", 64 * 10, 32 * 10)
+        .resizable()
+        .build()
+        .map_err(sdl_error)?;
+    let mut canvas = window.into_canvas().build().map_err(sdl_error)?;
+    let mut events = sdl.event_pump().map_err(sdl_error)?;
+
+    let mut saved = data[range.clone()].to_vec();
+    // What a drag sets pixels to, taken from the first pixel it toggled
+    let mut painting = None;
+    let mut status = String::new();
+    loop {
+        let (window_width, window_height) = canvas.window().drawable_size();
+        let cell = (window_width / width as u32)
+            .min(window_height.saturating_sub(TEXT_HEIGHT) / height as u32)
+            .max(1);
+        let cell_at = |x: i32, y: i32| {
+            let (column, row) = (x / cell as i32, y / cell as i32);
+            ((0..width as i32).contains(&column) && (0..height as i32).contains(&row))
+                .then_some((column as usize, row as usize))
+        };
+        let byte_at = |column: usize, row: usize| offset + row * width / 8 + column / 8;
+        let pixel =
+            |data: &[u8], column, row| data[byte_at(column, row)] & (0x80 >> (column % 8)) != 0;
+
+        canvas.set_draw_color(Color::RGB(32, 32, 32));
+        canvas.clear();
+        for row in 0..height {
+            for column in 0..width {
+                let lit = pixel(&data, column, row);
+                let shade = if lit { 255 } else { 0 };
+                canvas.set_draw_color(Color::RGB(shade, shade, shade));
+                // A one pixel gap shows the grid
+                let rect = Rect::new(
+                    (column as u32 * cell) as i32,
+                    (row as u32 * cell) as i32,
+                    cell.saturating_sub(1).max(1),
+                    cell.saturating_sub(1).max(1),
+                );
+                canvas.fill_rect(rect).map_err(sdl_error)?;
+            }
+        }
+        let changed = data[range.clone()] != saved[..];
+        let lines = vec![
+            format!(
+                "{:03X} {}X{}{}",
+                args.address,
+                width,
+                height,
+                if changed { " - CHANGED" } else { "" }
+            ),
+            "CLICK TO DRAW, S TO SAVE, D TO PRINT DB, ESC TO QUIT".to_string(),
+            status.clone(),
+        ];
+        let y = (height as u32 * cell) as i32;
+        overlay::draw_text(&mut canvas, 0, y, DOT, &lines).map_err(sdl_error)?;
+        canvas.present();
+
+        match events.wait_event() {
+            Event::Quit { .. }
+            | Event::KeyDown {
+                keycode: Some(Keycode::Escape),
+                ..
+            } => {
+                if changed && !status.starts_with("UNSAVED") {
+                    status = "UNSAVED CHANGES, ESC AGAIN TO QUIT".to_string();
+                    continue;
+                }
+                return Ok(());
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::S),
+                ..
+            } => match fs::write(&args.rom, &data) {
+                Ok(()) => {
+                    saved = data[range.clone()].to_vec();
+                    status = "SAVED".to_string();
+                    println!("Saved the sprite to {}", args.rom.display());
+                }
+                Err(err) => {
+                    status = "CANNOT SAVE".to_string();
+                    log::warn!("Cannot save {}: {}", args.rom.display(), err);
+                }
+            },
+            Event::KeyDown {
+                keycode: Some(Keycode::D),
+                ..
+            } => {
+                for (i, row) in data[range.clone()].chunks(width / 8).enumerate() {
+                    print!(
+                        "{}",
+                        listing::sprite_row(row, args.address as usize + i * width / 8)
+                    );
+                }
+                status = "PRINTED".to_string();
+            }
+            Event::MouseButtonDown {
+                mouse_btn: MouseButton::Left,
+                x,
+                y,
+                ..
+            } => {
+                if let Some((column, row)) = cell_at(x, y) {
+                    let lit = !pixel(&data, column, row);
+                    set(&mut data, byte_at(column, row), column, lit);
+                    painting = Some(lit);
+                    status.clear();
+                }
+            }
+            Event::MouseMotion { x, y, .. } => {
+                if let (Some(lit), Some((column, row))) = (painting, cell_at(x, y)) {
+                    set(&mut data, byte_at(column, row), column, lit);
+                }
+            }
+            Event::MouseButtonUp {
+                mouse_btn: MouseButton::Left,
+                ..
+            } => painting = None,
+            _ => (),
+        }
+    }
+}
+
+/// Sets or clears the pixel in `column` of the sprite, which is in the byte
+/// at `offset`
+fn set(data: &mut [u8], offset: usize, column: usize, lit: bool) {
+    let bit = 0x80 >> (column % 8);
+    if lit {
+        data[offset] |= bit;
+    } else {
+        data[offset] &= !bit;
+    }
+}
+
+/// The height the code draws a sprite at `address` with, as `chip8 disasm`
+/// and `chip8 sprites` find it
+fn drawn_height(data: &[u8], address: u16) -> Option<usize> {
+    let start = if rom::is_hires(data) {
+        chip8::HIRES_START
+    } else {
+        PC_START
+    };
+    let op_at = |address: u16| {
+        let offset = (address - PC_START) as usize;
+        u16::from_be_bytes([data[offset], data[offset + 1]])
+    };
+    let code = disasm::reachable(data, PC_START, start);
+    // 16x16 sprites need --wide
+    listing::sprite_heights(&code, op_at)
+        .get(&address)
+        .copied()
+        .filter(|&height| height <= 15)
+}
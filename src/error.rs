@@ -0,0 +1,162 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum Chip8Error {
+    /// The ROM file could not be read
+    Io { path: PathBuf, source: io::Error },
+    /// The ROM does not fit between the load address and the end of memory
+    RomTooLarge { size: usize, max: usize },
+    /// A load address that would overwrite the font or is past the end of
+    /// memory
+    LoadAddress(u16),
+    /// The file is something other than a CHIP-8 program
+    NotChip8(&'static str),
+    /// An opcode that is not part of CHIP-8
+    UnknownInstruction { op: u16, address: u16 },
+    /// A SUPER-CHIP opcode, which this interpreter does not implement
+    SuperChipInstruction { op: u16, address: u16 },
+    /// CALL with all 16 stack entries in use, listing them outermost first
+    StackOverflow { address: u16, stack: Vec<u16> },
+    /// RET with nothing on the stack
+    StackUnderflow { address: u16 },
+    /// An access past the end of memory by `op` at `address`, or while
+    /// fetching the instruction there if `op` is `None`
+    MemoryOutOfBounds {
+        target: usize,
+        op: Option<u16>,
+        address: u16,
+    },
+    /// The program ran into 0000, which ends it
+    Exit { address: u16 },
+    /// Invalid settings in a config file
+    Config(String),
+    /// A font file that can't be used
+    Font(String),
+    /// An input movie that can't be read or written
+    Movie(String),
+    /// A cheat file that can't be read or parsed
+    Cheats(String),
+    /// A symbol file that can't be read or parsed
+    Symbols(String),
+    /// Sprites that `chip8 sprites` or `chip8 edit` can't find or save
+    Sprites(String),
+    /// A ROM patch that can't be read or applied
+    Patch(String),
+    /// Some ROMs run by `chip8 test` didn't match their references
+    TestsFailed { failed: usize, total: usize },
+    /// `chip8 lint` found problems
+    LintFailed { warnings: usize },
+    /// Window, renderer or input setup failed
+    Frontend(String),
+    /// A ROM given as a URL could not be fetched
+    Download(String),
+    /// A ROM could not be taken out of the archive it came in
+    Archive(String),
+    /// A save state that can't be restored
+    State(String),
+}
+
+impl Chip8Error {
+    /// Where the instruction that failed is, for errors raised by running
+    /// one. A failed fetch is at the address it was fetching from.
+    pub fn address(&self) -> Option<u16> {
+        match *self {
+            Chip8Error::UnknownInstruction { address, .. }
+            | Chip8Error::SuperChipInstruction { address, .. }
+            | Chip8Error::StackOverflow { address, .. }
+            | Chip8Error::StackUnderflow { address }
+            | Chip8Error::MemoryOutOfBounds { address, .. }
+            | Chip8Error::Exit { address } => Some(address),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chip8Error::Io { path, source } => {
+                write!(f, "Cannot read ROM {}: {}", path.display(), source)
+            }
+            Chip8Error::RomTooLarge { size, max } => write!(
+                f,
+                "ROM is {} bytes, but at most {} bytes fit in memory",
+                size, max
+            ),
+            Chip8Error::LoadAddress(address) => write!(
+                f,
+                "Cannot load ROMs at {:X}: the address must be between 50 (after the font) and FFF",
+                address
+            ),
+            Chip8Error::NotChip8(hint) => write!(f, "This is not a CHIP-8 ROM: {}", hint),
+            Chip8Error::UnknownInstruction { op, address } => {
+                write!(f, "Unknown instruction {:04X} at {:03X}", op, address)
+            }
+            Chip8Error::SuperChipInstruction { op, address } => write!(
+                f,
+                "SUPER-CHIP instruction {:04X} at {:03X}: this ROM needs a SUPER-CHIP interpreter",
+                op, address
+            ),
+            Chip8Error::StackOverflow { address, stack } => {
+                write!(f, "Stack overflow at {:03X}, call stack:", address)?;
+                for return_address in stack {
+                    write!(f, " {:03X}", return_address)?;
+                }
+                Ok(())
+            }
+            Chip8Error::StackUnderflow { address } => {
+                write!(
+                    f,
+                    "Stack underflow at {:03X}: RET with an empty call stack",
+                    address
+                )
+            }
+            Chip8Error::MemoryOutOfBounds {
+                target,
+                op: Some(op),
+                address,
+            } => write!(
+                f,
+                "Instruction {:04X} at {:03X} accessed memory at {:X}, past the end of memory",
+                op, address, target
+            ),
+            Chip8Error::MemoryOutOfBounds {
+                target,
+                op: None,
+                address,
+            } => write!(
+                f,
+                "Cannot fetch the instruction at {:03X}: {:X} is past the end of memory",
+                address, target
+            ),
+            Chip8Error::Exit { address } => write!(f, "Program exited at {:03X}", address),
+            Chip8Error::Config(message) => write!(f, "{}", message),
+            Chip8Error::Font(message) => write!(f, "{}", message),
+            Chip8Error::Movie(message) => write!(f, "{}", message),
+            Chip8Error::Cheats(message) => write!(f, "{}", message),
+            Chip8Error::Symbols(message) => write!(f, "{}", message),
+            Chip8Error::Sprites(message) => write!(f, "{}", message),
+            Chip8Error::Patch(message) => write!(f, "{}", message),
+            Chip8Error::TestsFailed { failed, total } => {
+                write!(f, "{} of {} test ROMs failed", failed, total)
+            }
+            Chip8Error::LintFailed { warnings: 1 } => write!(f, "1 warning"),
+            Chip8Error::LintFailed { warnings } => write!(f, "{} warnings", warnings),
+            Chip8Error::Frontend(message) => write!(f, "Frontend error: {}", message),
+            Chip8Error::Download(message) => write!(f, "{}", message),
+            Chip8Error::Archive(message) => write!(f, "{}", message),
+            Chip8Error::State(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Chip8Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
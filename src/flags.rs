@@ -0,0 +1,41 @@
+//! Keeps the SUPER-CHIP flag registers (FX75/FX85) between sessions, which
+//! games use for high scores. Each ROM gets its own file in the data
+//! directory, named after a checksum of the ROM so renaming it doesn't
+//! lose the scores.
+
+use crate::png;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where the flags for `rom` are kept
+pub fn path(rom: &[u8]) -> Option<PathBuf> {
+    let dir = data_dir()?.join("flags");
+    Some(dir.join(format!("{:08x}.bin", png::crc32(rom))))
+}
+
+/// Flags saved by an earlier session, or all zeroes
+pub fn load(path: &Path) -> [u8; 8] {
+    let mut flags = [0; 8];
+    if let Ok(saved) = fs::read(path) {
+        let len = saved.len().min(flags.len());
+        flags[..len].copy_from_slice(&saved[..len]);
+    }
+    flags
+}
+
+pub fn save(path: &Path, flags: &[u8; 8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, flags)
+}
+
+/// Per-user data directory, e.g. `~/.local/share/chip8`
+pub fn data_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))?;
+    Some(base.join("chip8"))
+}
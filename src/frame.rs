@@ -0,0 +1,74 @@
+//! What the machine showed and sounded on the last frame, for the threads
+//! that send it somewhere else. The main loop owns the machine and publishes
+//! its output here when it changes, so those threads never touch the
+//! machine.
+
+use chip8::Chip8;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Clone)]
+pub struct Frame {
+    pub display: Vec<bool>,
+    pub beeping: bool,
+}
+
+impl Frame {
+    pub fn of(chip8: &Chip8) -> Self {
+        Self {
+            display: chip8.screen(),
+            beeping: chip8.sound_timer > 0,
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        self.display.len() / 64
+    }
+}
+
+pub type SharedFrame = Arc<Mutex<Frame>>;
+
+/// The main loop's end of a `SharedFrame`. Frames are filled in on the side
+/// and swapped in, so readers are only ever kept waiting for the swap.
+pub struct Publisher {
+    shared: SharedFrame,
+    back: Frame,
+    beeping: bool,
+    /// Time spent waiting for readers to let go of the frame, for `--bench`
+    pub waited: Duration,
+    /// Frames published
+    pub published: u64,
+}
+
+impl Publisher {
+    pub fn new(chip8: &Chip8) -> Self {
+        Self {
+            shared: Arc::new(Mutex::new(Frame::of(chip8))),
+            back: Frame::of(chip8),
+            beeping: chip8.sound_timer > 0,
+            waited: Duration::ZERO,
+            published: 0,
+        }
+    }
+
+    /// Readers' end, for the threads that send the frames on
+    pub fn shared(&self) -> SharedFrame {
+        self.shared.clone()
+    }
+
+    /// Publishes the machine's output if it changed since the last frame
+    pub fn publish(&mut self, chip8: &Chip8, display_changed: bool) {
+        let beeping = chip8.sound_timer > 0;
+        if !display_changed && beeping == self.beeping {
+            return;
+        }
+        self.beeping = beeping;
+        self.back.display.copy_from_slice(&chip8.screen());
+        self.back.beeping = beeping;
+        let start = Instant::now();
+        let mut shared = self.shared.lock().unwrap();
+        self.waited += start.elapsed();
+        std::mem::swap(&mut *shared, &mut self.back);
+        self.published += 1;
+    }
+}
@@ -0,0 +1,125 @@
+//! The display as frontends want it, without knowing that it's kept as a
+//! row of bits per line.
+
+/// The visible rows of the display, from `Chip8::display`
+#[derive(Clone, Copy)]
+pub struct Framebuffer<'a> {
+    rows: &'a [u64],
+}
+
+impl<'a> Framebuffer<'a> {
+    pub(crate) fn new(rows: &'a [u64]) -> Self {
+        Self { rows }
+    }
+
+    pub fn width(&self) -> usize {
+        64
+    }
+
+    /// 32, or 64 in hires mode
+    pub fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Whether the pixel at (`x`, `y`) is lit, or `None` off the display
+    pub fn pixel(&self, x: usize, y: usize) -> Option<bool> {
+        let row = self.rows.get(y).filter(|_| x < 64)?;
+        Some(row & (1 << 63 >> x) != 0)
+    }
+
+    /// Every pixel as `(x, y, lit)`, row by row
+    pub fn pixels(&self) -> impl Iterator<Item = (usize, usize, bool)> + 'a {
+        let rows = self.rows;
+        (0..64 * rows.len()).map(move |i| {
+            let (x, y) = (i % 64, i / 64);
+            (x, y, rows[y] & (1 << 63 >> x) != 0)
+        })
+    }
+
+    /// The 64x32 display as eight bytes a row with the leftmost pixel in
+    /// the top bit, or `None` in hires mode
+    pub fn packed(&self) -> Option<[u8; 256]> {
+        self.pack()
+    }
+
+    /// Like `packed`, for the 64x64 display in hires mode
+    pub fn packed_hires(&self) -> Option<[u8; 512]> {
+        self.pack()
+    }
+
+    fn pack<const N: usize>(&self) -> Option<[u8; N]> {
+        if self.rows.len() * 8 != N {
+            return None;
+        }
+        let mut bytes = [0; N];
+        for (chunk, row) in bytes.chunks_exact_mut(8).zip(self.rows) {
+            chunk.copy_from_slice(&row.to_be_bytes());
+        }
+        Some(bytes)
+    }
+
+    /// Fills `rgba`, four bytes a pixel row by row, with `off` and `on` for
+    /// unlit and lit pixels
+    ///
+    /// # Panics
+    ///
+    /// If `rgba` isn't `width() * height() * 4` bytes long
+    pub fn copy_to_rgba(&self, rgba: &mut [u8], off: [u8; 4], on: [u8; 4]) {
+        assert_eq!(
+            rgba.len(),
+            self.width() * self.height() * 4,
+            "the buffer has to be 4 bytes for each {}x{} pixel",
+            self.width(),
+            self.height()
+        );
+        for (pixel, (_, _, lit)) in rgba.chunks_exact_mut(4).zip(self.pixels()) {
+            pixel.copy_from_slice(if lit { &on } else { &off });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixels_off_the_display_are_none() {
+        let mut rows = [0; 32];
+        rows[31] = 1;
+        let display = Framebuffer::new(&rows);
+        assert_eq!(display.pixel(63, 31), Some(true));
+        assert_eq!(display.pixel(62, 31), Some(false));
+        assert_eq!(display.pixel(64, 0), None);
+        assert_eq!(display.pixel(0, 32), None);
+        assert_eq!(display.pixel(usize::MAX, usize::MAX), None);
+    }
+
+    #[test]
+    fn packed_is_eight_bytes_a_row_leftmost_pixel_first() {
+        let mut rows = [0; 32];
+        rows[0] = 1 << 63;
+        rows[31] = 0x0102_0304_0506_0708;
+        let packed = Framebuffer::new(&rows).packed().unwrap();
+        assert_eq!(packed[..8], [0x80, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(packed[248..], [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert!(Framebuffer::new(&rows).packed_hires().is_none());
+    }
+
+    #[test]
+    fn hires_packs_only_at_its_own_size() {
+        let rows = [u64::MAX; 64];
+        let display = Framebuffer::new(&rows);
+        assert!(display.packed().is_none());
+        assert_eq!(display.packed_hires(), Some([0xFF; 512]));
+    }
+
+    #[test]
+    fn rgba_takes_a_colour_per_pixel() {
+        let mut rows = [0; 32];
+        rows[0] = 1 << 62;
+        let mut rgba = vec![0; 64 * 32 * 4];
+        Framebuffer::new(&rows).copy_to_rgba(&mut rgba, [0, 0, 0, 255], [1, 2, 3, 4]);
+        assert_eq!(rgba[..8], [0, 0, 0, 255, 1, 2, 3, 4]);
+        assert!(rgba[8..].chunks(4).all(|pixel| pixel == [0, 0, 0, 255]));
+    }
+}
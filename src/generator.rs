@@ -0,0 +1,45 @@
+//! Where CXNN's numbers come from. By default that's ChaCha, whose place in
+//! its stream can be read back, so a save state holds the generator
+//! exactly and a restored machine draws the numbers the saved one would
+//! have. Any other generator can be plugged in, but states can't hold it.
+
+use rand::RngCore;
+use rand_chacha::ChaCha12Rng;
+
+pub enum Generator {
+    /// ChaCha with 12 rounds, which is what `StdRng` is, so a seed gives
+    /// the same numbers through either
+    ChaCha(Box<ChaCha12Rng>),
+    /// A generator from `Chip8Builder::with_rng`
+    Custom(Box<dyn RngCore + Send>),
+}
+
+impl RngCore for Generator {
+    fn next_u32(&mut self) -> u32 {
+        match self {
+            Generator::ChaCha(rng) => rng.next_u32(),
+            Generator::Custom(rng) => rng.next_u32(),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match self {
+            Generator::ChaCha(rng) => rng.next_u64(),
+            Generator::Custom(rng) => rng.next_u64(),
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        match self {
+            Generator::ChaCha(rng) => rng.fill_bytes(dest),
+            Generator::Custom(rng) => rng.fill_bytes(dest),
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        match self {
+            Generator::ChaCha(rng) => rng.try_fill_bytes(dest),
+            Generator::Custom(rng) => rng.try_fill_bytes(dest),
+        }
+    }
+}
@@ -0,0 +1,187 @@
+//! Animated GIF recording of the display. Frames are kept in memory while
+//! recording, which is cheap at 256 bytes of state per frame, and encoded
+//! when the recording is finished.
+
+use crate::config::Palette;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Instant;
+
+/// Size of each CHIP-8 pixel in recordings
+const SCALE: usize = 4;
+
+/// GIF delays are in hundredths of a second and most viewers slow down
+/// anything faster than 2, so frame times are rounded to that.
+const DELAY_STEP: u128 = 2;
+
+pub struct Recorder {
+    palette: Palette,
+    start: Instant,
+    frames: Vec<(Vec<bool>, Instant)>,
+}
+
+impl Recorder {
+    pub fn start(display: &[bool], palette: Palette) -> Self {
+        let now = Instant::now();
+        Self {
+            palette,
+            start: now,
+            frames: vec![(display.to_vec(), now)],
+        }
+    }
+
+    /// Adds the display as the next frame if it differs from the last one
+    pub fn capture(&mut self, display: &[bool]) {
+        if self.frames.last().map(|(last, _)| &last[..]) != Some(display) {
+            self.frames.push((display.to_vec(), Instant::now()));
+        }
+    }
+
+    pub fn finish(self, path: &Path) -> io::Result<()> {
+        let end = Instant::now();
+        let centis = |at: Instant| {
+            let centis = at.duration_since(self.start).as_millis() / 10;
+            (centis + DELAY_STEP / 2) / DELAY_STEP * DELAY_STEP
+        };
+
+        let rows = self.frames[0].0.len() / 64;
+        let (width, height) = (64 * SCALE, rows * SCALE);
+        let mut out = BufWriter::new(File::create(path)?);
+        write_header(&mut out, width as u16, height as u16, &self.palette)?;
+        for (i, (display, at)) in self.frames.iter().enumerate() {
+            let next = self.frames.get(i + 1).map_or(end, |(_, at)| *at);
+            let delay = centis(next) - centis(*at);
+            if delay == 0 {
+                // Shown for too short to matter
+                continue;
+            }
+            let mut indices = vec![0; width * height];
+            for (i, index) in indices.iter_mut().enumerate() {
+                let (x, y) = (i % width / SCALE, i / width / SCALE);
+                *index = display[x + 64 * y] as u8;
+            }
+            write_frame(
+                &mut out,
+                width as u16,
+                height as u16,
+                delay.min(u16::MAX as u128) as u16,
+                &indices,
+            )?;
+        }
+        out.write_all(&[0x3B])?;
+        out.flush()
+    }
+}
+
+fn write_header<W: Write>(
+    out: &mut W,
+    width: u16,
+    height: u16,
+    palette: &Palette,
+) -> io::Result<()> {
+    out.write_all(b"GIF89a")?;
+    out.write_all(&width.to_le_bytes())?;
+    out.write_all(&height.to_le_bytes())?;
+    // Global colour table of 4 entries, background colour 0
+    out.write_all(&[0x81, 0, 0])?;
+    out.write_all(&palette.background)?;
+    out.write_all(&palette.foreground)?;
+    out.write_all(&[0; 6])?;
+    // Loop forever
+    out.write_all(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00")
+}
+
+fn write_frame<W: Write>(
+    out: &mut W,
+    width: u16,
+    height: u16,
+    delay: u16,
+    indices: &[u8],
+) -> io::Result<()> {
+    out.write_all(&[0x21, 0xF9, 0x04, 0x00])?;
+    out.write_all(&delay.to_le_bytes())?;
+    out.write_all(&[0x00, 0x00])?;
+
+    out.write_all(&[0x2C, 0, 0, 0, 0])?;
+    out.write_all(&width.to_le_bytes())?;
+    out.write_all(&height.to_le_bytes())?;
+    out.write_all(&[0x00])?;
+
+    out.write_all(&[MIN_CODE_SIZE as u8])?;
+    for block in lzw(indices).chunks(255) {
+        out.write_all(&[block.len() as u8])?;
+        out.write_all(block)?;
+    }
+    out.write_all(&[0x00])
+}
+
+const MIN_CODE_SIZE: u32 = 2;
+
+/// LZW-compresses colour indices below 4 the way GIF expects
+fn lzw(indices: &[u8]) -> Vec<u8> {
+    let clear = 1 << MIN_CODE_SIZE;
+    let mut bits = BitWriter::default();
+    let mut tree = vec![[0u16; 4]; 4096];
+    let mut code_size = MIN_CODE_SIZE + 1;
+    let mut max_code = clear + 1;
+    bits.write(clear, code_size);
+
+    let mut current: Option<u16> = None;
+    for &index in indices {
+        let Some(code) = current else {
+            current = Some(index as u16);
+            continue;
+        };
+        let next = tree[code as usize][index as usize];
+        if next != 0 {
+            current = Some(next);
+            continue;
+        }
+        bits.write(code, code_size);
+        max_code += 1;
+        tree[code as usize][index as usize] = max_code;
+        if max_code >= 1 << code_size {
+            code_size += 1;
+        }
+        if max_code == 4095 {
+            bits.write(clear, code_size);
+            tree.iter_mut().for_each(|node| *node = [0; 4]);
+            code_size = MIN_CODE_SIZE + 1;
+            max_code = clear + 1;
+        }
+        current = Some(index as u16);
+    }
+    if let Some(code) = current {
+        bits.write(code, code_size);
+    }
+    bits.write(clear, code_size);
+    bits.write(clear + 1, MIN_CODE_SIZE + 1);
+    bits.finish()
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    len: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u32) {
+        self.buffer |= (code as u32) << self.len;
+        self.len += size;
+        while self.len >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.len -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.len > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
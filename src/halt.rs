@@ -0,0 +1,72 @@
+//! Spotting programs that have stopped for good, so the emulator can pause
+//! instead of spinning at full speed forever. That is either the usual
+//! end-of-program idiom, a jump to itself, or a long stretch without any
+//! visible effect: no drawing, no memory writes, no running timers and no
+//! looking at the keypad.
+
+use chip8::Chip8;
+
+/// Seconds without visible effects before a program counts as halted
+const QUIET_SECONDS: u32 = 5;
+
+pub struct Watchdog {
+    /// Instructions between checks, about a second's worth
+    interval: u32,
+    executed: u32,
+    quiet_checks: u32,
+    read_keys: bool,
+    memory: Vec<u8>,
+    display_hash: u64,
+}
+
+impl Watchdog {
+    pub fn new(speed: u32) -> Self {
+        Self {
+            interval: speed.max(1),
+            executed: 0,
+            quiet_checks: 0,
+            read_keys: false,
+            memory: Vec::new(),
+            display_hash: 0,
+        }
+    }
+
+    /// Call with each instruction before it runs. Returns whether the
+    /// program has halted.
+    pub fn check(&mut self, chip8: &Chip8, op: u16) -> bool {
+        let address = chip8.program_counter.wrapping_sub(2);
+        if op == 0x1000 | address {
+            return true;
+        }
+        // SKP, SKNP and LD VX, K all wait on the player
+        if op & 0xF000 == 0xE000 || op & 0xF0FF == 0xF00A {
+            self.read_keys = true;
+        }
+
+        self.executed += 1;
+        if self.executed < self.interval {
+            return false;
+        }
+        self.executed = 0;
+        let display_hash = chip8.display_hash();
+        let quiet = !self.read_keys
+            && chip8.delay_timer == 0
+            && chip8.sound_timer == 0
+            && display_hash == self.display_hash
+            && chip8.memory[..] == self.memory[..];
+        if quiet {
+            self.quiet_checks += 1;
+        } else {
+            self.quiet_checks = 0;
+            self.memory = chip8.memory.to_vec();
+            self.display_hash = display_hash;
+        }
+        self.read_keys = false;
+        self.quiet_checks >= QUIET_SECONDS
+    }
+
+    /// Starts over after the user resumes a halted program
+    pub fn reset(&mut self) {
+        self.quiet_checks = 0;
+    }
+}
@@ -0,0 +1,89 @@
+//! Running the core without a window, as fast as it goes, for the modes
+//! that only care about the end result
+
+use chip8::blocks::BlockCache;
+use chip8::{Chip8, Chip8Error};
+
+/// Executes `instructions` instructions, ticking the timers every
+/// `speed / 60` of them as if running at `speed` instructions per second.
+/// `before_step` gets to touch the machine before each instruction.
+pub fn run(
+    chip8: &mut Chip8,
+    instructions: u64,
+    speed: u32,
+    mut before_step: impl FnMut(&mut Chip8),
+) -> Result<(), Chip8Error> {
+    let per_frame = (speed as u64 / 60).max(1);
+    for executed in 0..instructions {
+        before_step(chip8);
+        chip8.step()?;
+        if (executed + 1) % per_frame == 0 {
+            chip8.tick_timers();
+        }
+    }
+    Ok(())
+}
+
+/// Like `run` with nothing to do between instructions, but through a
+/// `BlockCache`, which gets to the same state faster
+pub fn run_blocks(chip8: &mut Chip8, instructions: u64, speed: u32) -> Result<(), Chip8Error> {
+    let per_frame = (speed as u64 / 60).max(1);
+    let mut cache = BlockCache::new();
+    let mut executed = 0;
+    while executed < instructions {
+        let limit = (per_frame - executed % per_frame).min(instructions - executed);
+        cache.run(chip8, limit as usize)?;
+        executed += limit;
+        if executed % per_frame == 0 {
+            chip8.tick_timers();
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What `--seed 3 --hash-after 100000` prints for some of the ROMs in
+    /// `roms`, which should only change with the emulation
+    const HASHES: [(&str, &[u8], u64); 3] = [
+        (
+            "ibm",
+            include_bytes!("../roms/ibm.ch8"),
+            0xC094_F654_22BD_4E58,
+        ),
+        (
+            "maze",
+            include_bytes!("../roms/maze.ch8"),
+            0xA288_0AC1_567A_2095,
+        ),
+        (
+            "pong",
+            include_bytes!("../roms/pong.ch8"),
+            0x097B_D7C0_E1E7_A48A,
+        ),
+    ];
+
+    fn machine(rom: &[u8]) -> Chip8 {
+        Chip8::builder().with_rom(rom).with_seed(3).build().unwrap()
+    }
+
+    #[test]
+    fn roms_hash_to_their_recorded_values() {
+        for (name, rom, hash) in HASHES {
+            let mut chip8 = machine(rom);
+            run(&mut chip8, 100_000, 500, |_| ()).unwrap();
+            assert_eq!(chip8.display_hash(), hash, "{}", name);
+        }
+    }
+
+    #[test]
+    fn blocks_end_up_with_the_same_display() {
+        for (name, rom, hash) in HASHES {
+            let mut chip8 = machine(rom);
+            run_blocks(&mut chip8, 100_000, 500).unwrap();
+            assert_eq!(chip8.display_hash(), hash, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! Memory access counts for `--heatmap`, saved as a picture of memory with
+//! one cell per byte, 64 to a row so each row is 0x40 bytes. Each kind of
+//! use gets its own colour channel, so it shows at a glance which parts of a
+//! ROM are code, which are sprites and which are variables:
+//!
+//! - green: executed
+//! - blue: read by DXYN as sprite data
+//! - red: read or written by FX33, FX55 and FX65
+//!
+//! Brightness goes with the log of the count, relative to the busiest byte
+//! of the same kind, so bytes used once still show up.
+
+use crate::{log, png};
+use chip8::Access;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+/// Screen pixels per byte in the image
+const CELL: usize = 8;
+
+pub struct Heatmap {
+    path: PathBuf,
+    executed: Vec<u64>,
+    sprites: Vec<u64>,
+    data: Vec<u64>,
+}
+
+impl Heatmap {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            executed: vec![0; 4096],
+            sprites: vec![0; 4096],
+            data: vec![0; 4096],
+        }
+    }
+
+    /// Counts the instruction `op` at `address` and the memory it accessed
+    pub fn record(&mut self, address: u16, op: u16, accesses: &[Access]) {
+        for byte in [address, address.wrapping_add(1)] {
+            self.executed[byte as usize % 4096] += 1;
+        }
+        let counts = if op >> 12 == 0xD {
+            &mut self.sprites
+        } else {
+            &mut self.data
+        };
+        for access in accesses {
+            counts[access.address as usize] += 1;
+        }
+    }
+
+    /// Saves the image, saying where it went
+    pub fn save(&self) {
+        match self.write() {
+            Ok(()) => println!("Saved the heatmap to {}", self.path.display()),
+            Err(err) => log::warn!(
+                "Cannot save the heatmap to {}: {}",
+                self.path.display(),
+                err
+            ),
+        }
+    }
+
+    fn write(&self) -> io::Result<()> {
+        let size = 64 * CELL;
+        let channels = [&self.data, &self.executed, &self.sprites].map(|counts| {
+            let max = (*counts.iter().max().unwrap_or(&0) as f64).ln_1p();
+            counts
+                .iter()
+                .map(|&count| match count {
+                    0 => 0,
+                    _ => (64.0 + 191.0 * (count as f64).ln_1p() / max) as u8,
+                })
+                .collect::<Vec<u8>>()
+        });
+        let mut rgb = vec![0; size * size * 3];
+        for (i, pixel) in rgb.chunks_exact_mut(3).enumerate() {
+            let (x, y) = (i % size / CELL, i / size / CELL);
+            for (value, channel) in pixel.iter_mut().zip(&channels) {
+                *value = channel[y * 64 + x];
+            }
+        }
+        let mut out = BufWriter::new(File::create(&self.path)?);
+        png::write(&mut out, size as u32, size as u32, &rgb)
+    }
+}
@@ -0,0 +1,153 @@
+//! The last instructions executed, each with the registers it changed, for
+//! crash dumps and the debugger's `history` command. Each entry keeps the
+//! registers from before the instruction and, once the next one is
+//! recorded, from after it, so recording only ever needs the machine as it
+//! is before an instruction. What changed is only worked out and written
+//! down when the entries are shown, keeping recording, which happens for
+//! every instruction, down to a copy.
+
+use chip8::instruction::Instruction;
+use chip8::Chip8;
+use std::collections::VecDeque;
+
+/// Instructions kept
+const LEN: usize = 64;
+
+/// V0 - VF and I
+#[derive(Clone, Copy, Default)]
+struct Registers {
+    v: [u8; 16],
+    i: u16,
+}
+
+impl Registers {
+    fn of(chip8: &Chip8) -> Self {
+        Self {
+            v: chip8.registers,
+            i: chip8.index,
+        }
+    }
+}
+
+struct Entry {
+    address: u16,
+    op: u16,
+    before: Registers,
+    after: Registers,
+}
+
+impl Entry {
+    /// What the instruction changed, as register name, old and new value
+    fn changes(&self) -> impl Iterator<Item = String> + '_ {
+        let v = self
+            .before
+            .v
+            .iter()
+            .zip(&self.after.v)
+            .enumerate()
+            .filter(|(_, (old, new))| old != new)
+            .map(|(x, (old, new))| format!("V{:X} {:02X}->{:02X}", x, old, new));
+        let i = (self.before.i != self.after.i)
+            .then(|| format!("I {:02X}->{:02X}", self.before.i, self.after.i));
+        v.chain(i)
+    }
+}
+
+#[derive(Default)]
+pub struct History {
+    entries: VecDeque<Entry>,
+    /// Whether the newest entry still needs the registers after it
+    pending: bool,
+}
+
+impl History {
+    /// Records the instruction about to run at `address`
+    pub fn record(&mut self, address: u16, op: u16, chip8: &Chip8) {
+        self.settle(chip8);
+        if self.entries.len() == LEN {
+            self.entries.pop_front();
+        }
+        let registers = Registers::of(chip8);
+        self.entries.push_back(Entry {
+            address,
+            op,
+            before: registers,
+            after: registers,
+        });
+        self.pending = true;
+    }
+
+    /// Fills in the registers after the newest entry, given the machine
+    /// after it
+    pub fn settle(&mut self, chip8: &Chip8) {
+        if let Some(entry) = self.entries.back_mut().filter(|_| self.pending) {
+            entry.after = Registers::of(chip8);
+            self.pending = false;
+        }
+    }
+
+    /// The last `count` entries, oldest first, one line each
+    pub fn lines(&self, count: usize) -> impl Iterator<Item = String> + '_ {
+        let skip = self.entries.len().saturating_sub(count);
+        self.entries.iter().skip(skip).map(|entry| {
+            let text = Instruction::decode(entry.op).map_or("unknown".into(), |op| op.to_string());
+            let changes: Vec<String> = entry.changes().collect();
+            let line = format!(
+                "{:03X}  {:04X}  {:<16} {}",
+                entry.address,
+                entry.op,
+                text,
+                changes.join(" ")
+            );
+            line.trim_end().to_string()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The history of running `rom` for `steps` instructions
+    fn run(rom: &[u8], steps: usize) -> History {
+        let mut chip8 = Chip8::builder().with_program(rom).build().unwrap();
+        let mut history = History::default();
+        for _ in 0..steps {
+            let address = chip8.program_counter;
+            let op = chip8.fetch().unwrap();
+            history.record(address, op, &chip8);
+            chip8.execute(op).unwrap();
+        }
+        history.settle(&chip8);
+        history
+    }
+
+    #[test]
+    fn lines_show_what_each_instruction_changed() {
+        // V3 = 12, I = 345, then clear the screen
+        let history = run(&[0x63, 0x12, 0xA3, 0x45, 0x00, 0xE0], 3);
+        let lines: Vec<String> = history.lines(usize::MAX).collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("200  6312"));
+        assert!(lines[0].ends_with("V3 00->12"), "{}", lines[0]);
+        assert!(lines[1].ends_with("I 00->345"), "{}", lines[1]);
+        assert!(lines[2].starts_with("204  00E0"));
+        assert!(!lines[2].contains("->"), "{}", lines[2]);
+    }
+
+    #[test]
+    fn only_the_last_entries_are_kept() {
+        // ADD V0, 1 forever
+        let history = run(&[0x70, 0x01, 0x12, 0x00], LEN * 3);
+        let lines: Vec<String> = history.lines(usize::MAX).collect();
+        assert_eq!(lines.len(), LEN);
+        assert_eq!(history.lines(2).count(), 2);
+        // The newest ADD took V0 from 95 to 96
+        let last_add = lines
+            .iter()
+            .rev()
+            .find(|line| line.contains("7001"))
+            .unwrap();
+        assert!(last_add.ends_with("V0 5F->60"), "{}", last_add);
+    }
+}
@@ -0,0 +1,54 @@
+//! Callbacks the core makes as things happen, so frontends and scripts
+//! don't have to poll the whole machine to notice changes. Each is given
+//! the machine as it is at that point.
+
+use crate::instruction::Instruction;
+use crate::{Chip8, Chip8Error};
+
+/// A callback with nothing to report but the machine
+pub type Hook<B = [u8; 4096]> = Option<Box<dyn FnMut(&Chip8<B>) + Send>>;
+/// A callback with the instruction about to be carried out
+pub type InstructionHook<B = [u8; 4096]> = Option<Box<dyn FnMut(&Chip8<B>, Instruction) + Send>>;
+/// A callback with the error that stopped the machine
+pub type HaltHook<B = [u8; 4096]> = Option<Box<dyn FnMut(&Chip8<B>, &Chip8Error) + Send>>;
+
+pub struct Hooks<B = [u8; 4096]> {
+    /// After an instruction or `Chip8::reset` changes the display
+    pub on_display_update: Hook<B>,
+    /// When the sound timer is set from zero, starting the tone
+    pub on_sound_start: Hook<B>,
+    /// When the sound timer reaches zero, stopping the tone
+    pub on_sound_stop: Hook<B>,
+    /// Before each instruction is carried out, with the program counter
+    /// already past it
+    pub on_instruction: InstructionHook<B>,
+    /// When fetching or executing an instruction fails, which stops the
+    /// machine
+    pub on_halt: HaltHook<B>,
+}
+
+// Derived, it would need `B: Default`, which 4K arrays aren't
+impl<B> Default for Hooks<B> {
+    fn default() -> Self {
+        Self {
+            on_display_update: None,
+            on_sound_start: None,
+            on_sound_stop: None,
+            on_instruction: None,
+            on_halt: None,
+        }
+    }
+}
+
+/// Calls `self.hooks.$name` if it is set, with the machine and `$args`.
+/// The hook is taken out for the call, so it can be given `&Chip8`.
+macro_rules! call {
+    ($self:ident.$name:ident($($arg:expr),*)) => {
+        if let Some(mut hook) = $self.hooks.$name.take() {
+            hook($self, $($arg),*);
+            $self.hooks.$name = Some(hook);
+        }
+    };
+}
+
+pub(crate) use call;
@@ -0,0 +1,75 @@
+//! `chip8 info`: what can be told about a ROM without running it
+
+use crate::{png, sha1};
+use chip8::disasm;
+use chip8::instruction::{Extension, Instruction};
+use chip8::{rom, Chip8Error, PC_START};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+#[derive(clap::Args)]
+pub struct InfoArgs {
+    /// ROMs to describe
+    #[arg(required = true)]
+    roms: Vec<PathBuf>,
+}
+
+pub fn run(args: InfoArgs) -> Result<(), Chip8Error> {
+    for (i, path) in args.roms.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        let data = rom::read(path)?;
+        println!("File          {}", path.display());
+        println!("Size          {} bytes", data.len());
+        println!("CRC32         {:08x}", png::crc32(&data));
+        println!("SHA-1         {}", sha1::hex(&sha1::sha1(&data)));
+        if data.len() < 2 {
+            continue;
+        }
+
+        let entry = u16::from_be_bytes([data[0], data[1]]);
+        let text = Instruction::decode(entry).map_or("unknown".to_string(), |op| op.to_string());
+        println!("Entry         {:04X}  {}", entry, text);
+
+        let start = if rom::is_hires(&data) {
+            println!("Display       64x64 two-page hires");
+            chip8::HIRES_START
+        } else {
+            PC_START
+        };
+        let mut histogram: BTreeMap<&str, usize> = BTreeMap::new();
+        let mut extensions: BTreeMap<Extension, BTreeMap<String, usize>> = BTreeMap::new();
+        let mut unknown = 0;
+        for address in disasm::reachable(&data, PC_START, start) {
+            let offset = (address - PC_START) as usize;
+            let op = u16::from_be_bytes([data[offset], data[offset + 1]]);
+            match Instruction::decode(op) {
+                Some(instruction) => {
+                    *histogram.entry(instruction.mnemonic()).or_default() += 1;
+                    let used = extensions.entry(instruction.extension()).or_default();
+                    *used.entry(instruction.to_string()).or_default() += 1;
+                }
+                None => unknown += 1,
+            }
+        }
+        for (extension, used) in &extensions {
+            if *extension == Extension::Chip8 {
+                continue;
+            }
+            let ops: Vec<&str> = used.keys().map(String::as_str).collect();
+            println!("{:13} {}", extension.name(), ops.join(", "));
+        }
+        if unknown > 0 {
+            println!("Unknown       {} opcodes", unknown);
+        }
+
+        println!("Instructions reachable from the entry point:");
+        let mut histogram: Vec<_> = histogram.into_iter().collect();
+        histogram.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        for (mnemonic, count) in histogram {
+            println!("  {:6} {}", mnemonic, count);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,83 @@
+//! Key presses and releases queued with the time they happened. Each frame
+//! replays the ones that came in during the last frame at the same point in
+//! its run of instructions, instead of all at once before it, so a tap
+//! shorter than a frame is still seen by the program.
+
+use chip8::Chip8;
+use std::collections::VecDeque;
+use std::time::Instant;
+
+struct KeyEvent {
+    key: usize,
+    down: bool,
+    /// How far into the frame to apply it, from 0 to 1
+    position: f64,
+}
+
+pub struct KeyQueue {
+    arrived: Vec<(Instant, usize, bool)>,
+    due: VecDeque<KeyEvent>,
+    frame_start: Instant,
+}
+
+impl KeyQueue {
+    pub fn new() -> Self {
+        Self {
+            arrived: Vec::new(),
+            due: VecDeque::new(),
+            frame_start: Instant::now(),
+        }
+    }
+
+    pub fn push(&mut self, time: Instant, key: usize, down: bool) {
+        self.arrived.push((time, key, down));
+    }
+
+    /// Starts a frame, which replays everything pushed since the last one
+    pub fn start_frame(&mut self, chip8: &mut Chip8) {
+        // Whatever the last frame didn't get to goes in first
+        self.finish_frame(chip8);
+        let now = Instant::now();
+        let span = now.duration_since(self.frame_start).as_secs_f64();
+        self.arrived.sort_by_key(|&(time, _, _)| time);
+        for (time, key, down) in self.arrived.drain(..) {
+            let offset = time
+                .saturating_duration_since(self.frame_start)
+                .as_secs_f64();
+            self.due.push_back(KeyEvent {
+                key,
+                down,
+                position: if span > 0.0 { offset / span } else { 0.0 },
+            });
+        }
+        self.frame_start = now;
+    }
+
+    /// Applies the events due by `position` through the frame
+    pub fn apply(&mut self, chip8: &mut Chip8, position: f64) {
+        while self
+            .due
+            .front()
+            .is_some_and(|event| event.position <= position)
+        {
+            self.apply_next(chip8);
+        }
+    }
+
+    /// Applies the next event early, returning whether there was one. This
+    /// is for FX0A, which would otherwise wait for the next frame.
+    pub fn apply_next(&mut self, chip8: &mut Chip8) -> bool {
+        match self.due.pop_front() {
+            Some(event) => {
+                chip8.keys[event.key] = event.down;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Applies the rest of the frame's events
+    pub fn finish_frame(&mut self, chip8: &mut Chip8) {
+        while self.apply_next(chip8) {}
+    }
+}
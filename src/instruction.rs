@@ -0,0 +1,329 @@
+//! Opcodes decoded into typed instructions, shared by the interpreter, the
+//! disassembler and the tools built on them, so they can't disagree about
+//! what an opcode means. Mnemonics follow Cowgod's technical reference,
+//! with the SUPER-CHIP and XO-CHIP additions in the same style.
+
+use std::fmt;
+
+/// The instruction set an opcode first appeared in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Extension {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl Extension {
+    pub fn name(self) -> &'static str {
+        match self {
+            Extension::Chip8 => "CHIP-8",
+            Extension::SuperChip => "SUPER-CHIP",
+            Extension::XoChip => "XO-CHIP",
+        }
+    }
+}
+
+/// A decoded opcode. Registers are given by number, `x` and `y` as in the
+/// opcode's `VX` and `VY`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    /// 00E0
+    Clear,
+    /// 00EE
+    Return,
+    /// 0NNN, machine code on the original interpreters
+    System(u16),
+    /// 00CN
+    ScrollDown(u8),
+    /// 00DN
+    ScrollUp(u8),
+    /// 00FB
+    ScrollRight,
+    /// 00FC
+    ScrollLeft,
+    /// 00FD
+    Exit,
+    /// 00FE
+    LowRes,
+    /// 00FF
+    HighRes,
+    /// 1NNN
+    Jump(u16),
+    /// 2NNN
+    Call(u16),
+    /// 3XNN
+    SkipIfEqual { x: usize, value: u8 },
+    /// 4XNN
+    SkipIfNotEqual { x: usize, value: u8 },
+    /// 5XY0
+    SkipIfRegistersEqual { x: usize, y: usize },
+    /// 5XY2
+    SaveRange { x: usize, y: usize },
+    /// 5XY3
+    LoadRange { x: usize, y: usize },
+    /// 6XNN
+    Load { x: usize, value: u8 },
+    /// 7XNN
+    Add { x: usize, value: u8 },
+    /// 8XY0
+    Move { x: usize, y: usize },
+    /// 8XY1
+    Or { x: usize, y: usize },
+    /// 8XY2
+    And { x: usize, y: usize },
+    /// 8XY3
+    Xor { x: usize, y: usize },
+    /// 8XY4
+    AddRegisters { x: usize, y: usize },
+    /// 8XY5
+    Subtract { x: usize, y: usize },
+    /// 8XY6
+    ShiftRight { x: usize, y: usize },
+    /// 8XY7
+    SubtractReversed { x: usize, y: usize },
+    /// 8XYE
+    ShiftLeft { x: usize, y: usize },
+    /// 9XY0
+    SkipIfRegistersNotEqual { x: usize, y: usize },
+    /// ANNN
+    LoadIndex(u16),
+    /// BNNN
+    JumpOffset(u16),
+    /// CXNN
+    Random { x: usize, mask: u8 },
+    /// DXYN
+    Draw { x: usize, y: usize, rows: u8 },
+    /// EX9E
+    SkipIfKey(usize),
+    /// EXA1
+    SkipIfNotKey(usize),
+    /// F000, followed by a 16-bit address
+    LoadLongIndex,
+    /// FN01
+    Plane(u8),
+    /// F002
+    Audio,
+    /// FX07
+    GetDelay(usize),
+    /// FX0A
+    WaitKey(usize),
+    /// FX15
+    SetDelay(usize),
+    /// FX18
+    SetSound(usize),
+    /// FX1E
+    AddIndex(usize),
+    /// FX29
+    Font(usize),
+    /// FX30
+    BigFont(usize),
+    /// FX33
+    Bcd(usize),
+    /// FX3A
+    Pitch(usize),
+    /// FX55
+    Store(usize),
+    /// FX65
+    Restore(usize),
+    /// FX75, up to V7 on SUPER-CHIP and VF on XO-CHIP
+    SaveFlags(usize),
+    /// FX85
+    LoadFlags(usize),
+}
+
+impl Instruction {
+    /// Decodes `op`, or returns `None` if no known instruction set has it
+    pub fn decode(op: u16) -> Option<Instruction> {
+        use Instruction::*;
+        let x = ((op >> 8) & 0xF) as usize;
+        let y = ((op >> 4) & 0xF) as usize;
+        let n = (op & 0xF) as u8;
+        let nn = (op & 0xFF) as u8;
+        let nnn = op & 0xFFF;
+        Some(match (op >> 12, x, y, n) {
+            (0, 0, 0xE, 0) => Clear,
+            (0, 0, 0xE, 0xE) => Return,
+            (0, 0, 0xC, _) => ScrollDown(n),
+            (0, 0, 0xD, _) => ScrollUp(n),
+            (0, 0, 0xF, 0xB) => ScrollRight,
+            (0, 0, 0xF, 0xC) => ScrollLeft,
+            (0, 0, 0xF, 0xD) => Exit,
+            (0, 0, 0xF, 0xE) => LowRes,
+            (0, 0, 0xF, 0xF) => HighRes,
+            (0, _, _, _) => System(nnn),
+            (1, _, _, _) => Jump(nnn),
+            (2, _, _, _) => Call(nnn),
+            (3, _, _, _) => SkipIfEqual { x, value: nn },
+            (4, _, _, _) => SkipIfNotEqual { x, value: nn },
+            (5, _, _, 0) => SkipIfRegistersEqual { x, y },
+            (5, _, _, 2) => SaveRange { x, y },
+            (5, _, _, 3) => LoadRange { x, y },
+            (6, _, _, _) => Load { x, value: nn },
+            (7, _, _, _) => Add { x, value: nn },
+            (8, _, _, 0) => Move { x, y },
+            (8, _, _, 1) => Or { x, y },
+            (8, _, _, 2) => And { x, y },
+            (8, _, _, 3) => Xor { x, y },
+            (8, _, _, 4) => AddRegisters { x, y },
+            (8, _, _, 5) => Subtract { x, y },
+            (8, _, _, 6) => ShiftRight { x, y },
+            (8, _, _, 7) => SubtractReversed { x, y },
+            (8, _, _, 0xE) => ShiftLeft { x, y },
+            (9, _, _, 0) => SkipIfRegistersNotEqual { x, y },
+            (0xA, _, _, _) => LoadIndex(nnn),
+            (0xB, _, _, _) => JumpOffset(nnn),
+            (0xC, _, _, _) => Random { x, mask: nn },
+            (0xD, _, _, _) => Draw { x, y, rows: n },
+            (0xE, _, 9, 0xE) => SkipIfKey(x),
+            (0xE, _, 0xA, 1) => SkipIfNotKey(x),
+            (0xF, 0, 0, 0) => LoadLongIndex,
+            (0xF, _, 0, 1) => Plane(x as u8),
+            (0xF, 0, 0, 2) => Audio,
+            (0xF, _, 0, 7) => GetDelay(x),
+            (0xF, _, 0, 0xA) => WaitKey(x),
+            (0xF, _, 1, 5) => SetDelay(x),
+            (0xF, _, 1, 8) => SetSound(x),
+            (0xF, _, 1, 0xE) => AddIndex(x),
+            (0xF, _, 2, 9) => Font(x),
+            (0xF, _, 3, 0) => BigFont(x),
+            (0xF, _, 3, 3) => Bcd(x),
+            (0xF, _, 3, 0xA) => Pitch(x),
+            (0xF, _, 5, 5) => Store(x),
+            (0xF, _, 6, 5) => Restore(x),
+            (0xF, _, 7, 5) => SaveFlags(x),
+            (0xF, _, 8, 5) => LoadFlags(x),
+            _ => return None,
+        })
+    }
+
+    pub fn extension(self) -> Extension {
+        use Instruction::*;
+        match self {
+            ScrollDown(_) | ScrollRight | ScrollLeft | Exit | LowRes | HighRes | BigFont(_) => {
+                Extension::SuperChip
+            }
+            SaveFlags(x) | LoadFlags(x) if x <= 7 => Extension::SuperChip,
+            ScrollUp(_)
+            | SaveRange { .. }
+            | LoadRange { .. }
+            | LoadLongIndex
+            | Plane(_)
+            | Audio
+            | Pitch(_)
+            | SaveFlags(_)
+            | LoadFlags(_) => Extension::XoChip,
+            _ => Extension::Chip8,
+        }
+    }
+
+    /// Name without operands, e.g. `LD`
+    pub fn mnemonic(self) -> &'static str {
+        use Instruction::*;
+        match self {
+            Clear => "CLS",
+            Return => "RET",
+            System(_) => "SYS",
+            ScrollDown(_) => "SCD",
+            ScrollUp(_) => "SCU",
+            ScrollRight => "SCR",
+            ScrollLeft => "SCL",
+            Exit => "EXIT",
+            LowRes => "LOW",
+            HighRes => "HIGH",
+            Jump(_) | JumpOffset(_) => "JP",
+            Call(_) => "CALL",
+            SkipIfEqual { .. } | SkipIfRegistersEqual { .. } => "SE",
+            SkipIfNotEqual { .. } | SkipIfRegistersNotEqual { .. } => "SNE",
+            SaveRange { .. } => "SAVE",
+            LoadRange { .. } => "LOAD",
+            Add { .. } | AddRegisters { .. } | AddIndex(_) => "ADD",
+            Or { .. } => "OR",
+            And { .. } => "AND",
+            Xor { .. } => "XOR",
+            Subtract { .. } => "SUB",
+            ShiftRight { .. } => "SHR",
+            SubtractReversed { .. } => "SUBN",
+            ShiftLeft { .. } => "SHL",
+            Random { .. } => "RND",
+            Draw { .. } => "DRW",
+            SkipIfKey(_) => "SKP",
+            SkipIfNotKey(_) => "SKNP",
+            Plane(_) => "PLANE",
+            Audio => "AUDIO",
+            Pitch(_) => "PITCH",
+            Load { .. }
+            | Move { .. }
+            | LoadIndex(_)
+            | LoadLongIndex
+            | GetDelay(_)
+            | WaitKey(_)
+            | SetDelay(_)
+            | SetSound(_)
+            | Font(_)
+            | BigFont(_)
+            | Bcd(_)
+            | Store(_)
+            | Restore(_)
+            | SaveFlags(_)
+            | LoadFlags(_) => "LD",
+        }
+    }
+
+    /// The address the instruction works on, for JP, CALL, LD I and JP V0
+    pub fn address(self) -> Option<u16> {
+        match self {
+            Instruction::Jump(address)
+            | Instruction::Call(address)
+            | Instruction::LoadIndex(address)
+            | Instruction::JumpOffset(address) => Some(address),
+            _ => None,
+        }
+    }
+}
+
+/// The full text, e.g. `LD V3, 1F`
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Instruction::*;
+        write!(f, "{}", self.mnemonic())?;
+        match *self {
+            Clear | Return | ScrollRight | ScrollLeft | Exit | LowRes | HighRes | Audio => Ok(()),
+            System(address) | Jump(address) | Call(address) => write!(f, " {:03X}", address),
+            ScrollDown(n) | ScrollUp(n) | Plane(n) => write!(f, " {:X}", n),
+            SkipIfEqual { x, value }
+            | SkipIfNotEqual { x, value }
+            | Load { x, value }
+            | Add { x, value } => write!(f, " V{:X}, {:02X}", x, value),
+            Random { x, mask } => write!(f, " V{:X}, {:02X}", x, mask),
+            SaveRange { x, y } | LoadRange { x, y } => write!(f, " V{:X} - V{:X}", x, y),
+            SkipIfRegistersEqual { x, y }
+            | Move { x, y }
+            | Or { x, y }
+            | And { x, y }
+            | Xor { x, y }
+            | AddRegisters { x, y }
+            | Subtract { x, y }
+            | ShiftRight { x, y }
+            | SubtractReversed { x, y }
+            | ShiftLeft { x, y }
+            | SkipIfRegistersNotEqual { x, y } => write!(f, " V{:X}, V{:X}", x, y),
+            LoadIndex(address) => write!(f, " I, {:03X}", address),
+            JumpOffset(address) => write!(f, " V0, {:03X}", address),
+            Draw { x, y, rows } => write!(f, " V{:X}, V{:X}, {:X}", x, y, rows),
+            SkipIfKey(x) | SkipIfNotKey(x) | Pitch(x) => write!(f, " V{:X}", x),
+            LoadLongIndex => write!(f, " I, long"),
+            GetDelay(x) => write!(f, " V{:X}, DT", x),
+            WaitKey(x) => write!(f, " V{:X}, K", x),
+            SetDelay(x) => write!(f, " DT, V{:X}", x),
+            SetSound(x) => write!(f, " ST, V{:X}", x),
+            AddIndex(x) => write!(f, " I, V{:X}", x),
+            Font(x) => write!(f, " F, V{:X}", x),
+            BigFont(x) => write!(f, " HF, V{:X}", x),
+            Bcd(x) => write!(f, " B, V{:X}", x),
+            Store(x) => write!(f, " [I], V{:X}", x),
+            Restore(x) => write!(f, " V{:X}, [I]", x),
+            SaveFlags(x) => write!(f, " R, V{:X}", x),
+            LoadFlags(x) => write!(f, " V{:X}, R", x),
+        }
+    }
+}
@@ -0,0 +1,69 @@
+//! Keypad state written by another process, so a hardware keypad bridge
+//! (e.g. a microcontroller scanning a 4x4 membrane keypad) can drive the
+//! emulator without going through SDL.
+//!
+//! Every line holds the held keys as a 16-bit hex mask where bit N is key N,
+//! so `0012` means keys 1 and 4 are down. A named pipe is applied line by
+//! line as lines arrive; a regular file is polled and its last line applied.
+
+use crate::log;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Starts following `path`, returning the key presses and releases it makes
+/// as when, which key and whether it is now down
+pub fn spawn(path: String) -> Receiver<(Instant, usize, bool)> {
+    let (keys, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut held = 0;
+        loop {
+            let file = match File::open(&path) {
+                Ok(file) => file,
+                Err(err) => {
+                    log::warn!("Cannot read keypad file {}: {}", path, err);
+                    return;
+                }
+            };
+            let polled = file.metadata().is_ok_and(|meta| meta.is_file());
+            let mut last = None;
+            for line in BufReader::new(file).lines() {
+                let Ok(line) = line else { break };
+                let Ok(mask) = u16::from_str_radix(line.trim(), 16) else {
+                    continue;
+                };
+                if polled {
+                    last = Some(mask);
+                } else {
+                    apply(&keys, &mut held, mask);
+                }
+            }
+            if polled {
+                if let Some(mask) = last {
+                    apply(&keys, &mut held, mask);
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+            // A pipe hits EOF when its writer goes away; opening it again
+            // waits for the next one.
+        }
+    });
+    receiver
+}
+
+/// Sends only the keys whose state changed since the last mask, so keys
+/// held on the real keyboard are left alone.
+fn apply(keys: &Sender<(Instant, usize, bool)>, held: &mut u16, mask: u16) {
+    let changed = *held ^ mask;
+    let now = Instant::now();
+    for key in 0..16 {
+        if changed & (1 << key) != 0 {
+            let _ = keys.send((now, key, mask & (1 << key) != 0));
+        }
+    }
+    *held = mask;
+}
@@ -0,0 +1,1005 @@
+use instruction::{Extension, Instruction};
+use rand::{Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use std::fmt;
+use std::str::FromStr;
+
+pub mod blocks;
+mod bus;
+pub mod disasm;
+mod error;
+mod framebuffer;
+mod generator;
+mod hooks;
+pub mod instruction;
+pub mod rom;
+mod state;
+pub mod testing;
+
+pub use bus::Bus;
+pub use error::Chip8Error;
+pub use framebuffer::Framebuffer;
+pub use generator::Generator;
+pub use hooks::{HaltHook, Hook, Hooks, InstructionHook};
+
+pub const PC_START: u16 = 0x200;
+/// Entry point of two-page hires programs, past the interpreter patch the
+/// COSMAC VIP loaded at 0x260
+pub const HIRES_START: u16 = 0x2C0;
+/// Where the 10-byte digits of a big font go, right after `SPRITES`
+pub const BIG_FONT_START: u16 = 0x50;
+pub const SPRITES: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+/// What happens when an instruction reaches past the end of memory
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MemoryPolicy {
+    /// Addresses wrap around to the start of memory
+    Wrap,
+    /// Execution stops with [`Chip8Error::MemoryOutOfBounds`]
+    Trap,
+}
+
+/// `wrap` or `trap`, as config files spell it
+impl fmt::Display for MemoryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            MemoryPolicy::Wrap => "wrap",
+            MemoryPolicy::Trap => "trap",
+        })
+    }
+}
+
+impl FromStr for MemoryPolicy {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "wrap" => Ok(MemoryPolicy::Wrap),
+            "trap" => Ok(MemoryPolicy::Trap),
+            _ => Err(format!("no memory policy `{}`", name)),
+        }
+    }
+}
+
+/// A memory access made by an instruction, as recorded in
+/// [`Chip8::accesses`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Access {
+    pub address: u16,
+    pub write: bool,
+}
+
+/// Where CXNN gets its random numbers
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RandomSource {
+    /// A modern generator with no visible patterns
+    Modern,
+    /// A 16-bit linear feedback shift register, which like the generator in
+    /// the COSMAC VIP interpreter gives a short, correlated sequence
+    Lfsr,
+}
+
+/// `modern` or `lfsr`, as config files spell it
+impl fmt::Display for RandomSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            RandomSource::Modern => "modern",
+            RandomSource::Lfsr => "lfsr",
+        })
+    }
+}
+
+impl FromStr for RandomSource {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "modern" => Ok(RandomSource::Modern),
+            "lfsr" => Ok(RandomSource::Lfsr),
+            _ => Err(format!("no random source `{}`", name)),
+        }
+    }
+}
+
+/// Behaviours that differ between CHIP-8 implementations and that some ROMs
+/// depend on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quirks {
+    /// FX0A waits for a key to be pressed and released, like the COSMAC VIP,
+    /// rather than finishing as soon as any key is held
+    pub wait_for_release: bool,
+    /// DXYN waits for the next 60 Hz tick before drawing, like the COSMAC
+    /// VIP waiting for vertical blank, so at most one sprite per row of them
+    /// is drawn each frame
+    pub display_wait: bool,
+    pub random: RandomSource,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self {
+            wait_for_release: true,
+            display_wait: false,
+            random: RandomSource::Modern,
+        }
+    }
+}
+
+/// What `Chip8::run` and `Chip8::run_frame` did
+#[derive(Debug, Default)]
+pub struct Summary {
+    /// Instructions executed, not counting one that failed
+    pub instructions: u64,
+    /// Whether the display ended up different
+    pub drew: bool,
+    /// Whether the sound timer was running at any point
+    pub beeped: bool,
+    /// Whether it finished waiting in FX0A for a key
+    pub waiting_for_key: bool,
+    /// The error that stopped it early
+    pub halted: Option<Chip8Error>,
+}
+
+/// The machine, with memory on a `Bus` that is flat 4K unless given another
+pub struct Chip8<B = [u8; 4096]> {
+    pub memory: B,
+    pub stack: [u16; 16],
+    pub registers: [u8; 16],
+    /// SUPER-CHIP flag registers, which FX75 and FX85 copy V0 - V7 to and from
+    pub flags: [u8; 8],
+    /// Set by FX75 so the frontend knows to save the flags
+    pub flags_dirty: bool,
+    pub program_counter: u16,
+    pub stack_pointer: u16,
+    pub index: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    /// Rows of 64 pixels with the leftmost pixel in the top bit, with only
+    /// the top 32 rows in use outside hires mode
+    pub display: [u64; 64],
+    /// Two-page hires mode with a 64x64 display
+    pub hires: bool,
+    pub display_dirty: bool,
+    pub keys: [bool; 16],
+    pub instructions: u64,
+    /// Source for CXNN, ChaCha seeded from the OS unless a seed or another
+    /// generator is given
+    pub rng: Generator,
+    /// Shift register state for `RandomSource::Lfsr`, never zero
+    pub lfsr: u16,
+    pub memory_policy: MemoryPolicy,
+    pub quirks: Quirks,
+    /// Instructions per second that `run_frame` runs at
+    pub speed: u32,
+    /// The program as it was loaded, which `reset` loads again
+    pub rom: Vec<u8>,
+    /// Where `rom` was loaded and started from
+    pub load_address: u16,
+    /// Key that FX0A saw pressed and is waiting to be released
+    pub waiting_key: Option<u8>,
+    /// Whether the last instruction was an FX0A that is still waiting, so
+    /// nothing will happen until the keys change
+    pub blocked_on_key: bool,
+    /// Whether a 60 Hz tick has happened since the last DXYN, for
+    /// `Quirks::display_wait`
+    pub vblank: bool,
+    /// Memory read and written by instructions, recorded only while this is
+    /// `Some`, for debugger watchpoints. Fetching isn't included.
+    pub accesses: Option<Vec<Access>>,
+    pub hooks: Hooks<B>,
+}
+
+impl Chip8 {
+    pub fn builder() -> Chip8Builder {
+        Chip8Builder::new()
+    }
+
+    /// Goes back to the power-on state with `rom` loaded again, keeping the
+    /// font, the SUPER-CHIP flags and the settings: the memory policy,
+    /// quirks, speed and random number generator
+    pub fn reset(&mut self) {
+        let mut builder = Chip8Builder::new()
+            .with_font(&self.memory[..BIG_FONT_START as usize + 160])
+            .with_program_at(self.load_address, &self.rom)
+            .with_flags(self.flags)
+            .with_memory_policy(self.memory_policy)
+            .with_quirks(self.quirks)
+            .with_speed(self.speed);
+        if self.hires {
+            builder = builder.with_hires();
+        }
+        let mut power_on = builder.chip8;
+        std::mem::swap(&mut power_on.rng, &mut self.rng);
+        power_on.lfsr = self.lfsr;
+        power_on.accesses = self.accesses.as_ref().map(|_| Vec::new());
+        power_on.hooks = std::mem::take(&mut self.hooks);
+        let playing = self.sound_timer > 0;
+        *self = power_on;
+        if playing {
+            hooks::call!(self.on_sound_stop());
+        }
+        self.display_changed();
+    }
+
+    /// Resets with `data` as the program instead, at `PC_START` and in hires
+    /// mode if it starts like a two-page hires program. The machine is left
+    /// alone if it isn't a ROM that fits in memory.
+    pub fn load_rom(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        rom::validate(data, PC_START)?;
+        self.rom = data.to_vec();
+        self.load_address = PC_START;
+        self.hires = rom::is_hires(data);
+        self.reset();
+        Ok(())
+    }
+
+    /// Switches to `bus` for memory, after copying what's in memory so far
+    /// into it at the same addresses. `hooks` are left behind, as they are
+    /// for a machine with flat memory.
+    pub fn with_bus<B: Bus>(self, mut bus: B) -> Chip8<B> {
+        for (address, &byte) in self.memory.iter().enumerate().take(bus.size()) {
+            bus.write(address, byte);
+        }
+        Chip8 {
+            memory: bus,
+            stack: self.stack,
+            registers: self.registers,
+            flags: self.flags,
+            flags_dirty: self.flags_dirty,
+            program_counter: self.program_counter,
+            stack_pointer: self.stack_pointer,
+            index: self.index,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            display: self.display,
+            hires: self.hires,
+            display_dirty: self.display_dirty,
+            keys: self.keys,
+            instructions: self.instructions,
+            rng: self.rng,
+            lfsr: self.lfsr,
+            memory_policy: self.memory_policy,
+            quirks: self.quirks,
+            speed: self.speed,
+            rom: self.rom,
+            load_address: self.load_address,
+            waiting_key: self.waiting_key,
+            blocked_on_key: self.blocked_on_key,
+            vblank: self.vblank,
+            accesses: self.accesses,
+            hooks: Hooks::default(),
+        }
+    }
+}
+
+impl<B: Bus> Chip8<B> {
+    fn push(&mut self, value: u16) -> Result<(), Chip8Error> {
+        if self.stack_pointer as usize == self.stack.len() {
+            return Err(Chip8Error::StackOverflow {
+                address: self.program_counter.wrapping_sub(2),
+                stack: self.stack.to_vec(),
+            });
+        }
+        self.stack[self.stack_pointer as usize] = value;
+        self.stack_pointer += 1;
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<u16, Chip8Error> {
+        if self.stack_pointer == 0 {
+            return Err(Chip8Error::StackUnderflow {
+                address: self.program_counter.wrapping_sub(2),
+            });
+        }
+        self.stack_pointer -= 1;
+        Ok(self.stack[self.stack_pointer as usize])
+    }
+
+    /// Number of display rows in use
+    pub fn height(&self) -> usize {
+        if self.hires {
+            64
+        } else {
+            32
+        }
+    }
+
+    /// The rows of `display` in use
+    pub fn rows(&self) -> &[u64] {
+        &self.display[..self.height()]
+    }
+
+    /// The visible display, to read pixels from or copy out
+    pub fn display(&self) -> Framebuffer<'_> {
+        Framebuffer::new(self.rows())
+    }
+
+    /// Whether the pixel at (`x`, `y`) is lit, or `None` off the display
+    pub fn pixel(&self, x: usize, y: usize) -> Option<bool> {
+        self.display().pixel(x, y)
+    }
+
+    /// The visible display a pixel at a time, row by row, for frontends
+    pub fn screen(&self) -> Vec<bool> {
+        self.display().pixels().map(|(_, _, lit)| lit).collect()
+    }
+
+    /// Byte at `address`, which wraps around the end of memory, for tools
+    /// that watch the program from outside
+    pub fn peek(&self, address: u16) -> u8 {
+        self.memory.read(address as usize % self.memory.size())
+    }
+
+    /// Changes the byte at `address` from outside the program, e.g. for
+    /// cheats
+    pub fn poke(&mut self, address: u16, value: u8) {
+        let size = self.memory.size();
+        self.memory.write(address as usize % size, value);
+    }
+
+    fn random(&mut self) -> u8 {
+        match self.quirks.random {
+            RandomSource::Modern => self.rng.gen(),
+            RandomSource::Lfsr => {
+                for _ in 0..8 {
+                    let bit = self.lfsr & 1;
+                    self.lfsr >>= 1;
+                    if bit == 1 {
+                        self.lfsr ^= 0xB400;
+                    }
+                }
+                self.lfsr as u8
+            }
+        }
+    }
+
+    /// FNV-1a hash of the visible display, packed 8 pixels to a byte with the
+    /// leftmost pixel in the top bit, row by row. It stays the same between
+    /// versions, so scripts and tests can compare it with known values.
+    pub fn display_hash(&self) -> u64 {
+        let mut hash = 0xCBF2_9CE4_8422_2325u64;
+        for byte in self.rows().iter().flat_map(|row| row.to_be_bytes()) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01B3);
+        }
+        hash
+    }
+
+    /// Counts the timers down, to be called at 60 Hz
+    pub fn tick_timers(&mut self) {
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+        if self.sound_timer > 0 {
+            self.set_sound_timer(self.sound_timer - 1);
+        }
+        self.vblank = true;
+    }
+
+    /// Sets the sound timer, telling the hooks when the tone starts or stops
+    fn set_sound_timer(&mut self, value: u8) {
+        let playing = self.sound_timer > 0;
+        self.sound_timer = value;
+        match (playing, value > 0) {
+            (false, true) => hooks::call!(self.on_sound_start()),
+            (true, false) => hooks::call!(self.on_sound_stop()),
+            _ => (),
+        }
+    }
+
+    fn display_changed(&mut self) {
+        self.display_dirty = true;
+        hooks::call!(self.on_display_update());
+    }
+
+    /// Tells the hooks that `err` stopped the machine, passing it on
+    fn halt(&mut self, err: Chip8Error) -> Chip8Error {
+        hooks::call!(self.on_halt(&err));
+        err
+    }
+
+    /// Fetches and executes the instruction at the program counter
+    pub fn step(&mut self) -> Result<(), Chip8Error> {
+        let op = self.fetch()?;
+        self.execute(op)
+    }
+
+    /// Executes up to `instructions` instructions, stopping early if one
+    /// fails, and sums up what they did
+    pub fn run(&mut self, instructions: u64) -> Summary {
+        let display = self.display;
+        let mut summary = Summary {
+            beeped: self.sound_timer > 0,
+            ..Summary::default()
+        };
+        for _ in 0..instructions {
+            if let Err(err) = self.step() {
+                summary.halted = Some(err);
+                break;
+            }
+            summary.instructions += 1;
+            summary.beeped |= self.sound_timer > 0;
+        }
+        summary.drew = self.display != display;
+        summary.waiting_for_key = self.blocked_on_key;
+        summary
+    }
+
+    /// Runs a 60th of a second at `speed` and then ticks the timers, for
+    /// frontends that draw a frame at a time
+    pub fn run_frame(&mut self) -> Summary {
+        let summary = self.run((self.speed as u64 / 60).max(1));
+        if summary.halted.is_none() {
+            self.tick_timers();
+        }
+        summary
+    }
+
+    /// Maps an access to `target` onto memory according to `memory_policy`.
+    /// `op` and `address` identify the instruction making the access, with
+    /// no `op` while it is still being fetched.
+    fn resolve(&self, target: usize, op: Option<u16>, address: u16) -> Result<usize, Chip8Error> {
+        if target < self.memory.size() {
+            return Ok(target);
+        }
+        match self.memory_policy {
+            MemoryPolicy::Wrap => Ok(target % self.memory.size()),
+            MemoryPolicy::Trap => Err(Chip8Error::MemoryOutOfBounds {
+                target,
+                op,
+                address,
+            }),
+        }
+    }
+
+    fn read(&mut self, target: usize, op: u16) -> Result<u8, Chip8Error> {
+        let address = self.program_counter.wrapping_sub(2);
+        let target = self.resolve(target, Some(op), address)?;
+        self.record(target, false);
+        Ok(self.memory.read(target))
+    }
+
+    fn write(&mut self, target: usize, op: u16, value: u8) -> Result<(), Chip8Error> {
+        let address = self.program_counter.wrapping_sub(2);
+        let target = self.resolve(target, Some(op), address)?;
+        self.record(target, true);
+        self.memory.write(target, value);
+        Ok(())
+    }
+
+    fn record(&mut self, target: usize, write: bool) {
+        if let Some(accesses) = &mut self.accesses {
+            accesses.push(Access {
+                address: target as u16,
+                write,
+            });
+        }
+    }
+
+    pub fn fetch(&mut self) -> Result<u16, Chip8Error> {
+        let address = self.program_counter;
+        let high = self
+            .resolve(address as usize, None, address)
+            .map_err(|err| self.halt(err))?;
+        let low = self
+            .resolve(high + 1, None, address)
+            .map_err(|err| self.halt(err))?;
+        let high_byte = self.memory.read(high) as u16;
+        let low_byte = self.memory.read(low) as u16;
+        self.program_counter = high as u16 + 2;
+        Ok((high_byte << 8) | low_byte)
+    }
+
+    pub fn execute(&mut self, op: u16) -> Result<(), Chip8Error> {
+        self.instructions += 1;
+        let Some(instruction) = Instruction::decode(op) else {
+            let err = Chip8Error::UnknownInstruction {
+                op,
+                address: self.program_counter - 2,
+            };
+            return Err(self.halt(err));
+        };
+        self.execute_decoded(instruction, op)
+    }
+
+    /// Carries out `instruction`, decoded from `op`, without counting it
+    pub(crate) fn execute_decoded(
+        &mut self,
+        instruction: Instruction,
+        op: u16,
+    ) -> Result<(), Chip8Error> {
+        hooks::call!(self.on_instruction(instruction));
+        self.carry_out(instruction, op)
+            .map_err(|err| self.halt(err))
+    }
+
+    fn carry_out(&mut self, instruction: Instruction, op: u16) -> Result<(), Chip8Error> {
+        use Instruction::*;
+        match instruction {
+            System(0) => {
+                // Ends the program, which is for the frontend to act on
+                return Err(Chip8Error::Exit {
+                    address: self.program_counter.wrapping_sub(2),
+                });
+            }
+            Clear => {
+                self.display = [0; 64];
+                self.display_changed();
+            }
+            System(0x230) if self.hires => {
+                // CLS, as the hires interpreter spells it
+                self.display = [0; 64];
+                self.display_changed();
+            }
+            Return => {
+                self.program_counter = self.pop()?;
+            }
+            Jump(address) => {
+                self.program_counter = address;
+            }
+            Call(address) => {
+                self.push(self.program_counter)?;
+                self.program_counter = address;
+            }
+            SkipIfEqual { x, value } => {
+                if self.registers[x] == value {
+                    self.program_counter += 2;
+                }
+            }
+            SkipIfNotEqual { x, value } => {
+                if self.registers[x] != value {
+                    self.program_counter += 2;
+                }
+            }
+            SkipIfRegistersEqual { x, y } => {
+                if self.registers[x] == self.registers[y] {
+                    self.program_counter += 2;
+                }
+            }
+            Load { x, value } => {
+                self.registers[x] = value;
+            }
+            Add { x, value } => {
+                self.registers[x] = self.registers[x].wrapping_add(value);
+            }
+            Move { x, y } => {
+                self.registers[x] = self.registers[y];
+            }
+            Or { x, y } => {
+                self.registers[x] |= self.registers[y];
+            }
+            And { x, y } => {
+                self.registers[x] &= self.registers[y];
+            }
+            Xor { x, y } => {
+                self.registers[x] ^= self.registers[y];
+            }
+            AddRegisters { x, y } => {
+                let (new, carry) = self.registers[x].overflowing_add(self.registers[y]);
+                self.registers[x] = new;
+                self.registers[15] = if carry { 1 } else { 0 };
+            }
+            Subtract { x, y } => {
+                let (new, borrow) = self.registers[x].overflowing_sub(self.registers[y]);
+                self.registers[x] = new;
+                self.registers[15] = if borrow { 1 } else { 0 };
+            }
+            ShiftRight { x, .. } => {
+                self.registers[15] = self.registers[x] & 1;
+                self.registers[x] >>= 1;
+            }
+            SubtractReversed { x, y } => {
+                let (new, borrow) = self.registers[y].overflowing_sub(self.registers[x]);
+                self.registers[x] = new;
+                self.registers[15] = if borrow { 1 } else { 0 };
+            }
+            ShiftLeft { x, .. } => {
+                self.registers[15] = (self.registers[x] >> 7) & 1;
+                self.registers[x] <<= 1;
+            }
+            SkipIfRegistersNotEqual { x, y } => {
+                if self.registers[x] != self.registers[y] {
+                    self.program_counter += 2;
+                }
+            }
+            LoadIndex(address) => {
+                self.index = address;
+            }
+            JumpOffset(address) => {
+                self.program_counter = (self.registers[0] as u16) + address;
+            }
+            Random { x, mask } => {
+                self.registers[x] = self.random() & mask;
+            }
+            Draw { x, y, rows } => {
+                if self.quirks.display_wait && !self.vblank {
+                    self.program_counter -= 2;
+                    return Ok(());
+                }
+                self.vblank = false;
+                let x_coord = self.registers[x] as u32 % 64;
+                let y_coord = self.registers[y] as usize;
+                let start = self.index as usize;
+                let mut sprite = [0; 15];
+                let sprite = &mut sprite[..rows as usize];
+                match self.memory.slice(start..start + sprite.len()) {
+                    // Copied in one go unless the accesses are being recorded
+                    // or it runs off the end of memory
+                    Some(bytes) if self.accesses.is_none() => sprite.copy_from_slice(bytes),
+                    _ => {
+                        for (line, byte) in sprite.iter_mut().enumerate() {
+                            *byte = self.read(start + line, op)?;
+                        }
+                    }
+                }
+                // 32 or 64, so masking wraps rows past the bottom edge round
+                let wrap = self.height() - 1;
+                let mut flipped = false;
+                for (line, &pixels) in sprite.iter().enumerate() {
+                    // Rotating wraps the pixels past the right edge round
+                    let mask = ((pixels as u64) << 56).rotate_right(x_coord);
+                    let row = &mut self.display[(y_coord + line) & wrap];
+                    flipped |= *row & mask != 0;
+                    *row ^= mask;
+                }
+                self.registers[15] = flipped as u8;
+                self.display_changed();
+            }
+            SkipIfKey(x) => {
+                if self.keys[self.registers[x] as usize & 0xF] {
+                    self.program_counter += 2;
+                }
+            }
+            SkipIfNotKey(x) => {
+                if !self.keys[self.registers[x] as usize & 0xF] {
+                    self.program_counter += 2;
+                }
+            }
+            GetDelay(x) => {
+                self.registers[x] = self.delay_timer;
+            }
+            WaitKey(x) => {
+                let pressed = self.keys.iter().position(|&held| held).map(|key| key as u8);
+                let key = if !self.quirks.wait_for_release {
+                    pressed
+                } else if let Some(key) = self.waiting_key {
+                    if self.keys[key as usize] {
+                        None
+                    } else {
+                        self.waiting_key = None;
+                        Some(key)
+                    }
+                } else {
+                    self.waiting_key = pressed;
+                    None
+                };
+                self.blocked_on_key = key.is_none();
+                match key {
+                    Some(key) => self.registers[x] = key,
+                    None => self.program_counter -= 2,
+                }
+            }
+            SetDelay(x) => {
+                self.delay_timer = self.registers[x];
+            }
+            SetSound(x) => {
+                self.set_sound_timer(self.registers[x]);
+            }
+            AddIndex(x) => {
+                self.index = self.index.wrapping_add(self.registers[x] as u16);
+            }
+            Font(x) => {
+                self.index = self.registers[x] as u16 * 5;
+            }
+            Bcd(x) => {
+                let vx = self.registers[x] as f32;
+                let index = self.index as usize;
+                self.write(index, op, (vx / 100.0).floor() as u8)?;
+                self.write(index + 1, op, ((vx / 10.0) % 10.0).floor() as u8)?;
+                self.write(index + 2, op, (vx % 10.0) as u8)?;
+            }
+            Store(x) => {
+                for idx in 0..=x {
+                    self.write(self.index as usize + idx, op, self.registers[idx])?;
+                }
+            }
+            Restore(x) => {
+                for idx in 0..=x {
+                    self.registers[idx] = self.read(self.index as usize + idx, op)?;
+                }
+            }
+            SaveFlags(x) if x <= 7 => {
+                self.flags[..=x].copy_from_slice(&self.registers[..=x]);
+                self.flags_dirty = true;
+            }
+            LoadFlags(x) if x <= 7 => {
+                self.registers[..=x].copy_from_slice(&self.flags[..=x]);
+            }
+            _ if instruction.extension() == Extension::SuperChip => {
+                return Err(Chip8Error::SuperChipInstruction {
+                    op,
+                    address: self.program_counter - 2,
+                })
+            }
+            _ => {
+                return Err(Chip8Error::UnknownInstruction {
+                    op,
+                    address: self.program_counter - 2,
+                })
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sets up a machine piece by piece, starting from the power-on state with
+/// the font loaded and the program counter at `PC_START`. `with_rom` loads a
+/// program the way the frontend does, and the rest are mostly useful for
+/// tests that exercise a handful of instructions.
+pub struct Chip8Builder {
+    chip8: Chip8,
+    /// Why the ROM given to `with_rom` can't be run, reported by `build`
+    error: Option<Chip8Error>,
+}
+
+impl Chip8Builder {
+    fn new() -> Self {
+        let mut memory = [0; 4096];
+        memory[..80].copy_from_slice(&SPRITES);
+        let mut rng = Generator::ChaCha(Box::new(ChaCha12Rng::from_entropy()));
+        let lfsr = rng.gen_range(1..=u16::MAX);
+        Self {
+            chip8: Chip8 {
+                memory,
+                stack: [0; 16],
+                registers: [0; 16],
+                flags: [0; 8],
+                flags_dirty: false,
+                program_counter: PC_START,
+                stack_pointer: 0,
+                index: 0,
+                delay_timer: 0,
+                sound_timer: 0,
+                display: [0; 64],
+                hires: false,
+                display_dirty: false,
+                keys: [false; 16],
+                instructions: 0,
+                rng,
+                lfsr,
+                memory_policy: MemoryPolicy::Trap,
+                quirks: Quirks::default(),
+                speed: 500,
+                rom: Vec::new(),
+                load_address: PC_START,
+                waiting_key: None,
+                blocked_on_key: false,
+                vblank: true,
+                accesses: None,
+                hooks: Hooks::default(),
+            },
+            error: None,
+        }
+    }
+
+    /// Loads `data` as the program at `PC_START`, in hires mode if it starts
+    /// like a two-page hires program. `build` fails if it isn't a ROM that
+    /// fits in memory.
+    pub fn with_rom(self, data: &[u8]) -> Self {
+        if let Err(err) = rom::validate(data, PC_START) {
+            return Self {
+                error: self.error.or(Some(err)),
+                ..self
+            };
+        }
+        let builder = self.with_program(data);
+        if rom::is_hires(data) {
+            builder.with_hires()
+        } else {
+            builder
+        }
+    }
+
+    /// Copies `bytes` into memory starting at `addr`
+    pub fn with_memory(mut self, addr: u16, bytes: &[u8]) -> Self {
+        self.chip8.memory[addr as usize..addr as usize + bytes.len()].copy_from_slice(bytes);
+        self
+    }
+
+    /// Replaces the built-in font with 80 bytes of 5-byte digits, optionally
+    /// followed by 160 bytes of 10-byte big digits
+    pub fn with_font(self, font: &[u8]) -> Self {
+        self.with_memory(0, font)
+    }
+
+    /// Loads `bytes` as the program at `PC_START`
+    pub fn with_program(self, bytes: &[u8]) -> Self {
+        self.with_program_at(PC_START, bytes)
+    }
+
+    /// Loads `bytes` as the program at `address` and starts it from there
+    pub fn with_program_at(mut self, address: u16, bytes: &[u8]) -> Self {
+        self.chip8.rom = bytes.to_vec();
+        self.chip8.load_address = address;
+        self.with_memory(address, bytes)
+            .with_program_counter(address)
+    }
+
+    /// Sets V0, V1, ... to `values` in order
+    pub fn with_registers(mut self, values: &[u8]) -> Self {
+        self.chip8.registers[..values.len()].copy_from_slice(values);
+        self
+    }
+
+    /// Sets VX to `value`
+    pub fn with_register(mut self, x: usize, value: u8) -> Self {
+        self.chip8.registers[x] = value;
+        self
+    }
+
+    /// Sets the SUPER-CHIP flag registers, e.g. to ones saved earlier
+    pub fn with_flags(mut self, flags: [u8; 8]) -> Self {
+        self.chip8.flags = flags;
+        self
+    }
+
+    pub fn with_index(mut self, index: u16) -> Self {
+        self.chip8.index = index;
+        self
+    }
+
+    pub fn with_program_counter(mut self, program_counter: u16) -> Self {
+        self.chip8.program_counter = program_counter;
+        self
+    }
+
+    /// Pushes `addresses` onto the call stack, innermost last
+    pub fn with_stack(mut self, addresses: &[u16]) -> Self {
+        for &address in addresses {
+            self.chip8
+                .push(address)
+                .expect("the stack holds at most 16 addresses");
+        }
+        self
+    }
+
+    pub fn with_memory_policy(mut self, memory_policy: MemoryPolicy) -> Self {
+        self.chip8.memory_policy = memory_policy;
+        self
+    }
+
+    pub fn with_quirks(mut self, quirks: Quirks) -> Self {
+        self.chip8.quirks = quirks;
+        self
+    }
+
+    /// Switches to the 64x64 display. A program that starts with the
+    /// two-page entry jump (1260) is started at `HIRES_START` instead, so
+    /// call this after loading it.
+    pub fn with_hires(mut self) -> Self {
+        self.chip8.hires = true;
+        if self.chip8.memory[PC_START as usize..PC_START as usize + 2] == [0x12, 0x60] {
+            self.chip8.program_counter = HIRES_START;
+        }
+        self
+    }
+
+    /// Sets the instructions per second for `run_frame`, 500 by default
+    pub fn with_speed(mut self, speed: u32) -> Self {
+        self.chip8.speed = speed;
+        self
+    }
+
+    /// Makes CXNN produce the same numbers on every run
+    pub fn with_seed(self, seed: u64) -> Self {
+        self.with_generator(Generator::ChaCha(Box::new(ChaCha12Rng::seed_from_u64(
+            seed,
+        ))))
+    }
+
+    /// Takes CXNN's numbers from `rng`, which also seeds the LFSR. Save
+    /// states can't hold it, so a restored machine gets a fresh generator.
+    pub fn with_rng(self, rng: impl RngCore + Send + 'static) -> Self {
+        self.with_generator(Generator::Custom(Box::new(rng)))
+    }
+
+    fn with_generator(mut self, rng: Generator) -> Self {
+        self.chip8.rng = rng;
+        self.chip8.lfsr = self.chip8.rng.gen_range(1..=u16::MAX);
+        self
+    }
+
+    pub fn with_timers(mut self, delay_timer: u8, sound_timer: u8) -> Self {
+        self.chip8.delay_timer = delay_timer;
+        self.chip8.sound_timer = sound_timer;
+        self
+    }
+
+    /// Holds down the given keys
+    pub fn with_keys(mut self, keys: &[usize]) -> Self {
+        for &key in keys {
+            self.chip8.keys[key] = true;
+        }
+        self
+    }
+
+    pub fn build(self) -> Result<Chip8, Chip8Error> {
+        match self.error {
+            Some(err) => Err(err),
+            None => Ok(self.chip8),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_are_named_as_they_parse() {
+        for policy in [MemoryPolicy::Wrap, MemoryPolicy::Trap] {
+            assert_eq!(policy.to_string().parse(), Ok(policy));
+        }
+        for source in [RandomSource::Modern, RandomSource::Lfsr] {
+            assert_eq!(source.to_string().parse(), Ok(source));
+        }
+        assert!("Wrap".parse::<MemoryPolicy>().is_err());
+    }
+
+    #[test]
+    fn blank_display_hashes_to_fnv_of_zeros() {
+        let chip8 = Chip8::builder().build().unwrap();
+        // FNV-1a of 256 zero bytes
+        assert_eq!(chip8.display_hash(), 0xD80A_C658_736B_B725);
+        let hires = Chip8::builder().with_hires().build().unwrap();
+        // and of 512, for the taller display
+        assert_eq!(hires.display_hash(), 0x7DA1_44B9_7D05_4B25);
+    }
+
+    #[test]
+    fn display_hash_follows_the_pixels() {
+        // Draw the font's 0, draw it again to erase it, then draw it lower
+        let mut chip8 = Chip8::builder()
+            .with_program(&[0xF0, 0x29, 0xD0, 0x05, 0xD0, 0x05, 0xD0, 0x15])
+            .with_register(1, 1)
+            .build()
+            .unwrap();
+        let blank = chip8.display_hash();
+        chip8.step().unwrap();
+        chip8.step().unwrap();
+        let drawn = chip8.display_hash();
+        assert_ne!(drawn, blank);
+        chip8.step().unwrap();
+        assert_eq!(chip8.display_hash(), blank);
+        chip8.step().unwrap();
+        assert_ne!(chip8.display_hash(), blank);
+        assert_ne!(chip8.display_hash(), drawn);
+    }
+
+    #[test]
+    fn display_hash_ignores_everything_but_the_display() {
+        let mut chip8 = Chip8::builder().build().unwrap();
+        let blank = chip8.display_hash();
+        chip8.registers = [0xAA; 16];
+        chip8.memory[0x300] = 0xFF;
+        chip8.index = 0x300;
+        assert_eq!(chip8.display_hash(), blank);
+    }
+}
@@ -0,0 +1,305 @@
+//! `chip8 lint`: checks a ROM for constructs that are likely bugs, from the
+//! code reachable from its entry point, without running it. Warnings are
+//! for what's almost always wrong: jumps out of the ROM, RETs with nothing
+//! to return to, subroutines that never return, and memory accesses through
+//! I that run off the end. Notes are for what's worth knowing but often
+//! fine: bytes that are never run nor pointed at, and instructions whose
+//! behaviour differs between interpreters.
+
+use chip8::disasm;
+use chip8::instruction::Instruction;
+use chip8::{rom, Chip8Error, PC_START};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+use std::path::PathBuf;
+
+/// Instructions after an LD I to look through for the accesses using it
+const I_LOOKAHEAD: usize = 16;
+
+#[derive(clap::Args)]
+pub struct LintArgs {
+    /// ROMs to check
+    #[arg(required = true)]
+    roms: Vec<PathBuf>,
+}
+
+struct Finding {
+    address: Option<u16>,
+    warning: bool,
+    message: String,
+}
+
+pub fn run(args: LintArgs) -> Result<(), Chip8Error> {
+    let mut warnings = 0;
+    for path in &args.roms {
+        let data = rom::read(path)?;
+        for finding in lint(&data) {
+            let kind = if finding.warning { "warning" } else { "note" };
+            match finding.address {
+                Some(address) => println!(
+                    "{}:{:03X}: {}: {}",
+                    path.display(),
+                    address,
+                    kind,
+                    finding.message
+                ),
+                None => println!("{}: {}: {}", path.display(), kind, finding.message),
+            }
+            warnings += finding.warning as usize;
+        }
+    }
+    if warnings > 0 {
+        return Err(Chip8Error::LintFailed { warnings });
+    }
+    Ok(())
+}
+
+fn lint(data: &[u8]) -> Vec<Finding> {
+    let start = if rom::is_hires(data) {
+        chip8::HIRES_START
+    } else {
+        PC_START
+    };
+    let rom_range = PC_START..PC_START + data.len() as u16;
+    let instruction_at = |address: u16| {
+        let offset = address.checked_sub(PC_START)? as usize;
+        let bytes = data.get(offset..offset + 2)?;
+        Instruction::decode(u16::from_be_bytes([bytes[0], bytes[1]]))
+    };
+    let code = disasm::reachable(data, PC_START, start);
+    let mut findings = Vec::new();
+    let mut warn = |address, message| {
+        findings.push(Finding {
+            address: Some(address),
+            warning: true,
+            message,
+        })
+    };
+
+    for &address in &code {
+        let Some(instruction) = instruction_at(address) else {
+            continue;
+        };
+        let target = match instruction {
+            Instruction::Jump(target)
+            | Instruction::Call(target)
+            | Instruction::JumpOffset(target) => target,
+            _ => continue,
+        };
+        if !rom_range.contains(&target) {
+            warn(
+                address,
+                format!(
+                    "{} goes outside the ROM, which is {:03X}-{:03X}",
+                    instruction,
+                    rom_range.start,
+                    rom_range.end - 1
+                ),
+            );
+        }
+    }
+
+    // Code reached from the entry point without going into subroutines
+    let top_level = disasm::reachable_without_calls(data, PC_START, start);
+    for address in returns(&top_level, &instruction_at) {
+        warn(
+            address,
+            "RET at the top level of the program, with nothing to return to".into(),
+        );
+    }
+    let subroutines: BTreeSet<u16> = code
+        .iter()
+        .filter_map(|&address| match instruction_at(address) {
+            Some(Instruction::Call(target)) => Some(target),
+            _ => None,
+        })
+        .filter(|target| rom_range.contains(target))
+        .collect();
+    for &subroutine in &subroutines {
+        let body = disasm::reachable_without_calls(data, PC_START, subroutine);
+        if returns(&body, &instruction_at).is_empty() {
+            warn(
+                subroutine,
+                "the subroutine here never returns, leaving its CALL on the stack".into(),
+            );
+        }
+    }
+
+    let mut referenced = Vec::new();
+    for &address in &code {
+        let Some(Instruction::LoadIndex(i)) = instruction_at(address) else {
+            continue;
+        };
+        for (at, instruction, range) in uses_of_i(address, &code, &instruction_at) {
+            let range = i as usize + range.start..i as usize + range.end;
+            let access = format!(
+                "{} with I = {:03X} from {:03X} uses {:03X}-{:03X}, which",
+                instruction,
+                i,
+                address,
+                range.start,
+                range.end - 1
+            );
+            if range.end > 4096 {
+                warn(at, format!("{} runs past the end of memory", access));
+            } else if matches!(instruction, Instruction::Draw { .. })
+                && range.start >= PC_START as usize
+                && range.end > rom_range.end as usize
+            {
+                warn(at, format!("{} runs past the end of the ROM", access));
+            }
+            referenced.push(range);
+        }
+        referenced.push(i as usize..i as usize + 1);
+    }
+
+    for range in unused(data, &code, &referenced) {
+        findings.push(Finding {
+            address: Some(range.start),
+            warning: false,
+            message: match range.len() {
+                1 => format!(
+                    "the byte at {:03X} is never run, and no LD I points at it",
+                    range.start
+                ),
+                len => format!(
+                    "{} bytes at {:03X}-{:03X} are never run, and no LD I points at them",
+                    len,
+                    range.start,
+                    range.end - 1
+                ),
+            },
+        });
+    }
+    for (address, message) in quirks(&code, &instruction_at) {
+        findings.push(Finding {
+            address: Some(address),
+            warning: false,
+            message,
+        });
+    }
+    findings.sort_by_key(|finding| (finding.address, !finding.warning));
+    findings
+}
+
+/// The RETs among `addresses`
+fn returns(
+    addresses: &BTreeSet<u16>,
+    instruction_at: &impl Fn(u16) -> Option<Instruction>,
+) -> Vec<u16> {
+    addresses
+        .iter()
+        .copied()
+        .filter(|&address| instruction_at(address) == Some(Instruction::Return))
+        .collect()
+}
+
+/// The instructions using I after an LD I at `address`, following the code
+/// straight on until I changes or the flow of the program goes elsewhere,
+/// with their address and the bytes past I they use
+fn uses_of_i(
+    address: u16,
+    code: &BTreeSet<u16>,
+    instruction_at: &impl Fn(u16) -> Option<Instruction>,
+) -> Vec<(u16, Instruction, Range<usize>)> {
+    let mut uses = Vec::new();
+    let following = (1..=I_LOOKAHEAD as u16)
+        .map(|n| address + n * 2)
+        .take_while(|next| code.contains(next));
+    for at in following {
+        let Some(instruction) = instruction_at(at) else {
+            break;
+        };
+        let len = match instruction {
+            Instruction::Draw { rows: 0, .. } => 32,
+            Instruction::Draw { rows, .. } => rows as usize,
+            Instruction::Bcd(_) => 3,
+            Instruction::Store(x) | Instruction::Restore(x) => x + 1,
+            Instruction::Jump(_)
+            | Instruction::Call(_)
+            | Instruction::LoadIndex(_)
+            | Instruction::JumpOffset(_)
+            | Instruction::Return
+            | Instruction::AddIndex(_)
+            | Instruction::Font(_)
+            | Instruction::BigFont(_) => break,
+            _ => continue,
+        };
+        uses.push((at, instruction, 0..len));
+    }
+    uses
+}
+
+/// Runs of bytes that aren't code and that nothing reachable points I at,
+/// leaving out runs of zeros, which are padding or space for variables
+fn unused(data: &[u8], code: &BTreeSet<u16>, referenced: &[Range<usize>]) -> Vec<Range<u16>> {
+    let mut used = vec![false; data.len()];
+    let mut mark = |range: Range<usize>| {
+        let start = range.start.saturating_sub(PC_START as usize);
+        let end = range.end.saturating_sub(PC_START as usize).min(data.len());
+        for byte in used.iter_mut().take(end).skip(start) {
+            *byte = true;
+        }
+    };
+    for &address in code {
+        mark(address as usize..address as usize + 2);
+    }
+    for range in referenced {
+        mark(range.clone());
+    }
+    let mut runs = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        if used[offset] {
+            offset += 1;
+            continue;
+        }
+        let start = offset;
+        while offset < data.len() && !used[offset] {
+            offset += 1;
+        }
+        if data[start..offset].iter().any(|&byte| byte != 0) {
+            runs.push(start as u16 + PC_START..offset as u16 + PC_START);
+        }
+    }
+    runs
+}
+
+/// The first use of each instruction that interpreters disagree about
+fn quirks(
+    code: &BTreeSet<u16>,
+    instruction_at: &impl Fn(u16) -> Option<Instruction>,
+) -> Vec<(u16, String)> {
+    let mut first: BTreeMap<&str, (u16, usize)> = BTreeMap::new();
+    for &address in code {
+        let Some(instruction) = instruction_at(address) else {
+            continue;
+        };
+        let quirk = match instruction {
+            Instruction::ShiftRight { .. } | Instruction::ShiftLeft { .. } => {
+                "8XY6/8XYE shift VY into VX on the COSMAC VIP, but shift VX in place on CHIP-48 and SUPER-CHIP"
+            }
+            Instruction::Or { .. } | Instruction::And { .. } | Instruction::Xor { .. } => {
+                "8XY1/8XY2/8XY3 reset VF on the COSMAC VIP only"
+            }
+            Instruction::Store(_) | Instruction::Restore(_) => {
+                "FX55/FX65 leave I past the registers on the COSMAC VIP, but unchanged on CHIP-48 and SUPER-CHIP"
+            }
+            Instruction::JumpOffset(_) => "BNNN jumps to NNN + V0, but to XNN + VX on CHIP-48 and SUPER-CHIP",
+            Instruction::WaitKey(_) => {
+                "FX0A waits for a key to be released on the COSMAC VIP, but only pressed on some interpreters"
+            }
+            _ => continue,
+        };
+        first.entry(quirk).or_insert((address, 0)).1 += 1;
+    }
+    let mut quirks: Vec<(u16, String)> = first
+        .into_iter()
+        .map(|(quirk, (address, count))| match count {
+            1 => (address, format!("{} (used once)", quirk)),
+            _ => (address, format!("{} (used {} times)", quirk, count)),
+        })
+        .collect();
+    quirks.sort();
+    quirks
+}
@@ -0,0 +1,176 @@
+//! `chip8 disasm`: a listing of a ROM that can be assembled again. The code
+//! reachable from the entry point is written as instructions, with a label
+//! on every address a jump, call or LD I points at, and everything else as
+//! `DB` data. Labels are named from `--symbols` where it has a name, and
+//! `L_0234` after their address otherwise. Numbers are hex, as in the rest
+//! of the disassembly, and each line ends in a comment with its address.
+//!
+//! Data that an LD I points at and a DRW shortly after draws is taken to be
+//! a sprite of that DRW's height, and written a row to a line with the row
+//! drawn in the comment, so graphics stand out from the rest of the data.
+
+use crate::symbols::Symbols;
+use chip8::disasm;
+use chip8::instruction::Instruction;
+use chip8::{rom, Chip8Error, PC_START};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+/// Bytes per `DB` line
+const DATA_PER_LINE: usize = 8;
+/// Instructions after an LD I to look for the DRW that uses it
+const DRAW_LOOKAHEAD: usize = 8;
+
+#[derive(clap::Args)]
+pub struct DisasmArgs {
+    rom: PathBuf,
+
+    /// Names for addresses, used for the labels
+    #[arg(long, value_name = "FILE")]
+    symbols: Option<PathBuf>,
+}
+
+pub fn run(args: DisasmArgs) -> Result<(), Chip8Error> {
+    let symbols = match &args.symbols {
+        Some(path) => Symbols::read(path).map_err(Chip8Error::Symbols)?,
+        None => Symbols::default(),
+    };
+    let data = rom::read(&args.rom)?;
+    print!("{}", list(&data, &symbols));
+    Ok(())
+}
+
+fn list(data: &[u8], symbols: &Symbols) -> String {
+    let start = if rom::is_hires(data) {
+        chip8::HIRES_START
+    } else {
+        PC_START
+    };
+    let end = PC_START as usize + data.len();
+    let op_at = |address: u16| {
+        let offset = (address - PC_START) as usize;
+        u16::from_be_bytes([data[offset], data[offset + 1]])
+    };
+    let code: BTreeSet<u16> = disasm::reachable(data, PC_START, start);
+    let labels: BTreeMap<u16, String> = code
+        .iter()
+        .filter_map(|&address| Instruction::decode(op_at(address))?.address())
+        .chain([start])
+        .filter(|&target| (PC_START as usize..end).contains(&(target as usize)))
+        .map(|target| {
+            let name = symbols.name(target).map(str::to_string);
+            (target, name.unwrap_or_else(|| format!("L_{:04X}", target)))
+        })
+        .collect();
+
+    let sprites = sprite_heights(&code, op_at);
+
+    let mut out = String::new();
+    let mut address = PC_START;
+    while (address as usize) < end {
+        if let Some(label) = labels.get(&address) {
+            out += &format!("{}:\n", label);
+        }
+        let (text, len) = if code.contains(&address) {
+            let op = op_at(address);
+            let text = match Instruction::decode(op) {
+                Some(instruction) => with_label(instruction, &labels),
+                None => format!("DW {:04X}", op),
+            };
+            // The address after F000 is left to be written as data
+            (text, 2)
+        } else if let Some(&len) = sprites.get(&address) {
+            let len = (address..end as u16)
+                .take_while(|&a| a == address || !(code.contains(&a) || labels.contains_key(&a)))
+                .take(len)
+                .count();
+            let offset = (address - PC_START) as usize;
+            let width = if len > 16 { 2 } else { 1 };
+            for (i, row) in data[offset..offset + len].chunks(width).enumerate() {
+                out += &sprite_row(row, address as usize + i * width);
+            }
+            address += len as u16;
+            continue;
+        } else {
+            // Data runs up to the next instruction or label
+            let bytes: Vec<String> = (address..end as u16)
+                .take_while(|&a| a == address || !(code.contains(&a) || labels.contains_key(&a)))
+                .take(DATA_PER_LINE)
+                .map(|a| format!("{:02X}", data[(a - PC_START) as usize]))
+                .collect();
+            let len = bytes.len() as u16;
+            (format!("DB {}", bytes.join(", ")), len)
+        };
+        out += &format!("    {:<24}; {:03X}\n", text, address);
+        address += len;
+    }
+    out
+}
+
+/// Sprite heights by address, from each LD I followed by a DRW before the
+/// flow of the program goes elsewhere or I changes. A height of 0 is a
+/// 16x16 SUPER-CHIP sprite, two bytes a row, so 32 bytes are taken.
+pub fn sprite_heights(code: &BTreeSet<u16>, op_at: impl Fn(u16) -> u16) -> BTreeMap<u16, usize> {
+    let mut sprites = BTreeMap::new();
+    for &address in code {
+        let Some(Instruction::LoadIndex(sprite)) = Instruction::decode(op_at(address)) else {
+            continue;
+        };
+        let following = (1..=DRAW_LOOKAHEAD as u16)
+            .map(|i| address + i * 2)
+            .take_while(|next| code.contains(next))
+            .map(|next| Instruction::decode(op_at(next)));
+        for next in following {
+            match next {
+                Some(Instruction::Draw { rows, .. }) => {
+                    let height = match rows {
+                        0 => 32,
+                        n => n as usize,
+                    };
+                    let known = sprites.entry(sprite).or_insert(0);
+                    *known = height.max(*known);
+                    break;
+                }
+                Some(
+                    Instruction::Jump(_)
+                    | Instruction::Call(_)
+                    | Instruction::LoadIndex(_)
+                    | Instruction::JumpOffset(_)
+                    | Instruction::Return
+                    | Instruction::AddIndex(_),
+                ) => break,
+                _ => (),
+            }
+        }
+    }
+    sprites
+}
+
+/// A `DB` line for one row of a sprite at `address`, with its pixels drawn
+/// in the comment
+pub fn sprite_row(row: &[u8], address: usize) -> String {
+    let bytes: Vec<String> = row.iter().map(|byte| format!("{:02X}", byte)).collect();
+    let pixels: String = row
+        .iter()
+        .flat_map(|byte| (0..8).map(move |bit| byte & (0x80 >> bit) != 0))
+        .map(|on| if on { '#' } else { '.' })
+        .collect();
+    let text = format!("DB {}", bytes.join(", "));
+    format!("    {:<24}; {:03X}  {}\n", text, address, pixels)
+}
+
+/// The text of `instruction` with the address at its end replaced by the
+/// label there, if any
+fn with_label(instruction: Instruction, labels: &BTreeMap<u16, String>) -> String {
+    let text = instruction.to_string();
+    let Some(target) = instruction.address() else {
+        return text;
+    };
+    match (
+        text.strip_suffix(&format!("{:03X}", target)),
+        labels.get(&target),
+    ) {
+        (Some(rest), Some(label)) => format!("{}{}", rest, label),
+        _ => text,
+    }
+}
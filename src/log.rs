@@ -0,0 +1,135 @@
+//! Leveled logging for the frontend. Errors, warnings and notes such as the
+//! database entry found always go to stderr; `-v` adds debug messages such
+//! as frame timings and `-vv` traces every instruction executed. With `--log-file` the same messages are also
+//! written to a file as JSON lines, for reading by scripts.
+
+use crate::json::Json;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    fn name(self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+}
+
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+static FILE: OnceLock<Mutex<File>> = OnceLock::new();
+static START: OnceLock<Instant> = OnceLock::new();
+
+/// Sets up logging for `verbose` occurrences of `-v`, optionally copying
+/// the messages to `path`
+pub fn init(verbose: u8, path: Option<&Path>) -> io::Result<()> {
+    let level = match verbose {
+        0 => Level::Info,
+        1 => Level::Debug,
+        _ => Level::Trace,
+    };
+    MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+    START.get_or_init(Instant::now);
+    if let Some(path) = path {
+        let _ = FILE.set(Mutex::new(File::create(path)?));
+    }
+    Ok(())
+}
+
+/// Whether messages at `level` are shown, to skip work that only feeds them
+pub fn enabled(level: Level) -> bool {
+    level as u8 <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+pub fn log(level: Level, args: fmt::Arguments) {
+    match level {
+        Level::Error | Level::Warn | Level::Info => eprintln!("{}", args),
+        _ => eprintln!("{}: {}", level.name(), args),
+    }
+    if let Some(file) = FILE.get() {
+        let time = START
+            .get()
+            .map_or(0.0, |start| start.elapsed().as_secs_f64());
+        let record = Json::Object(vec![
+            ("time".into(), Json::Number(time)),
+            ("level".into(), Json::String(level.name().into())),
+            ("message".into(), Json::String(args.to_string())),
+        ]);
+        let _ = writeln!(file.lock().unwrap(), "{}", record);
+    }
+}
+
+macro_rules! log_at {
+    ($level:expr, $($arg:tt)*) => {
+        if $crate::log::enabled($level) {
+            $crate::log::log($level, format_args!($($arg)*))
+        }
+    };
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => { $crate::log::log_at!($crate::log::Level::Error, $($arg)*) };
+}
+
+// Named so it doesn't clash with the `warn` attribute, and exported as
+// `log::warn!`
+macro_rules! warning {
+    ($($arg:tt)*) => { $crate::log::log_at!($crate::log::Level::Warn, $($arg)*) };
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => { $crate::log::log_at!($crate::log::Level::Info, $($arg)*) };
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => { $crate::log::log_at!($crate::log::Level::Debug, $($arg)*) };
+}
+
+macro_rules! trace {
+    ($($arg:tt)*) => { $crate::log::log_at!($crate::log::Level::Trace, $($arg)*) };
+}
+
+pub(crate) use {debug, error, info, log_at, trace, warning as warn};
+
+/// Logs how long a stretch of work took when dropped, e.g. a frame
+pub struct Span {
+    name: &'static str,
+    start: Instant,
+}
+
+impl Span {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let elapsed: Duration = self.start.elapsed();
+        debug!(
+            "{} took {:.2} ms",
+            self.name,
+            elapsed.as_secs_f64() * 1000.0
+        );
+    }
+}
@@ -1,299 +1,80 @@
-use clap::Parser;
-use rand::{self, Rng};
-use sdl2::{event::Event, keyboard::Keycode, pixels::Color, rect::Rect};
+use chip8::instruction::Instruction;
+use chip8::{Chip8, Chip8Error};
+use clap::{Parser, Subcommand};
+use config::Palette;
+use sdl2::{
+    event::{Event, WindowEvent},
+    keyboard::{Keycode, Mod},
+    pixels::{Color, PixelFormatEnum},
+    rect::Rect,
+    EventPump, Sdl,
+};
+use std::fmt;
 use std::io::Read;
-use std::path::Path;
-use std::sync::{Arc, Mutex};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
 use std::thread;
 use std::time::{Duration, Instant};
 
-const PC_START: u16 = 0x200;
-const SPRITES: [u8; 80] = [
-    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-    0x20, 0x60, 0x20, 0x20, 0x70, // 1
-    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-];
-
-struct Chip8 {
-    memory: [u8; 4096],
-    stack: [u16; 16],
-    registers: [u8; 16],
-    program_counter: u16,
-    stack_pointer: u16,
-    index: u16,
-    delay_timer: u8,
-    sound_timer: u8,
-    display: [bool; 2048],
-    keys: [bool; 16],
-}
+mod bench;
+mod browser;
+mod cheats;
+mod config;
+mod console;
+mod control;
+mod crash;
+mod database;
+mod debug_window;
+mod debugger;
+mod detect;
+#[cfg(feature = "http")]
+mod download;
+mod editor;
+mod flags;
+mod frame;
+mod gif;
+mod halt;
+mod headless;
+mod heatmap;
+mod history;
+mod info;
+mod input;
+mod json;
+mod keypad_file;
+mod lint;
+mod listing;
+mod log;
+#[cfg(feature = "max7219")]
+mod max7219;
+mod movie;
+mod netplay;
+mod overlay;
+mod pacing;
+mod patch;
+mod png;
+mod recent;
+mod savestate;
+mod screenshot;
+mod selfmod;
+mod sha1;
+mod sprites;
+mod stats;
+mod stream;
+mod symbols;
+mod test_suite;
+mod video;
+mod watch;
+mod zip;
 
-impl Chip8 {
-    fn from_file<P>(path: P) -> Self
-    where
-        P: AsRef<Path>,
-    {
-        let mut memory = [0; 4096];
-        let mut data = Vec::new();
-        std::fs::File::open(path)
-            .unwrap()
-            .read_to_end(&mut data)
-            .unwrap();
-        memory[PC_START as usize..PC_START as usize + data.len()].copy_from_slice(&data);
-        memory[..80].copy_from_slice(&SPRITES);
-        Self {
-            memory,
-            stack: [0; 16],
-            registers: [0; 16],
-            program_counter: PC_START,
-            stack_pointer: 0,
-            index: 0,
-            delay_timer: 0,
-            sound_timer: 0,
-            display: [false; 2048],
-            keys: [false; 16],
-        }
-    }
-
-    fn push(&mut self, value: u16) {
-        self.stack[self.stack_pointer as usize] = value;
-        self.stack_pointer += 1;
-    }
-
-    fn pop(&mut self) -> u16 {
-        self.stack_pointer -= 1;
-        self.stack[self.stack_pointer as usize]
-    }
-
-    fn fetch(&mut self) -> u16 {
-        let high_byte = self.memory[self.program_counter as usize] as u16;
-        let low_byte = self.memory[self.program_counter as usize + 1] as u16;
-        self.program_counter += 2;
-        (high_byte << 8) | low_byte
-    }
-
-    fn execute(&mut self, op: u16) {
-        let digit1 = (op & 0xF000) >> 12;
-        let digit2 = (op & 0x0F00) >> 8;
-        let digit3 = (op & 0x00F0) >> 4;
-        let digit4 = op & 0x000F;
-
-        match (digit1, digit2, digit3, digit4) {
-            (0, 0, 0, 0) => {
-                // NOP
-                std::process::exit(0);
-            }
-            (0, 0, 0xE, 0) => {
-                // CLS
-                self.display = [false; 64 * 32];
-            }
-            (0, 0, 0xE, 0xE) => {
-                // RET
-                self.program_counter = self.pop();
-            }
-            (1, _, _, _) => {
-                // JMP NNN
-                self.program_counter = op & 0xFFF;
-            }
-            (2, _, _, _) => {
-                // CALL NNN
-                self.push(self.program_counter);
-                self.program_counter = op & 0xFFF;
-            }
-            (3, _, _, _) => {
-                // SKIP VX == NN
-                if self.registers[digit2 as usize] == (op & 0xFF) as u8 {
-                    self.program_counter += 2;
-                }
-            }
-            (4, _, _, _) => {
-                // SKIP VC != NN
-                if self.registers[digit2 as usize] != (op & 0xFF) as u8 {
-                    self.program_counter += 2;
-                }
-            }
-            (6, _, _, _) => {
-                // VX = NN
-                self.registers[digit2 as usize] = (op & 0xFF) as u8;
-            }
-            (7, _, _, _) => {
-                // VX += NN
-                self.registers[digit2 as usize] =
-                    self.registers[digit2 as usize].wrapping_add((op & 0xFF) as u8);
-            }
-            (8, _, _, 0) => {
-                // VX = VY
-                self.registers[digit2 as usize] = self.registers[digit3 as usize];
-            }
-            (8, _, _, 1) => {
-                // VX |= VY
-                self.registers[digit2 as usize] |= self.registers[digit3 as usize];
-            }
-            (8, _, _, 2) => {
-                // VX &= VY
-                self.registers[digit2 as usize] &= self.registers[digit3 as usize];
-            }
-            (8, _, _, 3) => {
-                // VX ^= VY
-                self.registers[digit2 as usize] ^= self.registers[digit3 as usize];
-            }
-            (8, _, _, 4) => {
-                // VX += VY
-                let (new, carry) = self.registers[digit2 as usize]
-                    .overflowing_add(self.registers[digit3 as usize]);
-                self.registers[digit2 as usize] = new;
-                self.registers[15] = if carry { 1 } else { 0 };
-            }
-            (8, _, _, 5) => {
-                // VX -= VY
-                let (new, borrow) = self.registers[digit2 as usize]
-                    .overflowing_sub(self.registers[digit3 as usize]);
-                self.registers[digit2 as usize] = new;
-                self.registers[15] = if borrow { 1 } else { 0 };
-            }
-            (8, _, _, 6) => {
-                // VX >>= 1
-                self.registers[15] = self.registers[digit2 as usize] & 1;
-                self.registers[digit2 as usize] >>= 1;
-            }
-            (8, _, _, 7) => {
-                // VX = VY - VX
-                let (new, borrow) = self.registers[digit3 as usize]
-                    .overflowing_sub(self.registers[digit2 as usize]);
-                self.registers[digit2 as usize] = new;
-                self.registers[15] = if borrow { 1 } else { 0 };
-            }
-            (8, _, _, 0xE) => {
-                // VX <<= 1
-                self.registers[15] = (self.registers[digit2 as usize] >> 7) & 1;
-                self.registers[digit2 as usize] <<= 1;
-            }
-            (9, _, _, 0) => {
-                // SKIP VX != VY
-                if self.registers[digit2 as usize] != self.registers[digit3 as usize] {
-                    self.program_counter += 2;
-                }
-            }
-            (0xA, _, _, _) => {
-                // I = NNN
-                self.index = op & 0xFFF;
-            }
-            (0xB, _, _, _) => {
-                // JMP V0 + NNN
-                self.program_counter = (self.registers[0] as u16) + (op & 0xFFF);
-            }
-            (0xC, _, _, _) => {
-                // VX = random & NN
-                self.registers[digit2 as usize] =
-                    rand::thread_rng().gen::<u8>() & (op & 0xFF) as u8;
-            }
-            (0xD, _, _, _) => {
-                // DRAW
-                let x_coord = self.registers[digit2 as usize] as u16;
-                let y_coord = self.registers[digit3 as usize] as u16;
-                let num_rows = digit4;
-                let mut flipped = false;
-                for y_line in 0..num_rows {
-                    let addr = self.index + y_line;
-                    let pixels = self.memory[addr as usize];
-                    for x_line in 0..8 {
-                        if (pixels & (0b1000_0000 >> x_line)) != 0 {
-                            let x = (x_coord + x_line) as usize % 64;
-                            let y = (y_coord + y_line) as usize % 32;
-                            let idx = x + 64 * y;
-                            flipped |= self.display[idx];
-                            self.display[idx] ^= true;
-                        }
-                    }
-                }
-                self.registers[15] = flipped as u8;
-            }
-            (0xE, _, 9, 0xE) => {
-                // SKIP KEY PRESS
-                if self.keys[self.registers[digit2 as usize] as usize] {
-                    self.program_counter += 2;
-                }
-            }
-            (0xE, _, 0xA, 1) => {
-                // SKIP KEY RELEASE
-                if !self.keys[self.registers[digit2 as usize] as usize] {
-                    self.program_counter += 2;
-                }
-            }
-            (0xF, _, 0, 7) => {
-                // VX = DT
-                self.registers[digit2 as usize] = self.delay_timer;
-            }
-            (0xF, _, 0, 0xA) => {
-                // WAIT KEY
-                let mut pressed = false;
-                for i in 0..self.keys.len() {
-                    if self.keys[i] {
-                        self.registers[digit2 as usize] = i as u8;
-                        pressed = true;
-                        break;
-                    }
-                }
-                if !pressed {
-                    self.program_counter -= 2;
-                }
-            }
-            (0xF, _, 1, 5) => {
-                // DT = VX
-                self.delay_timer = self.registers[digit2 as usize];
-            }
-            (0xF, _, 1, 8) => {
-                // ST = VX
-                self.sound_timer = self.registers[digit2 as usize];
-            }
-            (0xF, _, 1, 0xE) => {
-                // I += VX
-                self.index = self
-                    .index
-                    .wrapping_add(self.registers[digit2 as usize] as u16);
-            }
-            (0xF, _, 2, 9) => {
-                // I = FONT
-                self.index = self.registers[digit2 as usize] as u16 * 5;
-            }
-            (0xF, _, 3, 3) => {
-                // BCD
-                let vx = self.registers[digit2 as usize] as f32;
-                self.memory[self.index as usize] = (vx / 100.0).floor() as u8;
-                self.memory[(self.index + 1) as usize] = ((vx / 10.0) % 10.0).floor() as u8;
-                self.memory[(self.index + 2) as usize] = (vx % 10.0) as u8;
-            }
-            (0xF, _, 5, 5) => {
-                // STORE V0 - VX
-                for idx in 0..=digit2 as usize {
-                    self.memory[self.index as usize + idx] = self.registers[idx];
-                }
-            }
-            (0xF, _, 6, 5) => {
-                // LOAD V0 - VX
-                for idx in 0..=digit2 as usize {
-                    self.registers[idx] = self.memory[self.index as usize + idx];
-                }
-            }
-            _ => panic!(
-                "Unknown instruction: {} ({} {} {} {})",
-                op, digit1, digit2, digit3, digit4
-            ),
-        }
-    }
-}
+/// Bytes from I drawn by the sprite overlay, and how many to a column
+const SPRITE_BYTES: u16 = 128;
+const SPRITE_ROWS: usize = 16;
+
+/// Seconds for an unlit pixel to fade to half brightness with `--phosphor`
+const PHOSPHOR_HALF_LIFE: f32 = 1.0 / 30.0;
+/// Frames a key stays marked on the keypad overlay after the program read
+/// it, as most programs only read keys now and then
+const POLL_FRAMES: u8 = 30;
 
 fn key_code(key: Keycode) -> Option<usize> {
     match key {
@@ -317,105 +98,1444 @@ fn key_code(key: Keycode) -> Option<usize> {
     }
 }
 
+/// Save state slot for F1 to F10
+fn state_slot(key: Keycode) -> Option<u8> {
+    match key {
+        Keycode::F1 => Some(1),
+        Keycode::F2 => Some(2),
+        Keycode::F3 => Some(3),
+        Keycode::F4 => Some(4),
+        Keycode::F5 => Some(5),
+        Keycode::F6 => Some(6),
+        Keycode::F7 => Some(7),
+        Keycode::F8 => Some(8),
+        Keycode::F9 => Some(9),
+        Keycode::F10 => Some(10),
+        _ => None,
+    }
+}
+
+/// Renders per-pixel brightness at `scale` with scanlines and gaps between
+/// pixels, optionally bent like a curved tube. Returns packed RGB24 rows.
+fn crt_filter(levels: &[f32], palette: &Palette, scale: u32, curvature: bool) -> Vec<u8> {
+    let scale = scale as usize;
+    let width = 64 * scale;
+    let height = levels.len() / 64 * scale;
+    let mut pixels = vec![0; width * height * 3];
+    for y in 0..height {
+        for x in 0..width {
+            let (src_x, src_y) = if curvature {
+                let u = x as f32 / width as f32 * 2.0 - 1.0;
+                let v = y as f32 / height as f32 * 2.0 - 1.0;
+                let (u, v) = (u * (1.0 + 0.1 * v * v), v * (1.0 + 0.1 * u * u));
+                if u.abs() >= 1.0 || v.abs() >= 1.0 {
+                    continue;
+                }
+                (
+                    ((u + 1.0) / 2.0 * width as f32) as usize,
+                    ((v + 1.0) / 2.0 * height as f32) as usize,
+                )
+            } else {
+                (x, y)
+            };
+            let mut brightness = levels[src_x / scale + 64 * (src_y / scale)];
+            if scale >= 4 && (src_x % scale == scale - 1 || src_y % scale == scale - 1) {
+                // Gap between neighbouring pixels
+                brightness *= 0.5;
+            }
+            if scale >= 2 && src_y % 2 == 1 {
+                // Scanline
+                brightness *= 0.6;
+            }
+            let idx = (x + width * y) * 3;
+            pixels[idx..idx + 3].copy_from_slice(&palette.shade(brightness));
+        }
+    }
+    pixels
+}
+
 #[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Show debug messages, or with -vv every instruction executed
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Also write log messages to this file as JSON lines
+    #[arg(long, global = true)]
+    log_file: Option<PathBuf>,
+
+    #[command(flatten)]
+    args: Args,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run a folder of test ROMs headless and compare their final displays
+    /// with reference bitmaps
+    Test(test_suite::TestArgs),
+    /// Print sizes, hashes and the instructions used by ROMs
+    Info(info::InfoArgs),
+    /// Disassemble a ROM into a listing with labels that can be assembled
+    /// again
+    Disasm(listing::DisasmArgs),
+    /// Check ROMs for likely bugs without running them
+    Lint(lint::LintArgs),
+    /// Draw the sprites in a ROM, as text or a PNG image
+    Sprites(sprites::SpritesArgs),
+    /// Draw a sprite in a ROM with the mouse
+    Edit(editor::EditArgs),
+}
+
+#[derive(clap::Args)]
 struct Args {
-    /// Path to ROM file
-    rom_path: String,
+    /// Path to ROM file, `-` to read it from standard input, or pick one
+    /// from --rom-dir if left out. For zip archives, the first .ch8 or .sc8
+    /// file in them is loaded, or another one named as `roms.zip#game.ch8`
+    rom_path: Option<String>,
+
+    /// Folder to pick a ROM from when none is given
+    #[arg(long, default_value = ".")]
+    rom_dir: PathBuf,
+
+    /// List the ROMs opened most recently and exit
+    #[arg(long)]
+    recent: bool,
+
+    /// Run full screen as a small console until the window is closed: the
+    /// ROMs in --rom-dir in a grid with a picture of each, demos of them
+    /// when left alone, and games resumed where they were left. In a game,
+    /// only the keypad, P and Escape, which goes back to the grid, work.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "rom_path", "recent", "debug", "bench", "hash_after", "from_dump",
+            "load_state", "record_input", "play_input", "host", "join",
+            "stream", "control_port", "no_resume", "patch", "record", "keypad_file",
+        ]
+    )]
+    console: bool,
+
+    /// IPS or BPS patch to apply to the ROM before running it
+    #[arg(long)]
+    patch: Option<PathBuf>,
+
+    /// Config file to use instead of the default one
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Instructions per second, overriding the config files
+    #[arg(long)]
+    speed: Option<u32>,
+
+    /// Colours to draw in, overriding the config files: classic,
+    /// inverted, high-contrast, blue-yellow or amber
+    #[arg(long, value_parser = Palette::named)]
+    palette: Option<Palette>,
+
+    /// Instructions per 60 Hz frame, like Octo's tickrate; the same as a
+    /// speed of 60 times as many
+    #[arg(long, value_name = "CYCLES", conflicts_with = "speed")]
+    cycles_per_frame: Option<u32>,
+
+    /// Address to load the ROM at and start it from, in hex, such as 600 for
+    /// the ETI-660
+    #[arg(long, default_value = "200", value_parser = parse_address)]
+    load_address: u16,
+
+    /// Font to use instead of the built-in one: 80 bytes of 5-byte digits,
+    /// optionally followed by 160 bytes of 10-byte big digits
+    #[arg(long)]
+    font_file: Option<PathBuf>,
+
+    /// Run in two-page hires mode with a 64x64 display, which is otherwise
+    /// picked for ROMs starting with the hires entry jump
+    #[arg(long)]
+    hires: bool,
+
+    /// Turn the picture clockwise by this many degrees: 0, 90, 180 or 270,
+    /// for screens mounted on their side and ROMs meant to be played that
+    /// way
+    #[arg(long, default_value_t = 0, value_parser = parse_rotation)]
+    rotate: u16,
+
+    /// Start with the CRT filter enabled (toggle with V)
+    #[arg(long)]
+    crt: bool,
+
+    /// Bend the CRT filter image like a curved tube
+    #[arg(long)]
+    crt_curvature: bool,
+
+    /// Let pixels fade out over a few frames instead of turning off at once
+    #[arg(long)]
+    phosphor: bool,
+
+    /// Wait for the monitor's vertical blank to show each frame, so the
+    /// picture doesn't tear
+    #[arg(long)]
+    vsync: bool,
+
+    /// Names for addresses, used by the debugger and in traced
+    /// instructions
+    #[arg(long, value_name = "FILE")]
+    symbols: Option<PathBuf>,
+
+    /// Cheats to apply every frame (toggle the first nine with Ctrl+1 to 9)
+    #[arg(long)]
+    cheats: Option<PathBuf>,
+
+    /// Read held keys from a file or named pipe written by another process
+    #[arg(long)]
+    keypad_file: Option<String>,
+
+    /// Record video and sound to this file through ffmpeg
+    #[arg(long)]
+    record: Option<PathBuf>,
+
+    /// Seed for the random number generator, for runs that can be repeated
+    /// exactly
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Print statistics about the session on exit, or on SIGUSR1
+    #[arg(long)]
+    stats: bool,
+
+    /// Run headless as fast as possible for this many seconds and report
+    /// the speed reached
+    #[arg(long, value_name = "SECONDS")]
+    bench: Option<f64>,
+
+    /// Run headless for this many instructions, then print the display hash
+    #[arg(long, value_name = "INSTRUCTIONS")]
+    hash_after: Option<u64>,
+
+    /// Run --bench and --hash-after from a cache of decoded straight-line
+    /// code, which is faster and gives the same results
+    #[arg(long)]
+    blocks: bool,
+
+    /// Don't look the ROM up in the CHIP-8 database
+    #[arg(long)]
+    no_db: bool,
+
+    /// Count how memory is used and save it as a heatmap image, a PNG, on
+    /// exit
+    #[arg(long, value_name = "FILE")]
+    heatmap: Option<PathBuf>,
+
+    /// Record the keys pressed to this input movie
+    #[arg(long, conflicts_with = "play_input")]
+    record_input: Option<PathBuf>,
+
+    /// Replay an input movie recorded with --record-input, ignoring the
+    /// keyboard until it ends
+    #[arg(long)]
+    play_input: Option<PathBuf>,
+
+    /// Step through the ROM in a debugger on the terminal instead of
+    /// running it in a window
+    #[arg(long)]
+    debug: bool,
+
+    /// Open a crash dump in the debugger, stopped at the instruction that
+    /// failed; no ROM is needed
+    #[arg(long, value_name = "FILE")]
+    from_dump: Option<PathBuf>,
+
+    /// Start from a save state of this ROM instead of from the beginning
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["record_input", "play_input", "host", "join"]
+    )]
+    load_state: Option<PathBuf>,
+
+    /// Save the machine on quit and carry on from there the next time this
+    /// ROM is run, also set with `resume = true` in the config file
+    #[arg(long, overrides_with = "no_resume")]
+    resume: bool,
+
+    /// Start from the beginning even if the config file asks to resume
+    #[arg(long, overrides_with = "resume")]
+    no_resume: bool,
+
+    /// Host a two-player game on this TCP port, waiting for the other player
+    /// to --join with the same ROM
+    #[arg(long, value_name = "PORT", conflicts_with_all = ["join", "record_input", "play_input", "keypad_file"])]
+    host: Option<u16>,
+
+    /// Join a two-player game hosted at this address, e.g. 192.168.1.5:7700
+    #[arg(long, value_name = "ADDRESS", conflicts_with_all = ["record_input", "play_input", "keypad_file"])]
+    join: Option<String>,
+
+    /// Serve a page on this port where others can watch the display live
+    #[arg(long, value_name = "PORT")]
+    stream: Option<u16>,
+
+    /// Address --host and --stream listen on; 0.0.0.0 lets in every network
+    #[arg(long, value_name = "ADDRESS", default_value = "127.0.0.1")]
+    listen: String,
+
+    /// Accept JSON commands from local programs on this TCP port
+    #[arg(long, value_name = "PORT")]
+    control_port: Option<u16>,
+
+    /// Directory for screenshots (F12), GIF recordings (F11) and crash dumps
+    #[arg(long, default_value = ".")]
+    screenshot_dir: PathBuf,
+
+    /// Mirror the display onto MAX7219 LED modules on this spidev device
+    #[cfg(feature = "max7219")]
+    #[arg(long)]
+    max7219: Option<String>,
 }
 
-fn main() {
-    let args = Args::parse();
+fn parse_address(hex: &str) -> Result<u16, String> {
+    let digits = hex.strip_prefix("0x").unwrap_or(hex);
+    u16::from_str_radix(digits, 16).map_err(|err| format!("invalid hex address: {}", err))
+}
 
-    let chip8 = Arc::new(Mutex::new(Chip8::from_file(args.rom_path)));
+fn parse_rotation(text: &str) -> Result<u16, String> {
+    match text.parse() {
+        Ok(degrees @ (0 | 90 | 180 | 270)) => Ok(degrees),
+        _ => Err("the rotation must be 0, 90, 180 or 270 degrees".into()),
+    }
+}
 
-    let clone = chip8.clone();
-    thread::spawn(move || {
-        let hz_time: f64 = 1.0 / 500.0;
-        loop {
-            let time = Instant::now();
-            {
-                let mut chip8 = clone.lock().unwrap();
-                let op = chip8.fetch();
-                chip8.execute(op);
+/// Reads the ROM at `rom_path`, applying `patch` if there is one, and works
+/// out its settings from the config files and the database
+fn open_rom(
+    args: &Args,
+    rom_path: &str,
+    patch: Option<&Path>,
+) -> Result<(Vec<u8>, config::Settings), Chip8Error> {
+    let mut rom = read_rom(rom_path)?;
+    if let Some(path) = patch {
+        rom = patch::apply(&rom, path).map_err(Chip8Error::Patch)?;
+    }
+    for warning in chip8::rom::validate(&rom, args.load_address)? {
+        log::warn!("Warning: {}", warning);
+    }
+    if let Some((op, address)) = detect::super_chip(&rom) {
+        log::warn!(
+            "Warning: the ROM reached SUPER-CHIP instruction {:04X} at {:03X} when it last ran, \
+             and there is no SUPER-CHIP mode to run it in",
+            op,
+            address
+        );
+    }
+    if Path::new(rom_path).is_file() {
+        if let Err(err) = recent::add(Path::new(rom_path)) {
+            log::warn!("Cannot update the recent ROMs list: {}", err);
+        }
+    }
+
+    let entry = if args.no_db {
+        None
+    } else {
+        database::lookup(&sha1::hex(&sha1::sha1(&rom))).unwrap_or_else(|err| {
+            log::warn!("Cannot use the CHIP-8 database: {}", err);
+            None
+        })
+    };
+    if let Some(entry) = &entry {
+        log::info!("Found {} in the CHIP-8 database", entry.title);
+        match &entry.platform {
+            Some(platform) if !database::SUPPORTED_PLATFORMS.contains(&platform.as_str()) => {
+                log::warn!(
+                    "Warning: the database lists this ROM for {}, which isn't supported",
+                    platform
+                )
             }
-            thread::sleep(Duration::from_secs_f64(hz_time) - time.elapsed())
+            _ => (),
         }
-    });
+        for missing in &entry.missing {
+            log::warn!(
+                "Warning: the database says this ROM needs {}, which isn't supported",
+                missing
+            );
+        }
+    }
+    let settings = config::load(args.config.as_deref(), rom_path, entry.as_ref())
+        .map_err(Chip8Error::Config)?;
+    Ok((rom, settings))
+}
 
-    let clone = chip8.clone();
-    thread::spawn(move || {
-        let hz_time: f64 = 1.0 / 60.0;
-        loop {
-            let time = Instant::now();
-            {
-                let mut chip8 = clone.lock().unwrap();
-                if chip8.delay_timer > 0 {
-                    chip8.delay_timer -= 1;
-                }
-                if chip8.sound_timer > 0 {
-                    if chip8.sound_timer == 1 {
-                        // BEEP
-                    }
-                    chip8.sound_timer -= 1;
+/// Instructions per second, from the command line or else the settings
+fn session_speed(args: &Args, settings: &config::Settings) -> u32 {
+    args.speed
+        .or(args
+            .cycles_per_frame
+            .map(|cycles| cycles.saturating_mul(60)))
+        .unwrap_or(settings.speed)
+}
+
+/// The machine for `rom`, set up by the command line and `settings`
+fn build_machine(
+    args: &Args,
+    rom: &[u8],
+    settings: &config::Settings,
+    speed: u32,
+    flags_path: Option<&Path>,
+    seed: Option<u64>,
+) -> Result<Chip8, Chip8Error> {
+    let mut builder = Chip8::builder()
+        .with_program_at(args.load_address, rom)
+        .with_memory_policy(settings.memory_policy)
+        .with_quirks(settings.quirks)
+        .with_flags(flags_path.map_or([0; 8], flags::load));
+    if let Some(seed) = seed {
+        builder = builder.with_seed(seed);
+    }
+    if let Some(path) = &args.font_file {
+        builder = builder.with_font(&read_font(path)?);
+    }
+    if args.hires || chip8::rom::is_hires(rom) {
+        builder = builder.with_hires();
+    }
+    builder.with_speed(speed).build()
+}
+
+/// What `build_machine` makes of everything but the speed and seed, for
+/// netplay to check both sides agree on
+fn netplay_setup(
+    args: &Args,
+    settings: &config::Settings,
+    rom: &[u8],
+) -> Result<netplay::Setup, Chip8Error> {
+    Ok(netplay::Setup {
+        quirks: settings.quirks,
+        memory_policy: settings.memory_policy,
+        load_address: args.load_address,
+        hires: args.hires || chip8::rom::is_hires(rom),
+        font: match &args.font_file {
+            Some(path) => Some(png::crc32(&read_font(path)?)),
+            None => None,
+        },
+    })
+}
+
+/// Where to save `rom` on quit. Resuming into netplay or an input movie
+/// would throw them off, and an explicit state wins.
+fn resume_file(
+    args: &Args,
+    settings: &config::Settings,
+    rom: &[u8],
+    netplay: bool,
+) -> Option<PathBuf> {
+    (!args.no_resume && (args.resume || args.console || settings.resume))
+        .then(|| savestate::resume_path(rom))
+        .flatten()
+        .filter(|_| {
+            !netplay
+                && args.load_state.is_none()
+                && args.record_input.is_none()
+                && args.play_input.is_none()
+        })
+}
+
+fn read_font(path: &Path) -> Result<Vec<u8>, Chip8Error> {
+    let font = std::fs::read(path)
+        .map_err(|err| Chip8Error::Font(format!("Cannot read font {}: {}", path.display(), err)))?;
+    if font.len() != 80 && font.len() != 240 {
+        return Err(Chip8Error::Font(format!(
+            "Font {} is {} bytes, expected 80, or 240 with big digits",
+            path.display(),
+            font.len()
+        )));
+    }
+    Ok(font)
+}
+
+/// Reads the ROM from a file, standard input for `-`, or a URL with the
+/// `http` feature, unpacking it if it's zipped
+fn read_rom(rom_path: &str) -> Result<Vec<u8>, Chip8Error> {
+    let (source, entry) = match rom_path.rsplit_once('#') {
+        Some((archive, entry)) if archive.to_ascii_lowercase().ends_with(".zip") => {
+            (archive, Some(entry))
+        }
+        _ => (rom_path, None),
+    };
+    let data = read_source(source)?;
+    if zip::is_zip(&data) {
+        return zip::extract(&data, entry).map_err(Chip8Error::Archive);
+    }
+    Ok(data)
+}
+
+fn read_source(rom_path: &str) -> Result<Vec<u8>, Chip8Error> {
+    if rom_path == "-" {
+        let mut rom = Vec::new();
+        std::io::stdin()
+            .read_to_end(&mut rom)
+            .map_err(|source| Chip8Error::Io {
+                path: "standard input".into(),
+                source,
+            })?;
+        return Ok(rom);
+    }
+    if rom_path.starts_with("http://") || rom_path.starts_with("https://") {
+        #[cfg(feature = "http")]
+        return download::fetch(rom_path);
+        #[cfg(not(feature = "http"))]
+        return Err(Chip8Error::Download(
+            "Loading ROMs from URLs needs a build with the http feature".into(),
+        ));
+    }
+    chip8::rom::read(rom_path)
+}
+
+fn main() {
+    let cli = Cli::parse();
+    if let Err(err) = log::init(cli.verbose, cli.log_file.as_deref()) {
+        eprintln!("Cannot create the log file: {}", err);
+        std::process::exit(1);
+    }
+    let result = match cli.command {
+        Some(Command::Test(args)) => test_suite::run(args),
+        Some(Command::Info(args)) => info::run(args),
+        Some(Command::Disasm(args)) => listing::run(args),
+        Some(Command::Lint(args)) => lint::run(args),
+        Some(Command::Sprites(args)) => sprites::run(args),
+        Some(Command::Edit(args)) => editor::run(args),
+        None => run(cli.args),
+    };
+    match result {
+        // 0000 ends the program, and with it the emulator
+        Ok(()) | Err(Chip8Error::Exit { .. }) => (),
+        Err(err) => {
+            log::error!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Handles events as they come in until `deadline`, when the next frame is
+/// due, rather than spinning. Returns `None` once it is time for the frame
+/// and no events are left.
+fn next_event(events: &mut EventPump, deadline: Instant) -> Option<Event> {
+    let wait = deadline.saturating_duration_since(Instant::now());
+    // Round up, so running out of events means the deadline has passed
+    match wait.as_micros().div_ceil(1000) {
+        0 => events.poll_event(),
+        wait => events.wait_event_timeout(wait as u32),
+    }
+}
+
+/// SDL reports most errors as plain strings
+fn sdl_error(err: impl fmt::Display) -> Chip8Error {
+    Chip8Error::Frontend(err.to_string())
+}
+
+fn run(args: Args) -> Result<(), Chip8Error> {
+    if args.recent {
+        for rom in recent::load() {
+            println!("{}", rom.display());
+        }
+        return Ok(());
+    }
+    let symbols = match &args.symbols {
+        Some(path) => symbols::Symbols::read(path).map_err(Chip8Error::Symbols)?,
+        None => symbols::Symbols::default(),
+    };
+    if let Some(path) = &args.from_dump {
+        return debugger::run(crash::read(path)?, args.speed.unwrap_or(500), symbols);
+    }
+    if args.console {
+        return console::run(&args);
+    }
+    let mut sdl = None;
+    let rom_path = match args.rom_path.clone() {
+        Some(rom_path) => rom_path,
+        None => {
+            let context = sdl2::init().map_err(sdl_error)?;
+            match browser::choose(&context, &args.rom_dir, &recent::load())? {
+                Some(path) => {
+                    sdl = Some(context);
+                    path.to_string_lossy().into_owned()
                 }
+                None => return Ok(()),
             }
-            thread::sleep(Duration::from_secs_f64(hz_time) - time.elapsed())
         }
+    };
+    play(&args, rom_path, sdl.as_ref(), symbols).map(|_| ())
+}
+
+/// How a game in the window ended, other than by failing
+enum Ending {
+    /// Without a window, in one of the headless modes
+    Done,
+    /// With Escape
+    Left,
+    /// With the window closed
+    Closed,
+}
+
+/// Runs the ROM at `rom_path`, in a window unless the command line asks for
+/// a headless mode, with `sdl` or else its own SDL context
+fn play(
+    args: &Args,
+    mut rom_path: String,
+    sdl: Option<&Sdl>,
+    symbols: symbols::Symbols,
+) -> Result<Ending, Chip8Error> {
+    let (mut rom, settings) = open_rom(args, &rom_path, args.patch.as_deref())?;
+    let speed = session_speed(args, &settings);
+    let mut palette = args.palette.unwrap_or(settings.palette);
+    let mut netplay = match (args.host, &args.join) {
+        (Some(port), _) => Some(netplay::host(
+            &args.listen,
+            port,
+            &rom,
+            args.seed.unwrap_or_else(rand::random),
+            speed,
+            netplay_setup(args, &settings, &rom)?,
+        )?),
+        (None, Some(address)) => Some(netplay::join(
+            address,
+            &rom,
+            netplay_setup(args, &settings, &rom)?,
+        )?),
+        (None, None) => None,
+    };
+    // Both players' machines have to start the same, so saved high scores
+    // are left out
+    let mut flags_path = match netplay {
+        Some(_) => None,
+        None => flags::path(&rom),
+    };
+    let speed = netplay
+        .as_ref()
+        .map_or(speed, |connection| connection.speed);
+    log::debug!(
+        "Running {} at {} instructions per second with {:?}",
+        rom_path,
+        speed,
+        settings.quirks
+    );
+    let seed = netplay
+        .as_ref()
+        .map(|connection| connection.seed)
+        .or(args.seed);
+    let mut chip8 = build_machine(args, &rom, &settings, speed, flags_path.as_deref(), seed)?;
+    let mut loaded = false;
+    if let Some(path) = &args.load_state {
+        chip8 = savestate::read(path, &rom)?;
+        loaded = true;
+    }
+    let mut resume_path = resume_file(args, &settings, &rom, netplay.is_some());
+    if let Some(path) = resume_path.as_ref().filter(|path| path.exists()) {
+        match savestate::read(path, &rom) {
+            Ok(resumed) => {
+                chip8 = resumed;
+                loaded = true;
+                println!("Resuming where you left off (--no-resume starts over)");
+            }
+            Err(err) => log::warn!("Cannot resume: {}", err),
+        }
+    }
+    // A state carries the speed it was saved at, like its quirks, unless
+    // the speed was given here or agreed with the other player
+    let speed =
+        if loaded && netplay.is_none() && args.speed.is_none() && args.cycles_per_frame.is_none() {
+            chip8.speed
+        } else {
+            speed
+        };
+    chip8.speed = speed;
+    if let Some(instructions) = args.hash_after {
+        if args.blocks {
+            headless::run_blocks(&mut chip8, instructions, speed)?;
+        } else {
+            headless::run(&mut chip8, instructions, speed, |_| ())?;
+        }
+        println!("{:016x}", chip8.display_hash());
+        return Ok(Ending::Done);
+    }
+    if let Some(seconds) = args.bench {
+        return bench::run(chip8, seconds, args.blocks).map(|()| Ending::Done);
+    }
+    if args.debug {
+        return debugger::run(chip8, speed, symbols).map(|()| Ending::Done);
+    }
+    let rows = chip8.height() as u32;
+    let keypad_keys = args.keypad_file.clone().map(keypad_file::spawn);
+
+    let mut publisher = frame::Publisher::new(&chip8);
+
+    #[cfg(feature = "max7219")]
+    if let Some(device) = &args.max7219 {
+        max7219::spawn(device.clone(), publisher.shared());
+    }
+
+    let mut input_recorder = match &args.record_input {
+        Some(path) => Some(movie::Recorder::create(path).map_err(|err| {
+            Chip8Error::Movie(format!(
+                "Cannot create input movie {}: {}",
+                path.display(),
+                err
+            ))
+        })?),
+        None => None,
+    };
+    let mut input_player = match &args.play_input {
+        Some(path) => Some(movie::Player::open(path).map_err(Chip8Error::Movie)?),
+        None => None,
+    };
+
+    let cheats = match &args.cheats {
+        Some(path) => cheats::load(path).map_err(Chip8Error::Cheats)?,
+        None => Vec::new(),
+    };
+
+    if let Some(port) = args.stream {
+        stream::spawn(&args.listen, port, publisher.shared(), palette).map_err(|err| {
+            Chip8Error::Frontend(format!("Cannot listen on port {}: {}", port, err))
+        })?;
+    }
+
+    let mut control = match args.control_port {
+        Some(port) => {
+            let requests = control::spawn(port).map_err(|err| {
+                Chip8Error::Frontend(format!("Cannot listen on port {}: {}", port, err))
+            })?;
+            let session = control::Session {
+                settings,
+                rom: rom.clone(),
+                rom_path: rom_path.clone(),
+                screenshot_dir: args.screenshot_dir.clone(),
+            };
+            Some((requests, session))
+        }
+        None => None,
+    };
+
+    let mut stats = args.stats.then(|| {
+        stats::listen_for_signal();
+        stats::Stats::new()
+    });
+
+    let mut watchdog = halt::Watchdog::new(speed);
+    let mut per_frame = speed as f64 / 60.0;
+
+    let mut recording = args.record.as_ref().and_then(|path| {
+        video::Recording::start(path, publisher.shared(), palette)
+            .map_err(|err| log::warn!("Cannot start ffmpeg: {}", err))
+            .ok()
     });
 
-    let sdl = sdl2::init().unwrap();
-    let video = sdl.video().unwrap();
-    let window = video
-        .window("CHIP-8", 64 * 10, 32 * 10)
-        .opengl()
-        .resizable()
-        .build()
-        .unwrap();
-    let mut canvas = window.into_canvas().build().unwrap();
-    let mut events = sdl.event_pump().unwrap();
+    let context;
+    let sdl = match sdl {
+        Some(sdl) => sdl,
+        None => {
+            context = sdl2::init().map_err(sdl_error)?;
+            &context
+        }
+    };
+    let video = sdl.video().map_err(sdl_error)?;
+    // The display's width and height on screen
+    let (across, down) = match args.rotate {
+        90 | 270 => (rows, 64),
+        _ => (64, rows),
+    };
+    let mut window = video.window("CHIP-8", across * 10, down * 10);
+    window.opengl().resizable();
+    if args.console {
+        window.fullscreen_desktop();
+        sdl.mouse().show_cursor(false);
+    }
+    let window = window.build().map_err(sdl_error)?;
+    let mut canvas = window.into_canvas();
+    if args.vsync {
+        canvas = canvas.present_vsync();
+    }
+    let mut canvas = canvas.build().map_err(sdl_error)?;
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGB24, 64, rows)
+        .map_err(sdl_error)?;
+    let mut crt_texture = None;
+    let mut crt = args.crt;
+    let mut levels = vec![0.0; 64 * rows as usize];
+    let mut last_frame = Instant::now();
+    let mut display = vec![false; 64 * rows as usize];
+    let mut redraw = true;
+    let mut gif_recording: Option<gif::Recorder> = None;
+    let mut show_speed = false;
+    let mut halt_shown = false;
+    let mut speed = overlay::Speed::new(0);
+    let mut speed_text = Vec::new();
+    let mut show_sprites = false;
+    // I and the memory after it, as last drawn by the sprite overlay
+    let mut sprite_memory = (0, Vec::new());
+    let mut show_hud = false;
+    let mut hud_text = Vec::new();
+    let mut show_keypad = false;
+    // Frames left to mark each key as read by EX9E, EXA1 or FX0A
+    let mut polls = [0u8; 16];
+    // The pressed and polled keys as last drawn by the keypad overlay
+    let mut keypad_shown = ([false; 16], [false; 16]);
+    let mut rom_name = screenshot::rom_name(&rom_path);
+    let mut events = sdl.event_pump().map_err(sdl_error)?;
+    // Keys pressed here in netplay, since the machine holds both players'
+    let mut local_keys = 0u16;
+    let mut key_queue = input::KeyQueue::new();
+    // When SDL started counting the milliseconds its events are stamped with
+    let ticks = Duration::from_millis(sdl.timer().map_err(sdl_error)?.ticks() as u64);
+    let sdl_epoch = Instant::now()
+        .checked_sub(ticks)
+        .unwrap_or_else(Instant::now);
+    let mut paused = false;
+    let mut halted = false;
+    // Instructions due but not yet run, as speed is rarely a multiple of 60
+    let mut owed = 0.0;
+    // The keys when FX0A last found none pressed
+    let mut blocked_keys = [false; 16];
+    let mut history = history::History::default();
+    let mut heatmap = args.heatmap.as_deref().map(heatmap::Heatmap::new);
+    let mut code = log::enabled(log::Level::Trace).then(selfmod::CodeTracker::new);
+    let mut scheduler = pacing::Scheduler::new(60.0, Instant::now());
+    let mut debug_window: Option<debug_window::DebugWindow> = None;
     loop {
-        for event in events.poll_iter() {
+        while let Some(event) = next_event(&mut events, scheduler.deadline()) {
+            if debug_window
+                .as_mut()
+                .is_some_and(|window| window.handle(&event))
+            {
+                debug_window = None;
+                continue;
+            }
+            // With the debugger open, closing the game doesn't end in
+            // Event::Quit, as another window is left
+            let event = match event {
+                Event::Window {
+                    win_event: WindowEvent::Close,
+                    timestamp,
+                    ..
+                } => Event::Quit { timestamp },
+                event => event,
+            };
             match event {
                 Event::Quit { .. }
                 | Event::KeyDown {
                     keycode: Some(Keycode::Escape),
                     ..
-                } => std::process::exit(0),
+                } => {
+                    if let Some(recording) = recording.take() {
+                        recording.finish();
+                    }
+                    if let Some(stats) = &stats {
+                        print!("{}", stats.report());
+                    }
+                    if let Some(heatmap) = &heatmap {
+                        heatmap.save();
+                    }
+                    if let Some(path) = &resume_path {
+                        if let Err(err) = savestate::write(path, &chip8, &rom) {
+                            log::warn!("Cannot save state to {}: {}", path.display(), err);
+                        }
+                    }
+                    return Ok(match event {
+                        Event::Quit { .. } => Ending::Closed,
+                        _ => Ending::Left,
+                    });
+                }
+                // A console only takes the keypad, pausing and leaving the
+                // game, so players can't get at files, cheats or the debugger
+                Event::KeyDown {
+                    keycode: Some(key),
+                    keymod,
+                    ..
+                } if args.console
+                    && key != Keycode::P
+                    && (key_code(key).is_none()
+                        || keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD)) => {}
+                Event::KeyDown {
+                    keycode: Some(Keycode::V),
+                    ..
+                } => {
+                    crt = !crt;
+                    redraw = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F12),
+                    ..
+                } => {
+                    match screenshot::save(
+                        &args.screenshot_dir,
+                        &rom_path,
+                        &chip8.screen(),
+                        &palette,
+                    ) {
+                        Ok(path) => println!("Saved screenshot to {}", path.display()),
+                        Err(err) => log::warn!("Cannot save screenshot: {}", err),
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F11),
+                    ..
+                } => match gif_recording.take() {
+                    Some(recorder) => {
+                        let path = screenshot::output_path(&args.screenshot_dir, &rom_path, "gif");
+                        // Encoding takes a moment, so don't stall the display
+                        thread::spawn(move || {
+                            match path.and_then(|path| {
+                                recorder.finish(&path)?;
+                                Ok(path)
+                            }) {
+                                Ok(path) => println!("Saved recording to {}", path.display()),
+                                Err(err) => log::warn!("Cannot save recording: {}", err),
+                            }
+                        });
+                    }
+                    None => {
+                        println!("Recording started, press F11 again to stop");
+                        gif_recording = Some(gif::Recorder::start(&display, palette));
+                    }
+                },
+                // The other player's machine would keep going
+                Event::KeyDown {
+                    keycode: Some(Keycode::P),
+                    ..
+                } if netplay.is_none() => {
+                    paused = !paused;
+                    halted = false;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::O),
+                    ..
+                } => {
+                    show_speed = !show_speed;
+                    redraw = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::S),
+                    ..
+                } => {
+                    show_sprites = !show_sprites;
+                    redraw = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::H),
+                    ..
+                } => {
+                    show_hud = !show_hud;
+                    redraw = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::K),
+                    ..
+                } => {
+                    show_keypad = !show_keypad;
+                    redraw = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::W),
+                    ..
+                } => {
+                    debug_window = match debug_window.take() {
+                        Some(_) => None,
+                        None => debug_window::DebugWindow::open(&video)
+                            .map_err(|err| log::warn!("Cannot open the debugger window: {}", err))
+                            .ok(),
+                    };
+                }
+                Event::Window { .. } => redraw = true,
+                Event::KeyDown {
+                    keycode: Some(key),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD)
+                    && key_code(key).is_some_and(|code| (1..=9).contains(&code)) =>
+                {
+                    let index = key_code(key).unwrap_or_default() - 1;
+                    if let Some(cheat) = cheats.get(index) {
+                        let enabled = !cheat.enabled.fetch_xor(true, Ordering::Relaxed);
+                        let state = if enabled { "on" } else { "off" };
+                        println!("Cheat {} {}", cheat.name, state);
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(key),
+                    keymod,
+                    ..
+                } if state_slot(key).is_some() => {
+                    let slot = state_slot(key).unwrap_or_default();
+                    let saving = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                    if netplay.is_some() || input_player.is_some() || input_recorder.is_some() {
+                        log::warn!("Save states can't be used in netplay or with input movies");
+                    } else if let Some(path) = savestate::slot_path(&rom, slot) {
+                        if saving {
+                            match savestate::write(&path, &chip8, &rom) {
+                                Ok(()) => println!("Saved state to slot {}", slot),
+                                Err(err) => {
+                                    log::warn!("Cannot save state to {}: {}", path.display(), err)
+                                }
+                            }
+                        } else if !path.exists() {
+                            println!("Slot {} is empty (Shift+F{} saves to it)", slot, slot);
+                        } else {
+                            match savestate::read(&path, &rom) {
+                                Ok(restored) if restored.height() == chip8.height() => {
+                                    chip8 = restored;
+                                    watchdog.reset();
+                                    println!("Loaded state from slot {}", slot);
+                                }
+                                Ok(_) => log::warn!(
+                                    "The state in slot {} needs a display of another size",
+                                    slot
+                                ),
+                                Err(err) => log::warn!("{}", err),
+                            }
+                        }
+                    }
+                }
                 Event::KeyDown {
-                    keycode: Some(key), ..
+                    keycode: Some(key),
+                    timestamp,
+                    ..
                 } => {
                     if let Some(key_code) = key_code(key) {
-                        chip8.lock().unwrap().keys[key_code] = true;
+                        if netplay.is_some() {
+                            local_keys |= 1 << key_code;
+                        } else {
+                            let time = sdl_epoch + Duration::from_millis(timestamp as u64);
+                            key_queue.push(time, key_code, true);
+                        }
                     }
                 }
                 Event::KeyUp {
-                    keycode: Some(key), ..
+                    keycode: Some(key),
+                    timestamp,
+                    ..
                 } => {
                     if let Some(key_code) = key_code(key) {
-                        chip8.lock().unwrap().keys[key_code] = false;
+                        if netplay.is_some() {
+                            local_keys &= !(1 << key_code);
+                        } else {
+                            let time = sdl_epoch + Duration::from_millis(timestamp as u64);
+                            key_queue.push(time, key_code, false);
+                        }
                     }
                 }
                 _ => (),
             }
         }
 
+        let frames = scheduler.due(Instant::now());
+        if frames == 0 {
+            continue;
+        }
+
+        if let Some(keys) = &keypad_keys {
+            for (time, key, down) in keys.try_iter() {
+                key_queue.push(time, key, down);
+            }
+        }
+        key_queue.start_frame(&mut chip8);
+        // Loading a ROM or a state makes a new machine, which doesn't record
+        if heatmap.is_some() || code.is_some() {
+            chip8.accesses.get_or_insert_with(Vec::new);
+        }
+        if let Some((requests, session)) = &mut control {
+            let locked = netplay.is_some() || input_player.is_some() || input_recorder.is_some();
+            for request in requests.try_iter() {
+                let load = match control::handle(request, session, &mut chip8, &mut paused, locked)
+                {
+                    control::Handled::Answered => continue,
+                    control::Handled::Restarted => {
+                        watchdog = halt::Watchdog::new(chip8.speed);
+                        per_frame = chip8.speed as f64 / 60.0;
+                        owed = 0.0;
+                        history = history::History::default();
+                        continue;
+                    }
+                    control::Handled::Load(load) => load,
+                };
+                // Everything named after the ROM goes with it, and the new
+                // one starts as it would from the command line
+                let result = (|| {
+                    let (new_rom, new_settings) =
+                        open_rom(args, &load.path, None).map_err(|err| err.to_string())?;
+                    let new_flags_path = flags::path(&new_rom);
+                    let speed = session_speed(args, &new_settings);
+                    let mut machine = build_machine(
+                        args,
+                        &new_rom,
+                        &new_settings,
+                        speed,
+                        new_flags_path.as_deref(),
+                        args.seed,
+                    )
+                    .map_err(|err| err.to_string())?;
+                    let new_resume_path = resume_file(args, &new_settings, &new_rom, false);
+                    if let Some(path) = new_resume_path.as_ref().filter(|path| path.exists()) {
+                        match savestate::read(path, &new_rom) {
+                            Ok(resumed) => machine = resumed,
+                            Err(err) => log::warn!("Cannot resume: {}", err),
+                        }
+                    }
+                    if args.speed.is_some() || args.cycles_per_frame.is_some() {
+                        machine.speed = speed;
+                    }
+                    if machine.height() != chip8.height() {
+                        return Err("the ROM needs a display of another size; start it from \
+                                    the command line"
+                            .into());
+                    }
+                    if let Some(path) = &resume_path {
+                        if let Err(err) = savestate::write(path, &chip8, &rom) {
+                            log::warn!("Cannot save state to {}: {}", path.display(), err);
+                        }
+                    }
+                    control::replace(&mut chip8, machine)?;
+                    watchdog = halt::Watchdog::new(chip8.speed);
+                    per_frame = chip8.speed as f64 / 60.0;
+                    owed = 0.0;
+                    history = history::History::default();
+                    palette = args.palette.unwrap_or(new_settings.palette);
+                    rom_name = screenshot::rom_name(&load.path);
+                    rom_path = load.path.clone();
+                    rom = new_rom;
+                    flags_path = new_flags_path;
+                    resume_path = new_resume_path;
+                    session.rom = rom.clone();
+                    session.rom_path = rom_path.clone();
+                    session.settings = new_settings;
+                    println!("Loaded {}", rom_path);
+                    Ok(())
+                })();
+                load.finish(result);
+            }
+        }
+        for _ in 0..frames {
+            if let Some(connection) = &mut netplay {
+                connection.sync(&mut chip8, local_keys)?;
+            }
+
+            if !paused {
+                owed += per_frame;
+                let due = owed.floor();
+                let mut executed = 0.0;
+                while owed >= 1.0 {
+                    owed -= 1.0;
+                    key_queue.apply(&mut chip8, executed / due);
+                    executed += 1.0;
+                    if let Some(player) = &mut input_player {
+                        player.apply(&mut chip8);
+                        if player.finished() {
+                            println!("Input movie finished, keyboard input is live again");
+                            input_player = None;
+                        }
+                    }
+                    if let Some(recorder) = &mut input_recorder {
+                        if let Err(err) = recorder.record(&chip8) {
+                            log::warn!("Cannot write input movie: {}", err);
+                            input_recorder = None;
+                        }
+                    }
+                    // Rather than running FX0A over and over, wait for the keys
+                    // to change, unless a movie is pressing them
+                    if chip8.blocked_on_key
+                        && input_player.is_none()
+                        && chip8.keys == blocked_keys
+                        && !key_queue.apply_next(&mut chip8)
+                    {
+                        owed = 0.0;
+                        break;
+                    }
+                    let result = chip8.fetch().and_then(|op| {
+                        history.record(chip8.program_counter.wrapping_sub(2), op, &chip8);
+                        // Pausing would leave the other player waiting
+                        if netplay.is_none() && watchdog.check(&chip8, op) {
+                            println!(
+                                "Program halted at {:03X}, pausing (press P to resume)",
+                                chip8.program_counter.wrapping_sub(2)
+                            );
+                            watchdog.reset();
+                            halted = true;
+                            paused = true;
+                        }
+                        if let Some(stats) = &mut stats {
+                            stats.count(chip8.program_counter.wrapping_sub(2), op);
+                        }
+                        if show_keypad {
+                            match Instruction::decode(op) {
+                                Some(Instruction::SkipIfKey(x) | Instruction::SkipIfNotKey(x)) => {
+                                    polls[chip8.registers[x] as usize & 0xF] = POLL_FRAMES;
+                                }
+                                Some(Instruction::WaitKey(_)) => polls = [POLL_FRAMES; 16],
+                                _ => (),
+                            }
+                        }
+                        log::trace!(
+                            "{} {:04X} {}",
+                            symbols.describe(chip8.program_counter.wrapping_sub(2)),
+                            op,
+                            Instruction::decode(op).map_or("unknown".into(), |op| op.to_string())
+                        );
+                        let result = chip8.execute(op);
+                        if let Some(accesses) = &mut chip8.accesses {
+                            let address = chip8.program_counter.wrapping_sub(2);
+                            if let Some(heatmap) = &mut heatmap {
+                                heatmap.record(address, op, accesses);
+                            }
+                            if let Some(target) =
+                                code.as_mut().and_then(|code| code.check(address, accesses))
+                            {
+                                log::trace!(
+                                    "Self-modifying code: {:03X} wrote to {:03X}, which ran before",
+                                    address,
+                                    target
+                                );
+                            }
+                            accesses.clear();
+                        }
+                        result
+                    });
+                    if let Err(err) = result {
+                        // Code the ROM check couldn't see, remembered so the
+                        // next load warns before it runs
+                        if let Chip8Error::SuperChipInstruction { op, address } = err {
+                            if let Err(err) = detect::record_super_chip(&rom, op, address) {
+                                log::warn!("Cannot remember the ROM needs SUPER-CHIP: {}", err);
+                            }
+                        }
+                        if let Some(stats) = &stats {
+                            print!("{}", stats.report());
+                        }
+                        if let Some(heatmap) = &heatmap {
+                            heatmap.save();
+                        }
+                        // Neither is a fault worth a crash dump
+                        if let Chip8Error::Exit { .. } | Chip8Error::SuperChipInstruction { .. } =
+                            err
+                        {
+                            return Err(err);
+                        }
+                        let dumped = crash::write(
+                            &args.screenshot_dir,
+                            &rom_path,
+                            &err,
+                            &mut chip8,
+                            &mut history,
+                        );
+                        match dumped {
+                            Ok(path) => println!("Wrote a crash dump to {}", path.display()),
+                            Err(err) => log::warn!("Cannot write a crash dump: {}", err),
+                        }
+                        return Err(err);
+                    }
+                    if chip8.blocked_on_key {
+                        blocked_keys = chip8.keys;
+                    }
+                    if paused {
+                        break;
+                    }
+                }
+                chip8.tick_timers();
+                if let Some(stats) = &mut stats {
+                    stats.ticks += 1;
+                }
+                // Both players' machines have to see the same memory
+                if netplay.is_none() {
+                    for cheat in &cheats {
+                        cheat.apply(&mut chip8);
+                    }
+                }
+            }
+        }
+        key_queue.finish_frame(&mut chip8);
+
+        let display_changed = chip8.display_dirty;
+        if display_changed {
+            chip8.display_dirty = false;
+            display.copy_from_slice(&chip8.screen());
+            redraw = true;
+        }
+        publisher.publish(&chip8, display_changed);
+        if chip8.flags_dirty {
+            chip8.flags_dirty = false;
+            if let Some(path) = &flags_path {
+                if let Err(err) = flags::save(path, &chip8.flags) {
+                    log::warn!("Cannot save flag registers to {}: {}", path.display(), err);
+                }
+            }
+        }
+        if let Some(stats) = stats.as_ref().filter(|_| stats::report_requested()) {
+            print!("{}", stats.report());
+        }
+        speed.update(chip8.instructions);
+        let mut title = vec![rom_name.clone(), format!("{} IPS", speed.ips)];
+        if paused {
+            title.push("paused".into());
+        }
+        if recording.is_some() || gif_recording.is_some() {
+            title.push("recording".into());
+        }
+        title.push("CHIP-8".into());
+        let title = title.join(" - ");
+        if canvas.window().title() != title {
+            canvas.window_mut().set_title(&title).map_err(sdl_error)?;
+        }
+        if show_speed {
+            let mut text = vec![
+                format!("FPS {}", speed.fps),
+                format!("IPS {}", speed.ips),
+                format!("DT {:3} ST {:3}", chip8.delay_timer, chip8.sound_timer),
+            ];
+            let hotspots = stats.as_ref().map(|stats| stats.hotspots(3));
+            for (address, _, share) in hotspots.into_iter().flatten() {
+                text.push(format!("HOT {:03X} {:3.0}%", address, share * 100.0));
+            }
+            if text != speed_text {
+                speed_text = text;
+                redraw = true;
+            }
+        }
+        if show_sprites {
+            let bytes = (0..SPRITE_BYTES)
+                .map(|n| chip8.peek(chip8.index.wrapping_add(n)))
+                .collect();
+            let memory = (chip8.index, bytes);
+            if memory != sprite_memory {
+                sprite_memory = memory;
+                redraw = true;
+            }
+        }
+        if show_hud {
+            let text = debug_window::registers(&chip8);
+            if text != hud_text {
+                hud_text = text;
+                redraw = true;
+            }
+        }
+        if show_keypad {
+            // FX0A isn't run again while it waits
+            if chip8.blocked_on_key {
+                polls = [POLL_FRAMES; 16];
+            }
+            let polled = polls.map(|frames| frames > 0);
+            for frames in polls.iter_mut() {
+                *frames = frames.saturating_sub(1);
+            }
+            if (chip8.keys, polled) != keypad_shown {
+                keypad_shown = (chip8.keys, polled);
+                redraw = true;
+            }
+        }
+        if halted != halt_shown {
+            halt_shown = !halt_shown;
+            redraw = true;
+        }
+        if let Some(recorder) = &mut gif_recording {
+            recorder.capture(&display);
+        }
+        if let Some(window) = &mut debug_window {
+            window.draw(&chip8).map_err(sdl_error)?;
+        }
+        let fade = if args.phosphor {
+            0.5f32.powf(last_frame.elapsed().as_secs_f32() / PHOSPHOR_HALF_LIFE)
+        } else {
+            0.0
+        };
+        last_frame = Instant::now();
+        for (level, &pixel) in levels.iter_mut().zip(display.iter()) {
+            *level = if pixel {
+                1.0
+            } else if *level * fade < 1.0 / 255.0 {
+                0.0
+            } else {
+                *level * fade
+            };
+        }
+        let fading = levels.iter().any(|&level| level > 0.0 && level < 1.0);
+        if !redraw && !fading {
+            continue;
+        }
+        let _span = log::Span::new("frame");
+
         canvas.set_draw_color(Color::RGB(0, 0, 0));
         canvas.clear();
-        canvas.set_draw_color(Color::RGB(255, 255, 255));
-        let display = chip8.lock().unwrap().display;
-        let pixel_width = canvas.window().drawable_size().0 / 64;
-        let pixel_height = canvas.window().drawable_size().1 / 32;
-        for (i, _) in display.iter().enumerate().filter(|(_, pixel)| **pixel) {
-            let x = (i % 64) as i32;
-            let y = (i / 64) as i32;
-            let rect = Rect::new(
-                x * pixel_width as i32,
-                y * pixel_height as i32,
-                pixel_width,
-                pixel_height,
-            );
-            canvas.fill_rect(rect).unwrap();
+        let (window_width, window_height) = canvas.window().drawable_size();
+        let scale = (window_width / across).min(window_height / down).max(1);
+        let (width, height) = (64 * scale, rows * scale);
+        // Centred before turning, as SDL turns it about its centre
+        let offset_x = (window_width as i32 - width as i32) / 2;
+        let offset_y = (window_height as i32 - height as i32) / 2;
+        let dst = Rect::new(offset_x, offset_y, width, height);
+        let angle = args.rotate as f64;
+        if crt {
+            let texture = match crt_texture {
+                Some((ref mut texture, w, h)) if (w, h) == (width, height) => texture,
+                _ => {
+                    let texture = texture_creator
+                        .create_texture_streaming(PixelFormatEnum::RGB24, width, height)
+                        .map_err(sdl_error)?;
+                    &mut crt_texture.insert((texture, width, height)).0
+                }
+            };
+            texture
+                .update(
+                    None,
+                    &crt_filter(&levels, &palette, scale, args.crt_curvature),
+                    width as usize * 3,
+                )
+                .map_err(sdl_error)?;
+            canvas
+                .copy_ex(texture, None, dst, angle, None, false, false)
+                .map_err(sdl_error)?;
+        } else {
+            let mut pixels = vec![0; levels.len() * 3];
+            for (pixel, &level) in pixels.chunks_exact_mut(3).zip(levels.iter()) {
+                pixel.copy_from_slice(&palette.shade(level));
+            }
+            texture.update(None, &pixels, 64 * 3).map_err(sdl_error)?;
+            canvas
+                .copy_ex(&texture, None, dst, angle, None, false, false)
+                .map_err(sdl_error)?;
+        }
+        if show_speed {
+            overlay::draw_text(&mut canvas, 0, 0, (scale / 4).max(1), &speed_text)
+                .map_err(sdl_error)?;
+        }
+        if show_sprites {
+            let dot = (scale / 4).max(1);
+            let (index, bytes) = &sprite_memory;
+            let columns = SPRITE_BYTES as u32 / SPRITE_ROWS as u32;
+            let x = window_width as i32 - ((columns * 9 + 1) * dot) as i32;
+            overlay::draw_text(&mut canvas, x, 0, dot, &[format!("I {:03X}", index)])
+                .map_err(sdl_error)?;
+            overlay::draw_sprites(&mut canvas, x, (7 * dot) as i32, dot, bytes, SPRITE_ROWS)
+                .map_err(sdl_error)?;
+        }
+        if show_hud {
+            let dot = (scale / 4).max(1);
+            // Above the halt banner's line
+            let lines = hud_text.len() as u32 + 1;
+            let y = window_height as i32 - ((lines * 6 + 1) * dot) as i32;
+            overlay::draw_text(&mut canvas, 0, y, dot, &hud_text).map_err(sdl_error)?;
+        }
+        if show_keypad {
+            let dot = (scale / 4).max(1);
+            let size = (overlay::KEYPAD_SIZE * dot) as i32;
+            let (x, y) = (window_width as i32 - size, window_height as i32 - size);
+            let (pressed, polled) = &keypad_shown;
+            overlay::draw_keypad(&mut canvas, x, y, dot, pressed, polled).map_err(sdl_error)?;
+        }
+        if halt_shown {
+            let dot = (scale / 4).max(1);
+            let y = window_height as i32 - (7 * dot) as i32;
+            overlay::draw_text(&mut canvas, 0, y, dot, &["PROGRAM HALTED".to_string()])
+                .map_err(sdl_error)?;
         }
         canvas.present();
+        speed.frame_presented();
+        redraw = fading;
     }
 }
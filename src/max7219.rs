@@ -0,0 +1,82 @@
+//! Mirrors the display onto a 64x32 wall of MAX7219 8x8 LED modules: 32
+//! chips daisy-chained on one Linux spidev device, left to right and then
+//! top to bottom starting from the chip nearest the SPI pins.
+
+use crate::frame::{Frame, SharedFrame};
+use crate::log;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+const CHIPS: usize = 32;
+const MODULES_PER_ROW: usize = 8;
+
+const DECODE_MODE: u8 = 0x09;
+const INTENSITY: u8 = 0x0A;
+const SCAN_LIMIT: u8 = 0x0B;
+const SHUTDOWN: u8 = 0x0C;
+const DISPLAY_TEST: u8 = 0x0F;
+
+pub fn spawn(device: String, frame: SharedFrame) {
+    let mut spi = match OpenOptions::new().write(true).open(&device) {
+        Ok(spi) => spi,
+        Err(err) => {
+            log::warn!("Cannot open LED matrix {}: {}", device, err);
+            return;
+        }
+    };
+    thread::spawn(move || {
+        if let Err(err) = run(&mut spi, &frame) {
+            log::warn!("LED matrix {} stopped: {}", device, err);
+        }
+    });
+}
+
+fn run(spi: &mut File, frame: &Mutex<Frame>) -> io::Result<()> {
+    init(spi)?;
+    let mut shown = None;
+    loop {
+        let display = frame.lock().unwrap().display.clone();
+        if shown.as_ref() != Some(&display) {
+            show(spi, &display)?;
+            shown = Some(display);
+        }
+        thread::sleep(Duration::from_secs_f64(1.0 / 60.0));
+    }
+}
+
+fn init(spi: &mut File) -> io::Result<()> {
+    write_register(spi, DISPLAY_TEST, |_| 0)?;
+    write_register(spi, DECODE_MODE, |_| 0)?;
+    write_register(spi, SCAN_LIMIT, |_| 7)?;
+    write_register(spi, INTENSITY, |_| 4)?;
+    write_register(spi, SHUTDOWN, |_| 1)
+}
+
+/// Shows the top 64x32 pixels, which is all of the display outside hires mode
+fn show(spi: &mut File, display: &[bool]) -> io::Result<()> {
+    for row in 0..8 {
+        write_register(spi, row as u8 + 1, |chip| {
+            let x = (chip % MODULES_PER_ROW) * 8;
+            let y = (chip / MODULES_PER_ROW) * 8 + row;
+            (0..8).fold(0, |byte, bit| {
+                byte | ((display[x + bit + 64 * y] as u8) << (7 - bit))
+            })
+        })?;
+    }
+    Ok(())
+}
+
+/// Writes one register on every chip in a single transaction. Data for the
+/// far end of the chain has to be shifted out first.
+fn write_register(spi: &mut File, register: u8, data: impl Fn(usize) -> u8) -> io::Result<()> {
+    let mut buf = [0; 2 * CHIPS];
+    for chip in 0..CHIPS {
+        let pos = 2 * (CHIPS - 1 - chip);
+        buf[pos] = register;
+        buf[pos + 1] = data(chip);
+    }
+    spi.write_all(&buf)
+}
@@ -0,0 +1,95 @@
+//! Input movies: the keypad state recorded against the instruction count, so
+//! a run can be replayed exactly. Together with `--seed` this makes whole
+//! runs reproducible.
+//!
+//! A movie is a text file starting with a `c8m 1` line, followed by one line
+//! per change of the held keys: the number of instructions executed so far
+//! and the keys held from then on as a 16-bit hex mask like the keypad file
+//! uses, e.g. `1520 0012`.
+
+use chip8::Chip8;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+const HEADER: &str = "c8m 1";
+
+fn mask(keys: &[bool; 16]) -> u16 {
+    (0..16).fold(0, |mask, key| mask | ((keys[key] as u16) << key))
+}
+
+/// Writes every change of the held keys as it is seen
+pub struct Recorder {
+    file: File,
+    held: u16,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut file = File::create(path)?;
+        writeln!(file, "{}", HEADER)?;
+        Ok(Self { file, held: 0 })
+    }
+
+    /// Call before each instruction
+    pub fn record(&mut self, chip8: &Chip8) -> io::Result<()> {
+        let held = mask(&chip8.keys);
+        if held != self.held {
+            writeln!(self.file, "{} {:04X}", chip8.instructions, held)?;
+            self.held = held;
+        }
+        Ok(())
+    }
+}
+
+pub struct Player {
+    changes: Vec<(u64, u16)>,
+    next: usize,
+    held: u16,
+}
+
+impl Player {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("Cannot read input movie {}: {}", path.display(), err))?;
+        let mut lines = text.lines();
+        if lines.next().map(str::trim) != Some(HEADER) {
+            return Err(format!("{} is not an input movie", path.display()));
+        }
+        let mut changes = Vec::new();
+        for (number, line) in lines.enumerate() {
+            let change = line.split_once(' ').and_then(|(at, mask)| {
+                Some((at.parse().ok()?, u16::from_str_radix(mask.trim(), 16).ok()?))
+            });
+            match change {
+                Some(change) => changes.push(change),
+                None if line.trim().is_empty() => (),
+                None => return Err(format!("{}:{}: invalid line", path.display(), number + 2)),
+            }
+        }
+        Ok(Self {
+            changes,
+            next: 0,
+            held: 0,
+        })
+    }
+
+    /// Call before each instruction. Holds exactly the keys the movie says,
+    /// overriding any live input.
+    pub fn apply(&mut self, chip8: &mut Chip8) {
+        while let Some(&(at, held)) = self.changes.get(self.next) {
+            if at > chip8.instructions {
+                break;
+            }
+            self.held = held;
+            self.next += 1;
+        }
+        for (key, down) in chip8.keys.iter_mut().enumerate() {
+            *down = self.held & (1 << key) != 0;
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.next == self.changes.len()
+    }
+}
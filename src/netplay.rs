@@ -0,0 +1,294 @@
+//! Two-player games over the network. Both sides run the same ROM from the
+//! same seed in lockstep: every frame each sends the keys held locally,
+//! waits for the other's, and both run the frame with the keys of both
+//! players held. With the same input on the same frames the two machines
+//! stay identical, which a display hash sent along with the keys checks.
+//!
+//! The host sends `c8n 3 <ROM CRC-32> <seed> <speed> <setup>` when the
+//! other side joins, the setup being everything else that changes how the
+//! machine runs: the quirks, memory policy, load address, hires mode and
+//! font. The other side answers `ok`, or `refused` with the reason if the
+//! setup differs from its own, and then every frame each side sends
+//! `<key mask> <display hash>` in hex.
+
+use crate::{log, png};
+use chip8::{Chip8, Chip8Error, MemoryPolicy, Quirks};
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+
+const HEADER: &str = "c8n 3";
+
+pub struct Connection {
+    writer: TcpStream,
+    reader: BufReader<TcpStream>,
+    /// Seed and speed both sides run with, picked by the host
+    pub seed: u64,
+    pub speed: u32,
+    /// Whether the user heard about the machines drifting apart
+    warned: bool,
+}
+
+fn error(err: impl std::fmt::Display) -> Chip8Error {
+    Chip8Error::Frontend(format!("Netplay: {}", err))
+}
+
+/// What both machines have to share besides the ROM, seed and speed
+#[derive(Clone, Copy)]
+pub struct Setup {
+    pub quirks: Quirks,
+    pub memory_policy: MemoryPolicy,
+    pub load_address: u16,
+    pub hires: bool,
+    /// CRC-32 of the font file, or `None` for the built-in font
+    pub font: Option<u32>,
+}
+
+/// As sent in the greeting, without spaces
+impl fmt::Display for Setup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "wait_for_release={},display_wait={},random={},memory={},load_address={:03X},hires={}",
+            self.quirks.wait_for_release,
+            self.quirks.display_wait,
+            self.quirks.random,
+            self.memory_policy,
+            self.load_address,
+            self.hires
+        )?;
+        match self.font {
+            Some(crc) => write!(f, ",font={:08x}", crc),
+            None => write!(f, ",font=built-in"),
+        }
+    }
+}
+
+/// Waits on `port` of `address` for the other player to join, and refuses
+/// them if they run with another setup
+pub fn host(
+    address: &str,
+    port: u16,
+    rom: &[u8],
+    seed: u64,
+    speed: u32,
+    setup: Setup,
+) -> Result<Connection, Chip8Error> {
+    let listener = TcpListener::bind((address, port)).map_err(error)?;
+    let bound = listener.local_addr().map_err(error)?;
+    println!("Waiting for the other player on {}", bound);
+    let (stream, peer) = listener.accept().map_err(error)?;
+    println!("{} joined", peer);
+    let mut connection = Connection::new(stream, seed, speed)?;
+    writeln!(
+        connection.writer,
+        "{} {:08x} {} {} {}",
+        HEADER,
+        png::crc32(rom),
+        seed,
+        speed,
+        setup
+    )
+    .map_err(error)?;
+    match connection.read_line()?.as_str() {
+        "ok" => Ok(connection),
+        answer => Err(error(format!(
+            "{} didn't join: {}",
+            peer,
+            answer.strip_prefix("refused ").unwrap_or(answer)
+        ))),
+    }
+}
+
+/// Joins a game hosted at `address`, taking the host's seed and speed, if
+/// the host runs with the same setup as here
+pub fn join(address: &str, rom: &[u8], setup: Setup) -> Result<Connection, Chip8Error> {
+    let stream = TcpStream::connect(address).map_err(error)?;
+    let mut connection = Connection::new(stream, 0, 0)?;
+    let line = connection.read_line()?;
+    let fields: Vec<&str> = line
+        .strip_prefix(HEADER)
+        .ok_or_else(|| error("the host isn't a netplay host of this version"))?
+        .split_whitespace()
+        .collect();
+    let [crc, seed, speed, host_setup] = fields[..] else {
+        return Err(error("the host sent an invalid greeting"));
+    };
+    let refusal = if u32::from_str_radix(crc, 16).ok() != Some(png::crc32(rom)) {
+        Some("the host is running a different ROM".to_string())
+    } else if host_setup != setup.to_string() {
+        Some(format!(
+            "the host runs with {}, but this side with {}; use the same config and options on \
+             both",
+            host_setup, setup
+        ))
+    } else {
+        None
+    };
+    if let Some(refusal) = refusal {
+        // Let the host know why, then give up
+        let _ = writeln!(connection.writer, "refused {}", refusal);
+        return Err(error(refusal));
+    }
+    connection.seed = seed.parse().map_err(error)?;
+    connection.speed = speed.parse().map_err(error)?;
+    writeln!(connection.writer, "ok").map_err(error)?;
+    Ok(connection)
+}
+
+impl Connection {
+    fn new(stream: TcpStream, seed: u64, speed: u32) -> Result<Self, Chip8Error> {
+        // Every frame waits on the other side, so don't batch
+        stream.set_nodelay(true).map_err(error)?;
+        Ok(Self {
+            writer: stream.try_clone().map_err(error)?,
+            reader: BufReader::new(stream),
+            seed,
+            speed,
+            warned: false,
+        })
+    }
+
+    fn read_line(&mut self) -> Result<String, Chip8Error> {
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) => Err(error("the other player left")),
+            Ok(_) => Ok(line.trim().to_string()),
+            Err(err) => Err(error(err)),
+        }
+    }
+
+    /// Swaps this frame's keys and display hash with the other side
+    fn exchange(&mut self, keys: u16, hash: u64) -> Result<(u16, u64), Chip8Error> {
+        writeln!(self.writer, "{:04X} {:016x}", keys, hash).map_err(error)?;
+        let line = self.read_line()?;
+        let parsed = line.split_once(' ').and_then(|(keys, hash)| {
+            Some((
+                u16::from_str_radix(keys, 16).ok()?,
+                u64::from_str_radix(hash, 16).ok()?,
+            ))
+        });
+        parsed.ok_or_else(|| error("the other player sent an invalid frame"))
+    }
+
+    /// Waits for the other side to reach the same frame, then holds the
+    /// keys of both players. `local` is the mask of keys pressed here, since
+    /// the machine's own keys are overwritten every frame.
+    pub fn sync(&mut self, chip8: &mut Chip8, local: u16) -> Result<(), Chip8Error> {
+        let hash = chip8.display_hash();
+        let (remote, remote_hash) = self.exchange(local, hash)?;
+        if remote_hash != hash && !self.warned {
+            log::warn!(
+                "Netplay: the two machines are out of sync; check both use the same settings"
+            );
+            self.warned = true;
+        }
+        let held = local | remote;
+        for key in 0..16 {
+            chip8.keys[key] = held & (1 << key) != 0;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8::RandomSource;
+    use std::thread;
+
+    /// A port nothing listens on, for a host to bind next
+    fn free_port() -> u16 {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        listener.local_addr().unwrap().port()
+    }
+
+    fn setup() -> Setup {
+        Setup {
+            quirks: Quirks::default(),
+            memory_policy: MemoryPolicy::Wrap,
+            load_address: 0x200,
+            hires: false,
+            font: None,
+        }
+    }
+
+    /// Hosts with `host_setup` and joins with `join_setup`, giving what
+    /// each side made of it
+    fn handshake(
+        host_setup: Setup,
+        join_setup: Setup,
+    ) -> (
+        Result<Connection, Chip8Error>,
+        Result<Connection, Chip8Error>,
+    ) {
+        let port = free_port();
+        let rom = [0x12, 0x00];
+        let host = thread::spawn(move || host("127.0.0.1", port, &rom, 42, 700, host_setup));
+        let address = format!("127.0.0.1:{}", port);
+        let joined = loop {
+            match join(&address, &rom, join_setup) {
+                Err(err) if err.to_string().contains("refused") => thread::yield_now(),
+                result => break result,
+            }
+        };
+        (host.join().unwrap(), joined)
+    }
+
+    #[test]
+    fn setup_is_sent_without_spaces() {
+        let setup = Setup {
+            quirks: Quirks {
+                random: RandomSource::Lfsr,
+                ..Quirks::default()
+            },
+            memory_policy: MemoryPolicy::Trap,
+            font: Some(0xDEAD_BEEF),
+            ..setup()
+        };
+        let sent = setup.to_string();
+        assert!(!sent.contains(' '));
+        assert!(
+            sent.ends_with(",random=lfsr,memory=trap,load_address=200,hires=false,font=deadbeef")
+        );
+    }
+
+    #[test]
+    fn joining_takes_the_seed_and_speed_of_the_host() {
+        let (host, joined) = handshake(setup(), setup());
+        assert!(host.is_ok());
+        let joined = joined.ok().unwrap();
+        assert_eq!((joined.seed, joined.speed), (42, 700));
+    }
+
+    #[test]
+    fn another_setup_is_refused_on_both_sides() {
+        let others = [
+            Setup {
+                quirks: Quirks {
+                    display_wait: !Quirks::default().display_wait,
+                    ..Quirks::default()
+                },
+                ..setup()
+            },
+            Setup {
+                load_address: 0x600,
+                ..setup()
+            },
+            Setup {
+                hires: true,
+                ..setup()
+            },
+            Setup {
+                font: Some(1),
+                ..setup()
+            },
+        ];
+        for other in others {
+            let (host, joined) = handshake(setup(), other);
+            for side in [host.err(), joined.err()] {
+                assert!(side.unwrap().to_string().contains("use the same config"));
+            }
+        }
+    }
+}
@@ -0,0 +1,246 @@
+//! Text drawn over the game with a tiny built-in font, memory drawn as
+//! sprites, the keypad, plus the speed measurements shown by the
+//! performance overlay.
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas};
+use sdl2::video::Window;
+use std::time::{Duration, Instant};
+
+/// 3x5 glyphs, one byte per row with the leftmost pixel in bit 2
+const GLYPHS: [(char, [u8; 5]); 49] = [
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b111, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b001, 0b001, 0b001]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    (',', [0b000, 0b000, 0b000, 0b010, 0b100]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    ('=', [0b000, 0b111, 0b000, 0b111, 0b000]),
+    ('/', [0b001, 0b001, 0b010, 0b100, 0b100]),
+    ('>', [0b100, 0b010, 0b001, 0b010, 0b100]),
+    ('<', [0b001, 0b010, 0b100, 0b010, 0b001]),
+    ('?', [0b111, 0b001, 0b010, 0b000, 0b010]),
+    ('!', [0b010, 0b010, 0b010, 0b000, 0b010]),
+    ('_', [0b000, 0b000, 0b000, 0b000, 0b111]),
+    ('+', [0b000, 0b010, 0b111, 0b010, 0b000]),
+    ('%', [0b101, 0b001, 0b010, 0b100, 0b101]),
+];
+
+/// Draws `lines` of text on a dark box with its top-left corner at
+/// (`x`, `y`), each font pixel `dot` screen pixels wide. Unknown characters
+/// are drawn as spaces.
+pub fn draw_text(
+    canvas: &mut Canvas<Window>,
+    x: i32,
+    y: i32,
+    dot: u32,
+    lines: &[String],
+) -> Result<(), String> {
+    let dot_i = dot as i32;
+    let columns = lines.iter().map(|line| line.len()).max().unwrap_or(0) as u32;
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(Color::RGBA(0, 0, 0, 192));
+    canvas.fill_rect(Rect::new(
+        x,
+        y,
+        (columns * 4 + 1) * dot,
+        (lines.len() as u32 * 6 + 1) * dot,
+    ))?;
+    canvas.set_blend_mode(BlendMode::None);
+
+    canvas.set_draw_color(Color::RGB(255, 255, 0));
+    for (row, line) in lines.iter().enumerate() {
+        for (column, c) in line.chars().enumerate() {
+            let glyph_x = x + (column as i32 * 4 + 1) * dot_i;
+            let glyph_y = y + (row as i32 * 6 + 1) * dot_i;
+            draw_glyph(canvas, glyph_x, glyph_y, dot, c)?;
+        }
+    }
+    Ok(())
+}
+
+/// Draws `c` in the current colour with its top-left corner at (`x`, `y`)
+fn draw_glyph(
+    canvas: &mut Canvas<Window>,
+    x: i32,
+    y: i32,
+    dot: u32,
+    c: char,
+) -> Result<(), String> {
+    let c = c.to_ascii_uppercase();
+    let Some((_, glyph)) = GLYPHS.iter().find(|(g, _)| *g == c) else {
+        return Ok(());
+    };
+    for (gy, bits) in glyph.iter().enumerate() {
+        for gx in 0..3 {
+            if bits & (0b100 >> gx) != 0 {
+                let rect = Rect::new(x + gx * dot as i32, y + gy as i32 * dot as i32, dot, dot);
+                canvas.fill_rect(rect)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The COSMAC VIP keypad, row by row
+const KEYPAD: [u8; 16] = [
+    0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF,
+];
+
+/// Width and height of the box `draw_keypad` draws, in font pixels
+pub const KEYPAD_SIZE: u32 = 4 * 6 + 1;
+
+/// Draws the keypad in its 4x4 layout on a dark box with its top-left
+/// corner at (`x`, `y`), with the keys in `pressed` lit and those in
+/// `polled`, which the program is reading, outlined
+pub fn draw_keypad(
+    canvas: &mut Canvas<Window>,
+    x: i32,
+    y: i32,
+    dot: u32,
+    pressed: &[bool; 16],
+    polled: &[bool; 16],
+) -> Result<(), String> {
+    let dot_i = dot as i32;
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(Color::RGBA(0, 0, 0, 192));
+    canvas.fill_rect(Rect::new(x, y, KEYPAD_SIZE * dot, KEYPAD_SIZE * dot))?;
+    canvas.set_blend_mode(BlendMode::None);
+
+    for (n, &key) in KEYPAD.iter().enumerate() {
+        let (column, row) = ((n % 4) as i32, (n / 4) as i32);
+        // A 5x5 key with the 3x5 glyph in its middle
+        let (key_x, key_y) = (x + (column * 6 + 1) * dot_i, y + (row * 6 + 1) * dot_i);
+        let key_rect = Rect::new(key_x, key_y, 5 * dot, 5 * dot);
+        if polled[key as usize] {
+            canvas.set_draw_color(Color::RGB(0, 160, 255));
+            canvas.fill_rect(Rect::new(key_x - dot_i, key_y - dot_i, 7 * dot, 7 * dot))?;
+        }
+        let (background, text) = if pressed[key as usize] {
+            (Color::RGB(255, 255, 0), Color::RGB(0, 0, 0))
+        } else {
+            (Color::RGB(48, 48, 48), Color::RGB(255, 255, 255))
+        };
+        canvas.set_draw_color(background);
+        canvas.fill_rect(key_rect)?;
+        canvas.set_draw_color(text);
+        let label = char::from_digit(key as u32, 16).unwrap_or('?');
+        draw_glyph(canvas, key_x + dot_i, key_y, dot, label)?;
+    }
+    Ok(())
+}
+
+/// Draws `bytes` as sprite rows, `rows` to a column and eight pixels wide,
+/// on a dark box with its top-left corner at (`x`, `y`). A column is as
+/// tall as the tallest sprite, so sprites of any height can be picked out.
+pub fn draw_sprites(
+    canvas: &mut Canvas<Window>,
+    x: i32,
+    y: i32,
+    dot: u32,
+    bytes: &[u8],
+    rows: usize,
+) -> Result<(), String> {
+    let dot_i = dot as i32;
+    let columns = bytes.len().div_ceil(rows) as u32;
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(Color::RGBA(0, 0, 0, 192));
+    canvas.fill_rect(Rect::new(
+        x,
+        y,
+        (columns * 9 + 1) * dot,
+        (rows as u32 + 2) * dot,
+    ))?;
+    canvas.set_blend_mode(BlendMode::None);
+
+    canvas.set_draw_color(Color::RGB(255, 255, 255));
+    for (n, byte) in bytes.iter().enumerate() {
+        let (column, row) = ((n / rows) as i32, (n % rows) as i32);
+        for bit in 0..8 {
+            if byte & (0x80 >> bit) != 0 {
+                let rect = Rect::new(
+                    x + (column * 9 + bit + 1) * dot_i,
+                    y + (row + 1) * dot_i,
+                    dot,
+                    dot,
+                );
+                canvas.fill_rect(rect)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Frames and instructions per second, averaged over one second windows
+pub struct Speed {
+    window_start: Instant,
+    frames: u32,
+    instructions: u64,
+    pub fps: u32,
+    pub ips: u64,
+}
+
+impl Speed {
+    pub fn new(instructions: u64) -> Self {
+        Self {
+            window_start: Instant::now(),
+            frames: 0,
+            instructions,
+            fps: 0,
+            ips: 0,
+        }
+    }
+
+    pub fn frame_presented(&mut self) {
+        self.frames += 1;
+    }
+
+    /// Closes the current window once a second has passed
+    pub fn update(&mut self, instructions: u64) {
+        let elapsed = self.window_start.elapsed();
+        if elapsed < Duration::from_secs(1) {
+            return;
+        }
+        self.fps = (self.frames as f64 / elapsed.as_secs_f64()).round() as u32;
+        self.ips =
+            ((instructions - self.instructions) as f64 / elapsed.as_secs_f64()).round() as u64;
+        self.window_start = Instant::now();
+        self.frames = 0;
+        self.instructions = instructions;
+    }
+}
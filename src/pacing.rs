@@ -0,0 +1,96 @@
+//! Keeping the machine at 60 frames a second on a host that doesn't always
+//! keep up. Frames fall due at fixed deadlines, so a slow frame doesn't push
+//! back every later one. A host that runs late catches up by running the
+//! frames it missed, but one that fell far behind, say while stopped in a
+//! debugger, drops them and carries on from now.
+//!
+//! The caller passes in the time rather than the scheduler reading the
+//! clock, so the catching up can be driven through made-up times.
+
+use crate::log;
+use std::time::{Duration, Instant};
+
+/// Most frames to run at once to catch up
+const MAX_CATCH_UP: u32 = 5;
+
+pub struct Scheduler {
+    frame_time: Duration,
+    next: Instant,
+}
+
+impl Scheduler {
+    /// Starts with a frame due at `now`
+    pub fn new(hz: f64, now: Instant) -> Self {
+        Self {
+            frame_time: Duration::from_secs_f64(1.0 / hz),
+            next: now,
+        }
+    }
+
+    /// When the next frame is due
+    pub fn deadline(&self) -> Instant {
+        self.next
+    }
+
+    /// How many frames are due by `now`, usually one once the deadline
+    /// passed
+    pub fn due(&mut self, now: Instant) -> u32 {
+        if now < self.next {
+            return 0;
+        }
+        let late = now.duration_since(self.next).as_secs_f64();
+        let due = (late / self.frame_time.as_secs_f64()) as u64 + 1;
+        if due > MAX_CATCH_UP as u64 {
+            log::debug!("Fell {} frames behind, skipping them", due - 1);
+            self.next = now + self.frame_time;
+            return 1;
+        }
+        self.next += self.frame_time * due as u32;
+        due as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A frame at 50 Hz, which is a whole number of nanoseconds
+    const FRAME: Duration = Duration::from_millis(20);
+
+    fn scheduler(start: Instant) -> Scheduler {
+        Scheduler::new(50.0, start)
+    }
+
+    #[test]
+    fn frames_fall_due_one_at_a_time_on_time() {
+        let start = Instant::now();
+        let mut scheduler = scheduler(start);
+        assert_eq!(scheduler.due(start), 1);
+        assert_eq!(scheduler.deadline(), start + FRAME);
+        assert_eq!(scheduler.due(start + FRAME / 2), 0);
+        assert_eq!(scheduler.due(start + FRAME), 1);
+        assert_eq!(scheduler.deadline(), start + FRAME * 2);
+    }
+
+    #[test]
+    fn a_stall_is_caught_up_without_moving_the_deadlines() {
+        let start = Instant::now();
+        let mut scheduler = scheduler(start);
+        assert_eq!(scheduler.due(start), 1);
+        // Three frames late, halfway into the fourth
+        assert_eq!(scheduler.due(start + FRAME * 3 + FRAME / 2), 3);
+        assert_eq!(scheduler.deadline(), start + FRAME * 4);
+        assert_eq!(scheduler.due(start + FRAME * 4), 1);
+    }
+
+    #[test]
+    fn falling_far_behind_drops_the_missed_frames() {
+        let start = Instant::now();
+        let mut scheduler = scheduler(start);
+        assert_eq!(scheduler.due(start), 1);
+        assert_eq!(scheduler.due(start + FRAME * MAX_CATCH_UP), MAX_CATCH_UP);
+        let stalled = start + FRAME * (MAX_CATCH_UP * 2 + 1);
+        assert_eq!(scheduler.due(stalled), 1);
+        assert_eq!(scheduler.deadline(), stalled + FRAME);
+    }
+}
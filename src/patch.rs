@@ -0,0 +1,245 @@
+//! IPS and BPS patches, the formats community bugfixes for ROMs come in,
+//! applied to the ROM image before it's loaded
+
+use crate::png;
+use std::fs;
+use std::path::Path;
+
+pub fn apply(rom: &[u8], path: &Path) -> Result<Vec<u8>, String> {
+    let patch =
+        fs::read(path).map_err(|err| format!("Cannot read patch {}: {}", path.display(), err))?;
+    let patched = if patch.starts_with(b"PATCH") {
+        ips(rom, &patch[5..])
+    } else if patch.starts_with(b"BPS1") {
+        bps(rom, &patch)
+    } else {
+        Err("not an IPS or BPS patch".into())
+    };
+    patched.map_err(|err| format!("Cannot apply {}: {}", path.display(), err))
+}
+
+fn ips(rom: &[u8], mut records: &[u8]) -> Result<Vec<u8>, String> {
+    const TRUNCATED: &str = "the patch is truncated";
+    let mut out = rom.to_vec();
+    loop {
+        let offset = records.get(..3).ok_or(TRUNCATED)?;
+        if offset == b"EOF" {
+            // Some patches also cut the image down to a new size
+            if let Some(size) = records.get(3..6) {
+                out.truncate(u32::from_be_bytes([0, size[0], size[1], size[2]]) as usize);
+            }
+            return Ok(out);
+        }
+        let offset = u32::from_be_bytes([0, offset[0], offset[1], offset[2]]) as usize;
+        let size = records.get(3..5).ok_or(TRUNCATED)?;
+        let size = u16::from_be_bytes([size[0], size[1]]) as usize;
+        let (bytes, used) = if size == 0 {
+            // Run of one byte
+            let run = records.get(5..8).ok_or(TRUNCATED)?;
+            let count = u16::from_be_bytes([run[0], run[1]]) as usize;
+            (vec![run[2]; count], 8)
+        } else {
+            (
+                records.get(5..5 + size).ok_or(TRUNCATED)?.to_vec(),
+                5 + size,
+            )
+        };
+        if out.len() < offset + bytes.len() {
+            out.resize(offset + bytes.len(), 0);
+        }
+        out[offset..offset + bytes.len()].copy_from_slice(&bytes);
+        records = &records[used..];
+    }
+}
+
+fn bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    const TRUNCATED: &str = "the patch is truncated";
+    const DAMAGED: &str = "the patch is damaged";
+    if patch.len() < 16 {
+        return Err(TRUNCATED.into());
+    }
+    let (actions, checksums) = patch.split_at(patch.len() - 12);
+    let checksum = |at: usize| {
+        u32::from_le_bytes([
+            checksums[at],
+            checksums[at + 1],
+            checksums[at + 2],
+            checksums[at + 3],
+        ])
+    };
+    if png::crc32(&patch[..patch.len() - 4]) != checksum(8) {
+        return Err(DAMAGED.into());
+    }
+    if png::crc32(rom) != checksum(0) {
+        return Err("it is for a different ROM".into());
+    }
+
+    let mut reader = Reader {
+        data: actions,
+        position: 4,
+    };
+    if reader.number()? != rom.len() {
+        return Err("it is for a different ROM".into());
+    }
+    let target_size = reader.number()?;
+    let metadata_size = reader.number()?;
+    reader.position = reader
+        .position
+        .checked_add(metadata_size)
+        .filter(|&position| position <= actions.len())
+        .ok_or(TRUNCATED)?;
+
+    // The sizes are only checked against the CRC at the end, so they aren't
+    // trusted with memory beyond what a ROM can take
+    if target_size > chip8::rom::max_size(0) {
+        return Err("the patched ROM would not fit in memory".into());
+    }
+    let mut out = Vec::with_capacity(target_size);
+    let (mut source_offset, mut target_offset) = (0usize, 0usize);
+    while reader.position < actions.len() {
+        let action = reader.number()?;
+        let len = (action >> 2) + 1;
+        if len > target_size - out.len() {
+            return Err(DAMAGED.into());
+        }
+        match action & 3 {
+            // Bytes at the same place in the ROM
+            0 => out.extend_from_slice(rom.get(out.len()..out.len() + len).ok_or(DAMAGED)?),
+            // Bytes stored in the patch
+            1 => {
+                let bytes = actions
+                    .get(reader.position..reader.position + len)
+                    .ok_or(TRUNCATED)?;
+                out.extend_from_slice(bytes);
+                reader.position += len;
+            }
+            // Bytes from elsewhere in the ROM
+            2 => {
+                source_offset = reader.offset(source_offset)?;
+                let end = source_offset.checked_add(len).ok_or(DAMAGED)?;
+                let bytes = rom.get(source_offset..end).ok_or(DAMAGED)?;
+                out.extend_from_slice(bytes);
+                source_offset += len;
+            }
+            // Bytes already written, which may overlap what is being written
+            _ => {
+                target_offset = reader.offset(target_offset)?;
+                if target_offset >= out.len() {
+                    return Err(DAMAGED.into());
+                }
+                for _ in 0..len {
+                    out.push(out[target_offset]);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+    if out.len() != target_size || png::crc32(&out) != checksum(4) {
+        return Err(DAMAGED.into());
+    }
+    Ok(out)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    /// BPS's variable-length number, 7 bits per byte with the last byte
+    /// marked by its top bit
+    fn number(&mut self) -> Result<usize, String> {
+        let (mut value, mut shift) = (0usize, 1usize);
+        loop {
+            let byte = *self
+                .data
+                .get(self.position)
+                .ok_or("the patch is truncated")?;
+            self.position += 1;
+            value = value
+                .checked_add((byte as usize & 0x7F).saturating_mul(shift))
+                .ok_or("the patch is damaged")?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_shl(7).ok_or("the patch is damaged")?;
+            value = value.saturating_add(shift);
+        }
+    }
+
+    /// Moves `offset` by a signed relative number
+    fn offset(&mut self, offset: usize) -> Result<usize, String> {
+        let relative = self.number()?;
+        let distance = relative >> 1;
+        let moved = if relative & 1 == 1 {
+            offset.checked_sub(distance)
+        } else {
+            offset.checked_add(distance)
+        };
+        moved.ok_or_else(|| "the patch is damaged".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A BPS number, as `Reader::number` reads it
+    fn number(mut value: usize, out: &mut Vec<u8>) {
+        loop {
+            let low = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                out.push(0x80 | low);
+                return;
+            }
+            out.push(low);
+            value -= 1;
+        }
+    }
+
+    /// A patch with good checksums turning `rom` into `target`, with the
+    /// sizes and actions given
+    fn patch(rom: &[u8], target: &[u8], sizes: [usize; 3], actions: &[u8]) -> Vec<u8> {
+        let mut patch = b"BPS1".to_vec();
+        for size in sizes {
+            number(size, &mut patch);
+        }
+        patch.extend_from_slice(actions);
+        patch.extend_from_slice(&png::crc32(rom).to_le_bytes());
+        patch.extend_from_slice(&png::crc32(target).to_le_bytes());
+        let crc = png::crc32(&patch);
+        patch.extend_from_slice(&crc.to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn bps_copies_and_stores_bytes() {
+        let rom = [0x12, 0x00, 0xAA];
+        let target = [0x12, 0x00, 0xBB];
+        // Two bytes from the ROM, then one stored
+        let mut actions = Vec::new();
+        number(1 << 2, &mut actions);
+        number(1, &mut actions);
+        actions.push(0xBB);
+        let patch = patch(&rom, &target, [3, 3, 0], &actions);
+        assert_eq!(bps(&rom, &patch).unwrap(), target);
+    }
+
+    #[test]
+    fn bps_sizes_past_the_patch_are_refused() {
+        let rom = [0x12, 0x00];
+        // Metadata that would run the position past the end of the patch
+        let patch_with = |sizes| patch(&rom, &rom, sizes, &[]);
+        assert!(bps(&rom, &patch_with([2, 2, usize::MAX - 8])).is_err());
+        assert!(bps(&rom, &patch_with([2, 2, 1 << 40])).is_err());
+        // A target far bigger than memory, reached by copying what's written
+        let mut actions = Vec::new();
+        number(1 << 2, &mut actions);
+        number((usize::MAX >> 3) << 2 | 3, &mut actions);
+        number(0, &mut actions);
+        assert!(bps(&rom, &patch(&rom, &rom, [2, usize::MAX >> 1, 0], &actions)).is_err());
+        // and an action writing past a target that fits
+        assert!(bps(&rom, &patch(&rom, &rom, [2, 100, 0], &actions)).is_err());
+    }
+}
@@ -0,0 +1,46 @@
+//! The ROMs opened most recently, newest first, kept in the data directory
+//! for the launcher and `--recent`
+
+use crate::flags;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// How many ROMs to remember
+const LIMIT: usize = 10;
+
+fn path() -> Option<PathBuf> {
+    Some(flags::data_dir()?.join("recent.txt"))
+}
+
+/// Recently opened ROMs that still exist, newest first
+pub fn load() -> Vec<PathBuf> {
+    let Some(text) = path().and_then(|path| fs::read_to_string(path).ok()) else {
+        return Vec::new();
+    };
+    text.lines()
+        .map(PathBuf::from)
+        .filter(|rom| rom.exists())
+        .collect()
+}
+
+/// Moves `rom` to the front of the list
+pub fn add(rom: &Path) -> io::Result<()> {
+    let Some(path) = path() else {
+        return Ok(());
+    };
+    let rom = fs::canonicalize(rom)?;
+    let mut roms = load();
+    roms.retain(|recent| *recent != rom);
+    roms.insert(0, rom);
+    roms.truncate(LIMIT);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut text = String::new();
+    for rom in &roms {
+        text.push_str(&rom.to_string_lossy());
+        text.push('\n');
+    }
+    fs::write(path, text)
+}
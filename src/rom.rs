@@ -0,0 +1,87 @@
+//! Reading ROM images and checking them before they are loaded
+
+use crate::{Chip8Error, SPRITES};
+use std::fmt;
+use std::path::Path;
+
+/// Largest program that fits between `load_address` and the end of memory
+pub fn max_size(load_address: u16) -> usize {
+    4096usize.saturating_sub(load_address as usize)
+}
+
+/// Something odd about a ROM that still lets it be loaded
+#[derive(Debug)]
+pub enum RomWarning {
+    Empty,
+}
+
+impl fmt::Display for RomWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomWarning::Empty => write!(f, "ROM is empty, so nothing will run"),
+        }
+    }
+}
+
+pub fn read<P>(path: P) -> Result<Vec<u8>, Chip8Error>
+where
+    P: AsRef<Path>,
+{
+    std::fs::read(&path).map_err(|source| Chip8Error::Io {
+        path: path.as_ref().to_path_buf(),
+        source,
+    })
+}
+
+/// Whether the program starts with the jump that two-page hires programs
+/// use to enter the hires interpreter
+pub fn is_hires(data: &[u8]) -> bool {
+    data.starts_with(&[0x12, 0x60])
+}
+
+/// Rejects images that don't fit in memory when loaded at `load_address`, or
+/// are obviously something other than a CHIP-8 program
+pub fn validate(data: &[u8], load_address: u16) -> Result<Vec<RomWarning>, Chip8Error> {
+    if (load_address as usize) < SPRITES.len() || load_address >= 4096 {
+        return Err(Chip8Error::LoadAddress(load_address));
+    }
+    if data.len() > max_size(load_address) {
+        return Err(Chip8Error::RomTooLarge {
+            size: data.len(),
+            max: max_size(load_address),
+        });
+    }
+    if let Some(hint) = foreign_format(data) {
+        return Err(Chip8Error::NotChip8(hint));
+    }
+    let mut warnings = Vec::new();
+    if data.is_empty() {
+        warnings.push(RomWarning::Empty);
+    }
+    Ok(warnings)
+}
+
+fn foreign_format(data: &[u8]) -> Option<&'static str> {
+    let text = std::str::from_utf8(data).ok();
+    let start = text.map(|text| text.trim_start().to_ascii_lowercase());
+    if let Some(start) = &start {
+        if start.starts_with("<!doctype html") || start.starts_with("<html") {
+            return Some("it is an HTML page, such as an Octo export; save the program with Octo's binary export instead");
+        }
+        if start.contains(": main") {
+            return Some("it is Octo source code; assemble it into a binary first");
+        }
+    }
+    if data.starts_with(b"PK\x03\x04") {
+        return Some("it is a zip archive; unpack the ROM from it first");
+    }
+    if data.starts_with(b"GIF8") {
+        // Octo cartridges carry the program as Octo source, which would
+        // need Octo's assembler to run
+        return Some("it is a GIF image; if it is an Octo cartridge, open it in Octo and export a .ch8 binary");
+    }
+    if data.starts_with(b"\x89PNG") {
+        return Some("it is a PNG image");
+    }
+    None
+}
@@ -0,0 +1,167 @@
+//! Save state files: the machine as `Chip8::save_state` gives it, wrapped
+//! with what's needed to refuse states that don't belong. The file starts
+//! with `C8SAVE`, a container version byte and the CRC-32 of the ROM the
+//! state was saved from, followed by the state compressed with PackBits
+//! run-length encoding, which shrinks the mostly empty memory and display to
+//! a fraction, and the CRC-32 of the uncompressed state.
+
+use crate::{flags, png};
+use chip8::{Chip8, Chip8Error};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8] = b"C8SAVE";
+const VERSION: u8 = 1;
+
+fn error(message: impl Into<String>) -> Chip8Error {
+    Chip8Error::State(message.into())
+}
+
+pub fn encode(chip8: &Chip8, rom: &[u8]) -> Vec<u8> {
+    let state = chip8.save_state();
+    let mut out = MAGIC.to_vec();
+    out.push(VERSION);
+    out.extend_from_slice(&png::crc32(rom).to_le_bytes());
+    out.extend_from_slice(&pack(&state));
+    out.extend_from_slice(&png::crc32(&state).to_le_bytes());
+    out
+}
+
+pub fn decode(data: &[u8], rom: &[u8]) -> Result<Chip8, Chip8Error> {
+    let header = MAGIC.len() + 5;
+    if data.len() < header + 4 || !data.starts_with(MAGIC) {
+        return Err(error("This is not a CHIP-8 save state"));
+    }
+    let version = data[MAGIC.len()];
+    if version != VERSION {
+        return Err(error(format!(
+            "The save state is version {}, but only version {} can be loaded",
+            version, VERSION
+        )));
+    }
+    let rom_crc = u32::from_le_bytes(data[MAGIC.len() + 1..header].try_into().unwrap());
+    if rom_crc != png::crc32(rom) {
+        return Err(error("The save state was made with a different ROM"));
+    }
+    let (packed, crc) = data[header..].split_at(data.len() - header - 4);
+    let state = unpack(packed).ok_or_else(|| error("The save state is damaged"))?;
+    if png::crc32(&state).to_le_bytes() != crc {
+        return Err(error("The save state is damaged"));
+    }
+    Chip8::from_state(&state)
+}
+
+/// Directory with the save states of `rom`, named like its flags file
+fn dir(rom: &[u8]) -> Option<PathBuf> {
+    let dir = flags::data_dir()?.join("states");
+    Some(dir.join(format!("{:08x}", png::crc32(rom))))
+}
+
+/// Where quick save slot `slot` for `rom` is kept
+pub fn slot_path(rom: &[u8], slot: u8) -> Option<PathBuf> {
+    Some(dir(rom)?.join(format!("{}.c8s", slot)))
+}
+
+/// Where the state `rom` was quit in is kept, to resume from
+pub fn resume_path(rom: &[u8]) -> Option<PathBuf> {
+    Some(dir(rom)?.join("resume.c8s"))
+}
+
+pub fn write(path: &Path, chip8: &Chip8, rom: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, encode(chip8, rom))
+}
+
+pub fn read(path: &Path, rom: &[u8]) -> Result<Chip8, Chip8Error> {
+    let data = fs::read(path).map_err(|err| {
+        error(format!(
+            "Cannot read save state {}: {}",
+            path.display(),
+            err
+        ))
+    })?;
+    decode(&data, rom)
+}
+
+/// PackBits: a header byte N below 128 is followed by N + 1 literal bytes,
+/// one above 128 by a byte repeated 257 - N times
+fn pack(data: &[u8]) -> Vec<u8> {
+    let run_at = |i: usize| {
+        data[i..]
+            .iter()
+            .take(128)
+            .take_while(|&&byte| byte == data[i])
+            .count()
+    };
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let run = run_at(i);
+        if run >= 3 {
+            out.push((257 - run) as u8);
+            out.push(data[i]);
+            i += run;
+            continue;
+        }
+        let start = i;
+        while i < data.len() && i - start < 128 && run_at(i) < 3 {
+            i += 1;
+        }
+        out.push((i - start - 1) as u8);
+        out.extend_from_slice(&data[start..i]);
+    }
+    out
+}
+
+fn unpack(mut data: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    while let Some((&header, rest)) = data.split_first() {
+        data = match header {
+            0..=127 => {
+                let literal = rest.get(..header as usize + 1)?;
+                out.extend_from_slice(literal);
+                &rest[literal.len()..]
+            }
+            128 => rest,
+            _ => {
+                let (&byte, rest) = rest.split_first()?;
+                out.extend(std::iter::repeat_n(byte, 257 - header as usize));
+                rest
+            }
+        };
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_address_and_speed_come_back() {
+        let rom = [0x12, 0x00];
+        let chip8 = Chip8::builder()
+            .with_program_at(0x600, &rom)
+            .with_speed(1200)
+            .build()
+            .unwrap();
+        let data = encode(&chip8, &rom);
+        let loaded = decode(&data, &rom).unwrap();
+        assert_eq!(loaded.load_address, 0x600);
+        assert_eq!(loaded.speed, 1200);
+        assert_eq!(loaded.rom, rom);
+    }
+
+    #[test]
+    fn other_roms_are_refused() {
+        let chip8 = Chip8::builder()
+            .with_program(&[0x12, 0x00])
+            .build()
+            .unwrap();
+        let data = encode(&chip8, &[0x12, 0x00]);
+        assert!(decode(&data, &[0x12, 0x02]).is_err());
+    }
+}
@@ -0,0 +1,85 @@
+use crate::config::Palette;
+use crate::png;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Size of each CHIP-8 pixel in saved screenshots
+const SCALE: usize = 10;
+
+/// Saves the display as `<rom name>-<timestamp>.png` inside `dir`
+pub fn save(
+    dir: &Path,
+    rom_path: &str,
+    display: &[bool],
+    palette: &Palette,
+) -> io::Result<PathBuf> {
+    let path = output_path(dir, rom_path, "png")?;
+
+    let (width, height) = (64 * SCALE, display.len() / 64 * SCALE);
+    let mut rgb = vec![0; width * height * 3];
+    for (i, pixel) in rgb.chunks_exact_mut(3).enumerate() {
+        let (x, y) = (i % width / SCALE, i / width / SCALE);
+        pixel.copy_from_slice(&palette.shade(display[x + 64 * y] as u8 as f32));
+    }
+    png::write(
+        &mut BufWriter::new(File::create(&path)?),
+        width as u32,
+        height as u32,
+        &rgb,
+    )?;
+    Ok(path)
+}
+
+/// Picks a `<rom name>-<timestamp>` file name inside `dir`, creating the
+/// directory if needed
+pub fn output_path(dir: &Path, rom_path: &str, extension: &str) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    Ok(dir.join(format!(
+        "{}-{}.{}",
+        rom_name(rom_path),
+        timestamp(),
+        extension
+    )))
+}
+
+/// File name of the ROM without its extension
+pub fn rom_name(rom_path: &str) -> String {
+    Path::new(rom_path).file_stem().map_or_else(
+        || "chip8".into(),
+        |stem| stem.to_string_lossy().into_owned(),
+    )
+}
+
+/// Current UTC time as `YYYYMMDD-HHMMSS-mmm`, which sorts chronologically
+pub fn timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let secs = now.as_secs();
+    let (hour, minute, second) = (secs % 86400 / 3600, secs % 3600 / 60, secs % 60);
+
+    // Days since the epoch to a civil date, after Howard Hinnant's
+    // `civil_from_days`
+    let z = (secs / 86400) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}-{:03}",
+        year,
+        month,
+        day,
+        hour,
+        minute,
+        second,
+        now.subsec_millis()
+    )
+}
@@ -0,0 +1,41 @@
+//! Spotting self-modifying code: writes by the program into memory it has
+//! executed. Several classic ROMs patch their own instructions on purpose,
+//! so this is only reported, in traces and by the debugger, to explain
+//! behaviour that would otherwise look like the code changing under you.
+//!
+//! The interpreter decodes every instruction as it fetches it, and the
+//! block cache used by `--blocks` drops its blocks when they are written
+//! to, so neither runs stale code.
+
+use chip8::Access;
+
+pub struct CodeTracker {
+    /// Bytes fetched as part of an instruction since they were last written
+    executed: Vec<bool>,
+}
+
+impl CodeTracker {
+    pub fn new() -> Self {
+        Self {
+            executed: vec![false; 4096],
+        }
+    }
+
+    /// Notes the instruction at `address`, returning the first byte of
+    /// executed code among the writes it made. A byte is reported once
+    /// until it is executed again.
+    pub fn check(&mut self, address: u16, accesses: &[Access]) -> Option<u16> {
+        for byte in [address, address.wrapping_add(1)] {
+            self.executed[byte as usize % 4096] = true;
+        }
+        let mut overwritten = None;
+        for access in accesses.iter().filter(|access| access.write) {
+            let executed = &mut self.executed[access.address as usize];
+            if *executed {
+                *executed = false;
+                overwritten = overwritten.or(Some(access.address));
+            }
+        }
+        overwritten
+    }
+}
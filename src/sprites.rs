@@ -0,0 +1,186 @@
+//! `chip8 sprites`: graphics in a ROM drawn as bitmaps, to find them
+//! without reading hex. By default these are the sprites the code can be
+//! seen drawing, an LD I followed by a DRW, at that DRW's height.
+//! `--height` instead cuts the whole ROM into sprites of that many rows, to
+//! go through data the code reaches in ways that can't be followed.
+
+use crate::{listing, png};
+use chip8::{disasm, rom, Chip8Error, PC_START};
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+/// Sprites side by side in the text output, which keeps it within 80
+/// columns for 8 pixel wide sprites
+const PER_LINE: usize = 8;
+/// Sprites to a row of the image
+const PER_ROW: usize = 16;
+/// Image pixels per sprite pixel
+const SCALE: usize = 4;
+
+#[derive(clap::Args)]
+pub struct SpritesArgs {
+    rom: PathBuf,
+
+    /// Draw every run of this many bytes in the ROM as a sprite, rather
+    /// than only those the code draws
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=15))]
+    height: Option<u8>,
+
+    /// Save the sprites as a PNG image rather than printing them
+    #[arg(long, value_name = "FILE")]
+    png: Option<PathBuf>,
+}
+
+struct Sprite {
+    address: u16,
+    /// 8, or 16 for a SUPER-CHIP 16x16 sprite
+    width: usize,
+    height: usize,
+}
+
+impl Sprite {
+    /// Whether the pixel at (`x`, `y`) is set, reading past the end of the
+    /// ROM as zeros
+    fn pixel(&self, data: &[u8], x: usize, y: usize) -> bool {
+        let offset = (self.address - PC_START) as usize + y * self.width / 8 + x / 8;
+        data.get(offset)
+            .is_some_and(|byte| byte & (0x80 >> (x % 8)) != 0)
+    }
+}
+
+pub fn run(args: SpritesArgs) -> Result<(), Chip8Error> {
+    let data = rom::read(&args.rom)?;
+    let sprites = match args.height {
+        Some(height) => every(&data, height as usize),
+        None => drawn(&data),
+    };
+    match &args.png {
+        Some(path) => {
+            save(path, &data, &sprites).map_err(|err| {
+                Chip8Error::Sprites(format!("Cannot save {}: {}", path.display(), err))
+            })?;
+            println!("Saved {} sprites to {}", sprites.len(), path.display());
+        }
+        None => print!("{}", text(&data, &sprites)),
+    }
+    Ok(())
+}
+
+/// The sprites in the ROM that reachable code draws
+fn drawn(data: &[u8]) -> Vec<Sprite> {
+    let start = if rom::is_hires(data) {
+        chip8::HIRES_START
+    } else {
+        PC_START
+    };
+    let end = PC_START as usize + data.len();
+    let op_at = |address: u16| {
+        let offset = (address - PC_START) as usize;
+        u16::from_be_bytes([data[offset], data[offset + 1]])
+    };
+    let code = disasm::reachable(data, PC_START, start);
+    listing::sprite_heights(&code, op_at)
+        .into_iter()
+        .filter(|&(address, _)| (PC_START as usize..end).contains(&(address as usize)))
+        .map(|(address, len)| match len {
+            32 => Sprite {
+                address,
+                width: 16,
+                height: 16,
+            },
+            height => Sprite {
+                address,
+                width: 8,
+                height,
+            },
+        })
+        .collect()
+}
+
+/// The whole ROM as sprites `height` rows high
+fn every(data: &[u8], height: usize) -> Vec<Sprite> {
+    (0..data.len())
+        .step_by(height)
+        .map(|offset| Sprite {
+            address: PC_START + offset as u16,
+            width: 8,
+            height,
+        })
+        .collect()
+}
+
+/// The sprites side by side, each under its address
+fn text(data: &[u8], sprites: &[Sprite]) -> String {
+    let mut out = String::new();
+    for (n, line) in sprites.chunks(PER_LINE).enumerate() {
+        if n > 0 {
+            out.push('\n');
+        }
+        let columns: Vec<String> = line
+            .iter()
+            .map(|sprite| {
+                format!(
+                    "{:<width$}",
+                    format!("{:03X}", sprite.address),
+                    width = sprite.width
+                )
+            })
+            .collect();
+        out += columns.join("  ").trim_end();
+        out.push('\n');
+        let height = line.iter().map(|sprite| sprite.height).max().unwrap_or(0);
+        for y in 0..height {
+            let rows: Vec<String> = line
+                .iter()
+                .map(|sprite| {
+                    (0..sprite.width)
+                        .map(|x| match (y < sprite.height, sprite.pixel(data, x, y)) {
+                            (false, _) => ' ',
+                            (true, true) => '#',
+                            (true, false) => '.',
+                        })
+                        .collect()
+                })
+                .collect();
+            out += rows.join("  ").trim_end();
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Saves the sprites in rows of `PER_ROW`, in cells as big as the largest
+fn save(path: &Path, data: &[u8], sprites: &[Sprite]) -> io::Result<()> {
+    let cell_width = sprites.iter().map(|sprite| sprite.width).max().unwrap_or(8) + 1;
+    let cell_height = sprites
+        .iter()
+        .map(|sprite| sprite.height)
+        .max()
+        .unwrap_or(1)
+        + 1;
+    let columns = sprites.len().clamp(1, PER_ROW);
+    let rows = sprites.len().div_ceil(PER_ROW).max(1);
+    let (width, height) = (columns * cell_width + 1, rows * cell_height + 1);
+    // Grey between sprites, so blank rows and columns still show
+    let mut pixels = vec![64; width * height];
+    for (n, sprite) in sprites.iter().enumerate() {
+        let (left, top) = (n % PER_ROW * cell_width + 1, n / PER_ROW * cell_height + 1);
+        for y in 0..sprite.height {
+            for x in 0..sprite.width {
+                let on = sprite.pixel(data, x, y);
+                pixels[(top + y) * width + left + x] = if on { 255 } else { 0 };
+            }
+        }
+    }
+    let (image_width, image_height) = (width * SCALE, height * SCALE);
+    let mut rgb = Vec::with_capacity(image_width * image_height * 3);
+    for y in 0..image_height {
+        for x in 0..image_width {
+            let value = pixels[y / SCALE * width + x / SCALE];
+            rgb.extend_from_slice(&[value; 3]);
+        }
+    }
+    let mut out = BufWriter::new(File::create(path)?);
+    png::write(&mut out, image_width as u32, image_height as u32, &rgb)
+}
@@ -0,0 +1,273 @@
+//! The whole machine as bytes, for save states, test fixtures and anything
+//! else that needs to put a machine aside and pick it up again.
+//!
+//! The format starts with `C8ST` and a version byte, then every field in
+//! declaration order, little-endian, with the display packed 8 pixels to a
+//! byte and the keys as a 16-bit mask. The ChaCha generator is kept as its
+//! seed, stream and place in the stream, so a restored machine draws the
+//! same numbers as the one that was saved. Last come the ROM and where it
+//! was loaded, for `Chip8::reset`.
+
+use crate::{Chip8, Chip8Error, Generator, MemoryPolicy, Quirks, RandomSource};
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+
+const MAGIC: &[u8] = b"C8ST";
+const VERSION: u8 = 2;
+
+impl Chip8 {
+    /// Saves the machine, leaving it as it was. A generator given with
+    /// `with_rng` isn't saved, and the restored machine gets a fresh one.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.push(VERSION);
+        out.extend_from_slice(&self.memory);
+        for address in self.stack {
+            out.extend_from_slice(&address.to_le_bytes());
+        }
+        out.extend_from_slice(&self.registers);
+        out.extend_from_slice(&self.flags);
+        out.extend_from_slice(&self.program_counter.to_le_bytes());
+        out.extend_from_slice(&self.stack_pointer.to_le_bytes());
+        out.extend_from_slice(&self.index.to_le_bytes());
+        out.push(self.delay_timer);
+        out.push(self.sound_timer);
+        out.extend(self.display.iter().flat_map(|row| row.to_be_bytes()));
+        out.push(self.hires as u8);
+        let keys = (0..16).fold(0u16, |mask, key| mask | (self.keys[key] as u16) << key);
+        out.extend_from_slice(&keys.to_le_bytes());
+        out.extend_from_slice(&self.instructions.to_le_bytes());
+        match &self.rng {
+            Generator::ChaCha(rng) => {
+                out.push(1);
+                out.extend_from_slice(&rng.get_seed());
+                out.extend_from_slice(&rng.get_stream().to_le_bytes());
+                out.extend_from_slice(&rng.get_word_pos().to_le_bytes());
+            }
+            Generator::Custom(_) => out.push(0),
+        }
+        out.extend_from_slice(&self.lfsr.to_le_bytes());
+        out.push(match self.memory_policy {
+            MemoryPolicy::Wrap => 0,
+            MemoryPolicy::Trap => 1,
+        });
+        out.push(self.quirks.wait_for_release as u8);
+        out.push(self.quirks.display_wait as u8);
+        out.push(match self.quirks.random {
+            RandomSource::Modern => 0,
+            RandomSource::Lfsr => 1,
+        });
+        out.push(self.waiting_key.unwrap_or(0xFF));
+        out.push(self.blocked_on_key as u8);
+        out.push(self.vblank as u8);
+        out.extend_from_slice(&self.speed.to_le_bytes());
+        out.extend_from_slice(&self.load_address.to_le_bytes());
+        out.extend_from_slice(&(self.rom.len() as u16).to_le_bytes());
+        out.extend_from_slice(&self.rom);
+        out
+    }
+
+    /// Rebuilds a machine saved with `save_state`
+    pub fn from_state(data: &[u8]) -> Result<Chip8, Chip8Error> {
+        let mut reader = Reader { data, pos: 0 };
+        if reader.bytes(MAGIC.len())? != MAGIC {
+            return Err(Chip8Error::State("This is not a CHIP-8 save state".into()));
+        }
+        let version = reader.u8()?;
+        if version != VERSION {
+            return Err(Chip8Error::State(format!(
+                "Save state version {} is not supported",
+                version
+            )));
+        }
+
+        let mut chip8 = Chip8::builder().build()?;
+        chip8.memory.copy_from_slice(reader.bytes(4096)?);
+        for address in chip8.stack.iter_mut() {
+            *address = reader.u16()?;
+        }
+        chip8.registers.copy_from_slice(reader.bytes(16)?);
+        chip8.flags.copy_from_slice(reader.bytes(8)?);
+        chip8.program_counter = reader.u16()?;
+        chip8.stack_pointer = reader.u16()?;
+        if chip8.stack_pointer as usize > chip8.stack.len() {
+            return Err(reader.invalid());
+        }
+        chip8.index = reader.u16()?;
+        chip8.delay_timer = reader.u8()?;
+        chip8.sound_timer = reader.u8()?;
+        for (row, bytes) in chip8.display.iter_mut().zip(reader.bytes(512)?.chunks(8)) {
+            *row = u64::from_be_bytes(bytes.try_into().unwrap_or_default());
+        }
+        chip8.hires = reader.bool()?;
+        let keys = reader.u16()?;
+        for (key, held) in chip8.keys.iter_mut().enumerate() {
+            *held = keys & (1 << key) != 0;
+        }
+        chip8.instructions = reader.u64()?;
+        chip8.rng = reader.generator()?;
+        chip8.lfsr = reader.u16()?;
+        chip8.memory_policy = match reader.u8()? {
+            0 => MemoryPolicy::Wrap,
+            1 => MemoryPolicy::Trap,
+            _ => return Err(reader.invalid()),
+        };
+        chip8.quirks = Quirks {
+            wait_for_release: reader.bool()?,
+            display_wait: reader.bool()?,
+            random: match reader.u8()? {
+                0 => RandomSource::Modern,
+                1 => RandomSource::Lfsr,
+                _ => return Err(reader.invalid()),
+            },
+        };
+        chip8.waiting_key = match reader.u8()? {
+            0xFF => None,
+            key if key < 16 => Some(key),
+            _ => return Err(reader.invalid()),
+        };
+        chip8.blocked_on_key = reader.bool()?;
+        chip8.vblank = reader.bool()?;
+        chip8.speed = reader.u32()?;
+        chip8.load_address = reader.u16()?;
+        let len = reader.u16()? as usize;
+        chip8.rom = reader.bytes(len)?.to_vec();
+        if chip8.load_address as usize + chip8.rom.len() > chip8.memory.len() {
+            return Err(reader.invalid());
+        }
+        if reader.pos != data.len() {
+            return Err(reader.invalid());
+        }
+        chip8.display_dirty = true;
+        Ok(chip8)
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn invalid(&self) -> Chip8Error {
+        Chip8Error::State("The save state is damaged".into())
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], Chip8Error> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| self.invalid())?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, Chip8Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, Chip8Error> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(self.invalid()),
+        }
+    }
+
+    fn u16(&mut self) -> Result<u16, Chip8Error> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    /// ChaCha at the place in its stream it was saved at, or a new one for
+    /// a generator that wasn't saved
+    fn generator(&mut self) -> Result<Generator, Chip8Error> {
+        match self.u8()? {
+            0 => Ok(Generator::ChaCha(Box::new(ChaCha12Rng::from_entropy()))),
+            1 => {
+                let mut rng = ChaCha12Rng::from_seed(self.bytes(32)?.try_into().unwrap());
+                rng.set_stream(self.u64()?);
+                rng.set_word_pos(u128::from_le_bytes(self.bytes(16)?.try_into().unwrap()));
+                Ok(Generator::ChaCha(Box::new(rng)))
+            }
+            _ => Err(self.invalid()),
+        }
+    }
+
+    fn u32(&mut self) -> Result<u32, Chip8Error> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, Chip8Error> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    /// A seeded machine that has drawn a few random numbers
+    fn machine() -> Chip8 {
+        let mut chip8 = Chip8::builder()
+            .with_program_at(0x600, &[0xC0, 0xFF, 0x16, 0x00])
+            .with_speed(900)
+            .with_seed(7)
+            .build()
+            .unwrap();
+        for _ in 0..3 {
+            chip8.rng.gen::<u32>();
+        }
+        chip8
+    }
+
+    #[test]
+    fn saving_leaves_the_generator_alone() {
+        let mut saved = machine();
+        let mut unsaved = machine();
+        saved.save_state();
+        let numbers: Vec<u32> = (0..8).map(|_| saved.rng.gen()).collect();
+        let expected: Vec<u32> = (0..8).map(|_| unsaved.rng.gen()).collect();
+        assert_eq!(numbers, expected);
+    }
+
+    #[test]
+    fn restoring_draws_the_numbers_the_saved_machine_would() {
+        let mut chip8 = machine();
+        let mut restored = Chip8::from_state(&chip8.save_state()).unwrap();
+        let numbers: Vec<u32> = (0..8).map(|_| restored.rng.gen()).collect();
+        let expected: Vec<u32> = (0..8).map(|_| chip8.rng.gen()).collect();
+        assert_eq!(numbers, expected);
+    }
+
+    #[test]
+    fn the_program_and_its_settings_come_back() {
+        let mut chip8 = machine();
+        chip8.step().unwrap();
+        let mut restored = Chip8::from_state(&chip8.save_state()).unwrap();
+        assert_eq!(restored.rom, [0xC0, 0xFF, 0x16, 0x00]);
+        assert_eq!(restored.load_address, 0x600);
+        assert_eq!(restored.speed, 900);
+        restored.assert_program_counter(0x602);
+        restored.reset();
+        restored.assert_program_counter(0x600);
+        restored.assert_memory(0x600, &[0xC0, 0xFF, 0x16, 0x00]);
+    }
+
+    #[test]
+    fn other_versions_are_refused() {
+        let mut state = machine().save_state();
+        state[4] = VERSION - 1;
+        assert!(Chip8::from_state(&state).is_err());
+    }
+
+    #[test]
+    fn a_custom_generator_is_replaced_on_restore() {
+        let chip8 = Chip8::builder()
+            .with_rng(rand::rngs::mock::StepRng::new(0, 1))
+            .build()
+            .unwrap();
+        let restored = Chip8::from_state(&chip8.save_state()).unwrap();
+        assert!(matches!(restored.rng, Generator::ChaCha(_)));
+    }
+}
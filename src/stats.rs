@@ -0,0 +1,145 @@
+//! Session statistics for `--stats`, printed on exit and, on Unix, whenever
+//! the process gets SIGUSR1: how fast the machine really ran, which
+//! instructions it spent its time on, the addresses it spent it at, and how
+//! well the timers kept up.
+
+use chip8::instruction::Instruction;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+pub struct Stats {
+    start: Instant,
+    /// Executed instructions by opcode pattern, see `class`
+    counts: HashMap<u16, u64>,
+    /// Executed instructions by address, with the last opcode seen there
+    addresses: HashMap<u16, (u16, u64)>,
+    /// Timer ticks, to compare with the 60 a second there should be
+    pub ticks: u64,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            counts: HashMap::new(),
+            addresses: HashMap::new(),
+            ticks: 0,
+        }
+    }
+
+    pub fn count(&mut self, address: u16, op: u16) {
+        *self.counts.entry(class(op)).or_default() += 1;
+        let entry = self.addresses.entry(address).or_default();
+        *entry = (op, entry.1 + 1);
+    }
+
+    /// The `n` addresses run most, with their opcode and share of all the
+    /// instructions executed
+    pub fn hotspots(&self, n: usize) -> Vec<(u16, u16, f64)> {
+        let total: u64 = self.counts.values().sum();
+        let mut addresses: Vec<_> = self.addresses.iter().collect();
+        addresses.sort_by(|a, b| b.1 .1.cmp(&a.1 .1).then(a.0.cmp(b.0)));
+        addresses
+            .into_iter()
+            .take(n)
+            .map(|(&address, &(op, count))| (address, op, count as f64 / total as f64))
+            .collect()
+    }
+
+    pub fn report(&self) -> String {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let instructions: u64 = self.counts.values().sum();
+        let draws: u64 = self
+            .counts
+            .iter()
+            .filter(|(&class, _)| class == 0x00E0 || class == 0xD000)
+            .map(|(_, count)| count)
+            .sum();
+        let expected_ticks = elapsed * 60.0;
+        let mut report = format!(
+            "Ran {} instructions in {:.1} s, {:.0} IPS on average\n",
+            instructions,
+            elapsed,
+            instructions as f64 / elapsed
+        );
+        report += &format!("{} draw calls (CLS and DRW)\n", draws);
+        report += &format!(
+            "{} timer ticks, {:+.1} from the {:.0} expected\n",
+            self.ticks,
+            self.ticks as f64 - expected_ticks,
+            expected_ticks
+        );
+        report += "Instructions executed:\n";
+        let mut counts: Vec<_> = self.counts.iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (&class, count) in counts {
+            let mnemonic = Instruction::decode(class).map_or("?", Instruction::mnemonic);
+            report += &format!("  {} {:6} {}\n", pattern(class), mnemonic, count);
+        }
+        report += "Hotspots:\n";
+        for (address, op, share) in self.hotspots(HOTSPOTS) {
+            let text = Instruction::decode(op).map_or("unknown".into(), |op| op.to_string());
+            report += &format!(
+                "  {:03X}  {:04X}  {:<16} {:5.1}%\n",
+                address,
+                op,
+                text,
+                share * 100.0
+            );
+        }
+        report
+    }
+}
+
+/// Addresses listed in the report
+const HOTSPOTS: usize = 16;
+
+/// `op` with its operands masked out, so e.g. every 8XY4 counts together
+fn class(op: u16) -> u16 {
+    match op >> 12 {
+        0 if op == 0x00E0 || op == 0x00EE => op,
+        5 | 8 | 9 => op & 0xF00F,
+        0xE | 0xF => op & 0xF0FF,
+        _ => op & 0xF000,
+    }
+}
+
+/// The usual way of writing a `class`, e.g. `8XY4`
+fn pattern(class: u16) -> String {
+    let high = class >> 12;
+    match high {
+        0 if class != 0 => format!("{:04X}", class),
+        0 | 1 | 2 | 0xA | 0xB => format!("{:X}NNN", high),
+        3 | 4 | 6 | 7 | 0xC => format!("{:X}XNN", high),
+        5 | 8 | 9 => format!("{:X}XY{:X}", high, class & 0xF),
+        0xD => "DXYN".into(),
+        _ => format!("{:X}X{:02X}", high, class & 0xFF),
+    }
+}
+
+static REPORT_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Makes SIGUSR1 ask for a report, to be picked up with `report_requested`
+#[cfg(unix)]
+pub fn listen_for_signal() {
+    extern "C" fn handle(_: libc::c_int) {
+        REPORT_REQUESTED.store(true, Ordering::Relaxed);
+    }
+    // SAFETY: the handler only stores to an atomic, which is
+    // async-signal-safe
+    unsafe {
+        libc::signal(
+            libc::SIGUSR1,
+            handle as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+}
+
+#[cfg(not(unix))]
+pub fn listen_for_signal() {}
+
+/// Whether a report was asked for since the last call
+pub fn report_requested() -> bool {
+    REPORT_REQUESTED.swap(false, Ordering::Relaxed)
+}
@@ -0,0 +1,274 @@
+//! Live view for spectators in a web browser: a tiny page that gets the
+//! display over a WebSocket. The first message has the whole display and
+//! later ones only the bytes that changed, which keeps the stream to a few
+//! bytes a frame.
+//!
+//! The display is packed 8 pixels to a byte, leftmost pixel in the top bit.
+//! A full frame is `0, rows, bytes...`; a change is `1` followed by a
+//! big-endian byte offset and the new byte for each changed byte.
+//!
+//! Browsers only send control frames back: pings are answered with pongs,
+//! and a close ends the stream to that spectator.
+
+use crate::config::Palette;
+use crate::frame::{Frame, SharedFrame};
+use crate::sha1;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Sender, TryRecvError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+const BINARY: u8 = 0x2;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xA;
+
+/// Longest message taken from a spectator, far more than a control frame
+const MAX_RECEIVED: u64 = 4096;
+
+const PAGE: &str = r#"<!doctype html>
+<meta charset="utf-8">
+<title>CHIP-8</title>
+<style>body { margin: 0; background: #111; display: grid; place-items: center; height: 100vh }
+canvas { width: min(100vw, 200vh); image-rendering: pixelated }</style>
+<canvas width="64" height="32"></canvas>
+<script>
+const canvas = document.querySelector("canvas");
+const context = canvas.getContext("2d");
+let rows = 32, bytes = new Uint8Array(256);
+function draw() {
+  context.fillStyle = "BACKGROUND";
+  context.fillRect(0, 0, 64, rows);
+  context.fillStyle = "FOREGROUND";
+  for (let i = 0; i < bytes.length * 8; i++) {
+    if (bytes[i >> 3] & (0x80 >> (i & 7))) context.fillRect(i % 64, i >> 6, 1, 1);
+  }
+}
+const socket = new WebSocket(`ws://${location.host}/`);
+socket.binaryType = "arraybuffer";
+socket.onmessage = event => {
+  const data = new Uint8Array(event.data);
+  if (data[0] == 0) {
+    rows = data[1];
+    canvas.height = rows;
+    bytes = data.slice(2);
+  } else {
+    for (let i = 1; i + 2 < data.length; i += 3) bytes[(data[i] << 8) | data[i + 1]] = data[i + 2];
+  }
+  draw();
+};
+</script>
+"#;
+
+pub fn spawn(address: &str, port: u16, frame: SharedFrame, palette: Palette) -> io::Result<()> {
+    let listener = TcpListener::bind((address, port))?;
+    println!(
+        "Streaming the display at http://{}/",
+        listener.local_addr()?
+    );
+    let color = |[r, g, b]: [u8; 3]| format!("#{:02x}{:02x}{:02x}", r, g, b);
+    let page = PAGE
+        .replace("BACKGROUND", &color(palette.background))
+        .replace("FOREGROUND", &color(palette.foreground));
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { continue };
+            let frame = frame.clone();
+            let page = page.clone();
+            // Spectators come and go, so their errors aren't worth reporting
+            thread::spawn(move || serve(stream, &frame, &page));
+        }
+    });
+    Ok(())
+}
+
+fn serve(stream: TcpStream, frame: &Mutex<Frame>, page: &str) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    let mut key = None;
+    for line in reader.by_ref().lines() {
+        let line = line?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value.trim().to_string());
+            }
+        }
+    }
+    let Some(key) = key else {
+        return write!(
+            writer,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            page.len(),
+            page
+        );
+    };
+
+    let accept = sha1::sha1(format!("{}258EAFA5-E914-47DA-95CA-C5AB0DC85B11", key).as_bytes());
+    write!(
+        writer,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        base64(&accept)
+    )?;
+    let (replies, received) = mpsc::channel();
+    thread::spawn(move || listen(reader, replies));
+    let mut sent: Vec<u8> = Vec::new();
+    loop {
+        // Answer what the spectator sent, all frames written from here
+        match received.try_recv() {
+            Ok((CLOSE, payload)) => return send(&mut writer, CLOSE, &payload),
+            Ok((opcode, payload)) => send(&mut writer, opcode, &payload)?,
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => return Ok(()),
+        }
+        let display = frame.lock().unwrap().display.clone();
+        let (rows, bytes) = ((display.len() / 64) as u8, pack(&display));
+        if bytes.len() != sent.len() {
+            let mut message = vec![0, rows];
+            message.extend_from_slice(&bytes);
+            send(&mut writer, BINARY, &message)?;
+        } else {
+            let mut message = vec![1];
+            for (offset, (&new, &old)) in bytes.iter().zip(&sent).enumerate() {
+                if new != old {
+                    message.extend_from_slice(&(offset as u16).to_be_bytes());
+                    message.push(new);
+                }
+            }
+            if message.len() > 1 {
+                send(&mut writer, BINARY, &message)?;
+            }
+        }
+        sent = bytes;
+        thread::sleep(Duration::from_secs_f64(1.0 / 60.0));
+    }
+}
+
+fn pack(screen: &[bool]) -> Vec<u8> {
+    screen
+        .chunks(8)
+        .map(|byte| byte.iter().fold(0, |byte, &on| (byte << 1) | on as u8))
+        .collect()
+}
+
+/// Reads the spectator's frames, passing on the pong for each ping and the
+/// close that echoes theirs, until the connection ends
+fn listen(mut reader: impl Read, replies: Sender<(u8, Vec<u8>)>) {
+    while let Ok((opcode, payload)) = receive(&mut reader) {
+        let reply = match opcode {
+            PING => PONG,
+            CLOSE => CLOSE,
+            _ => continue,
+        };
+        if replies.send((reply, payload)).is_err() || reply == CLOSE {
+            return;
+        }
+    }
+}
+
+/// Reads a WebSocket frame, giving its opcode and unmasked payload
+fn receive(reader: &mut impl Read) -> io::Result<(u8, Vec<u8>)> {
+    let mut header = [0; 2];
+    reader.read_exact(&mut header)?;
+    let len = match header[1] & 0x7F {
+        126 => {
+            let mut len = [0; 2];
+            reader.read_exact(&mut len)?;
+            u16::from_be_bytes(len) as u64
+        }
+        127 => {
+            let mut len = [0; 8];
+            reader.read_exact(&mut len)?;
+            u64::from_be_bytes(len)
+        }
+        len => len as u64,
+    };
+    if len > MAX_RECEIVED {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message too long",
+        ));
+    }
+    let mut mask = [0; 4];
+    if header[1] & 0x80 != 0 {
+        reader.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0; len as usize];
+    reader.read_exact(&mut payload)?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    Ok((header[0] & 0x0F, payload))
+}
+
+/// Sends a WebSocket message with `opcode` in one frame
+fn send(writer: &mut TcpStream, opcode: u8, message: &[u8]) -> io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    if message.len() < 126 {
+        frame.push(message.len() as u8);
+    } else {
+        frame.push(126);
+        frame.extend_from_slice(&(message.len() as u16).to_be_bytes());
+    }
+    frame.extend_from_slice(message);
+    writer.write_all(&frame)
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::new();
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| {
+            bits | (byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn masked_frames_are_unmasked() {
+        // A ping with "hi", as a browser masks it
+        let mask = [0x12, 0x34, 0x56, 0x78];
+        let mut frame = vec![0x89, 0x82];
+        frame.extend_from_slice(&mask);
+        frame.extend_from_slice(&[b'h' ^ mask[0], b'i' ^ mask[1]]);
+        assert_eq!(receive(&mut &frame[..]).unwrap(), (PING, b"hi".to_vec()));
+    }
+
+    #[test]
+    fn long_lengths_are_read() {
+        let mut frame = vec![0x82, 126, 0x01, 0x00];
+        frame.extend_from_slice(&[7; 256]);
+        assert_eq!(receive(&mut &frame[..]).unwrap(), (BINARY, vec![7; 256]));
+
+        let mut frame = vec![0x82, 127];
+        frame.extend_from_slice(&(1u64 << 40).to_be_bytes());
+        assert!(receive(&mut &frame[..]).is_err());
+    }
+
+    #[test]
+    fn pings_are_answered_and_close_ends_listening() {
+        let mut frames = vec![0x89, 0x01, b'a', 0x81, 0x01, b'b', 0x88, 0x02, 0x03, 0xE8];
+        // Nothing after the close is read
+        frames.extend_from_slice(&[0x89, 0x00]);
+        let (replies, received) = mpsc::channel();
+        listen(&frames[..], replies);
+        let received: Vec<_> = received.into_iter().collect();
+        assert_eq!(received, [(PONG, b"a".to_vec()), (CLOSE, vec![0x03, 0xE8])]);
+    }
+}
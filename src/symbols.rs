@@ -0,0 +1,81 @@
+//! Names for addresses, from a symbol file given with `--symbols` or the
+//! debugger's `symbols` command. Each line holds a name and an address in
+//! hex, either way round and optionally with `=` between, so both
+//! `034A draw_score` and Octo-style `draw_score = 0x34A` work. `#` starts a
+//! comment.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+#[derive(Default)]
+pub struct Symbols {
+    names: BTreeMap<u16, String>,
+}
+
+impl Symbols {
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("Cannot read symbols {}: {}", path.display(), err))?;
+        let mut names = BTreeMap::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").replace('=', " ");
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.is_empty() {
+                continue;
+            }
+            let parsed = match words[..] {
+                [name, address] if address.starts_with("0x") => {
+                    parse_address(address).map(|address| (address, name))
+                }
+                [a, b] => match (parse_address(a), parse_address(b)) {
+                    // Without 0x, a name that is also hex, like `add`, is
+                    // taken to come second
+                    (Some(address), _) => Some((address, b)),
+                    (None, Some(address)) => Some((address, a)),
+                    _ => None,
+                },
+                _ => None,
+            };
+            let (address, name) = parsed.ok_or_else(|| {
+                format!(
+                    "{} line {}: expected a name and an address",
+                    path.display(),
+                    n + 1
+                )
+            })?;
+            names.insert(address, name.trim_start_matches(':').to_string());
+        }
+        Ok(Self { names })
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// The name at exactly `address`
+    pub fn name(&self, address: u16) -> Option<&str> {
+        self.names.get(&address).map(String::as_str)
+    }
+
+    pub fn address(&self, name: &str) -> Option<u16> {
+        self.names
+            .iter()
+            .find(|(_, n)| n.as_str() == name)
+            .map(|(&address, _)| address)
+    }
+
+    /// `address` with the symbol at or before it, like `206 draw+4`
+    pub fn describe(&self, address: u16) -> String {
+        match self.names.range(..=address).next_back() {
+            Some((&at, name)) if at == address => format!("{:03X} {}", address, name),
+            Some((&at, name)) => format!("{:03X} {}+{:X}", address, name, address - at),
+            None => format!("{:03X}", address),
+        }
+    }
+}
+
+fn parse_address(text: &str) -> Option<u16> {
+    let digits = text.strip_prefix("0x").unwrap_or(text);
+    u16::from_str_radix(digits, 16).ok()
+}
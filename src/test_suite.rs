@@ -0,0 +1,206 @@
+//! `chip8 test`: runs a folder of test ROMs, such as Timendus' CHIP-8 test
+//! suite, headless and compares the final display of each with a reference
+//! bitmap.
+//!
+//! The reference for `5-quirks.ch8` is `5-quirks.txt`, one line per display
+//! row with `#` for lit and `.` for unlit pixels. A line may end with a
+//! label, which names the rows from there to the next label so they pass or
+//! fail on their own, e.g. one label per line of results on the quirks test.
+//! ROMs that need key presses, like picking a platform from a menu, get
+//! them from an input movie next to the reference (`5-quirks.c8m`).
+
+use crate::{config, headless, movie};
+use chip8::{rom, Chip8, Chip8Error};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(clap::Args)]
+pub struct TestArgs {
+    /// Folder with the test ROMs
+    suite: PathBuf,
+
+    /// Folder with the reference bitmaps and input movies, if not the one
+    /// with the ROMs
+    #[arg(long)]
+    references: Option<PathBuf>,
+
+    /// Save the final displays as the new references
+    #[arg(long)]
+    update: bool,
+
+    /// Emulated seconds to run each ROM for
+    #[arg(long, default_value_t = 10)]
+    seconds: u32,
+}
+
+pub fn run(args: TestArgs) -> Result<(), Chip8Error> {
+    let references = args.references.as_ref().unwrap_or(&args.suite);
+    let mut roms: Vec<PathBuf> = fs::read_dir(&args.suite)
+        .map_err(|source| Chip8Error::Io {
+            path: args.suite.clone(),
+            source,
+        })?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "ch8"))
+        .collect();
+    roms.sort();
+
+    let mut failed = 0;
+    for path in &roms {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        let reference_path = references.join(format!("{}.txt", name));
+        let movie_path = references.join(format!("{}.c8m", name));
+        let player = if movie_path.exists() {
+            Some(movie::Player::open(&movie_path).map_err(Chip8Error::Movie)?)
+        } else {
+            None
+        };
+        let chip8 = match run_rom(path, player, args.seconds) {
+            Ok(chip8) => chip8,
+            Err(err) => {
+                println!("ERROR {}: {}", name, err);
+                failed += 1;
+                continue;
+            }
+        };
+        let reference = fs::read_to_string(&reference_path).ok().map(parse);
+        if args.update {
+            let labels = reference
+                .map(|reference| reference.labels)
+                .unwrap_or_default();
+            match fs::write(&reference_path, format(&chip8.screen(), &labels)) {
+                Ok(()) => println!("SAVED {}", name),
+                Err(err) => {
+                    println!("ERROR {}: cannot save the reference: {}", name, err);
+                    failed += 1;
+                }
+            }
+            continue;
+        }
+        let Some(reference) = reference else {
+            println!(
+                "NEW   {}: no reference, run with --update to save one",
+                name
+            );
+            continue;
+        };
+        let results = compare(&chip8.screen(), &reference);
+        if results.iter().any(|(_, passed)| !passed) {
+            failed += 1;
+        }
+        match &results[..] {
+            [(_, passed)] if reference.labels.is_empty() => {
+                println!("{:5} {}", verdict(*passed), name)
+            }
+            _ => {
+                println!("{}", name);
+                for (label, passed) in &results {
+                    println!("  {} {}", verdict(*passed), label);
+                }
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(Chip8Error::TestsFailed {
+            failed,
+            total: roms.len(),
+        });
+    }
+    Ok(())
+}
+
+fn verdict(passed: bool) -> &'static str {
+    if passed {
+        "PASS"
+    } else {
+        "FAIL"
+    }
+}
+
+/// Runs a ROM for `seconds` of emulated time at its configured speed, with
+/// a fixed seed so every run is the same
+fn run_rom(
+    path: &Path,
+    mut player: Option<movie::Player>,
+    seconds: u32,
+) -> Result<Chip8, Chip8Error> {
+    let settings = config::load(None, &path.to_string_lossy(), None).map_err(Chip8Error::Config)?;
+    let mut chip8 = Chip8::builder()
+        .with_rom(&rom::read(path)?)
+        .with_memory_policy(settings.memory_policy)
+        .with_quirks(settings.quirks)
+        .with_seed(0)
+        .build()?;
+
+    let instructions = seconds as u64 * settings.speed as u64;
+    headless::run(&mut chip8, instructions, settings.speed, |chip8| {
+        if let Some(player) = &mut player {
+            player.apply(chip8);
+        }
+    })?;
+    Ok(chip8)
+}
+
+struct Reference {
+    rows: Vec<Vec<bool>>,
+    /// Labels and the row each one starts at
+    labels: Vec<(usize, String)>,
+}
+
+fn parse(text: String) -> Reference {
+    let mut reference = Reference {
+        rows: Vec::new(),
+        labels: Vec::new(),
+    };
+    for (y, line) in text.lines().enumerate() {
+        let (pixels, label) = line.split_once(' ').unwrap_or((line, ""));
+        reference
+            .rows
+            .push(pixels.chars().map(|c| c == '#').collect());
+        if !label.trim().is_empty() {
+            reference.labels.push((y, label.trim().to_string()));
+        }
+    }
+    reference
+}
+
+fn format(screen: &[bool], labels: &[(usize, String)]) -> String {
+    let mut text = String::new();
+    for (y, row) in screen.chunks(64).enumerate() {
+        text.extend(row.iter().map(|&on| if on { '#' } else { '.' }));
+        if let Some((_, label)) = labels.iter().find(|(start, _)| *start == y) {
+            text.push(' ');
+            text.push_str(label);
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// Whether each labelled group of rows matches, or the whole display if
+/// there are no labels
+fn compare(screen: &[bool], reference: &Reference) -> Vec<(String, bool)> {
+    let rows: Vec<&[bool]> = screen.chunks(64).collect();
+    let row_matches = |y: usize| match (reference.rows.get(y), rows.get(y)) {
+        (Some(expected), Some(row)) => expected[..] == **row,
+        _ => false,
+    };
+    if reference.labels.is_empty() {
+        let passed = reference.rows.len() == rows.len() && (0..rows.len()).all(row_matches);
+        return vec![(String::new(), passed)];
+    }
+    let mut results = Vec::new();
+    if reference.labels[0].0 > 0 {
+        let passed = (0..reference.labels[0].0).all(row_matches);
+        results.push(("(above the first label)".to_string(), passed));
+    }
+    for (i, (start, label)) in reference.labels.iter().enumerate() {
+        let end = reference
+            .labels
+            .get(i + 1)
+            .map_or(rows.len(), |(end, _)| *end);
+        results.push((label.clone(), (*start..end).all(row_matches)));
+    }
+    results
+}
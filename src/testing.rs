@@ -0,0 +1,320 @@
+//! Assertions for instruction tests, meant to be paired with
+//! [`Chip8::builder`](crate::Chip8::builder):
+//!
+//! ```
+//! use chip8::Chip8;
+//!
+//! let mut chip8 = Chip8::builder()
+//!     .with_program(&[0x80, 0x14]) // V0 += V1
+//!     .with_registers(&[0xFF, 0x01])
+//!     .build()
+//!     .unwrap();
+//! chip8.step().unwrap();
+//! chip8.assert_registers(&[0x00, 0x01]);
+//! chip8.assert_register(0xF, 1);
+//! ```
+//!
+//! The core never reads the clock: time passes only when the timers are
+//! ticked, so timing can be tested a 60th of a second at a time:
+//!
+//! ```
+//! use chip8::Chip8;
+//!
+//! let mut chip8 = Chip8::builder()
+//!     .with_program(&[0xF0, 0x15, 0xF1, 0x07]) // DT = V0, V1 = DT
+//!     .with_register(0, 3)
+//!     .build()
+//!     .unwrap();
+//! chip8.step().unwrap();
+//! chip8.tick_timers();
+//! chip8.tick_timers();
+//! chip8.step().unwrap();
+//! chip8.assert_register(1, 1);
+//! ```
+
+use crate::Chip8;
+
+impl Chip8 {
+    /// Panics unless VX holds `value`
+    #[track_caller]
+    pub fn assert_register(&self, x: usize, value: u8) {
+        assert_eq!(
+            self.registers[x], value,
+            "V{:X} is {:02X}, expected {:02X}",
+            x, self.registers[x], value
+        );
+    }
+
+    /// Panics unless V0, V1, ... hold `values` in order
+    #[track_caller]
+    pub fn assert_registers(&self, values: &[u8]) {
+        for (x, &value) in values.iter().enumerate() {
+            self.assert_register(x, value);
+        }
+    }
+
+    /// Panics unless memory starting at `addr` holds `bytes`
+    #[track_caller]
+    pub fn assert_memory(&self, addr: u16, bytes: &[u8]) {
+        let actual = &self.memory[addr as usize..addr as usize + bytes.len()];
+        assert_eq!(actual, bytes, "memory at {:03X} differs", addr);
+    }
+
+    #[track_caller]
+    pub fn assert_index(&self, index: u16) {
+        assert_eq!(
+            self.index, index,
+            "I is {:03X}, expected {:03X}",
+            self.index, index
+        );
+    }
+
+    #[track_caller]
+    pub fn assert_program_counter(&self, program_counter: u16) {
+        assert_eq!(
+            self.program_counter, program_counter,
+            "PC is {:03X}, expected {:03X}",
+            self.program_counter, program_counter
+        );
+    }
+
+    /// Panics unless the pixel at (`x`, `y`) is `on`
+    #[track_caller]
+    pub fn assert_pixel(&self, x: usize, y: usize, on: bool) {
+        let Some(lit) = self.pixel(x, y) else {
+            panic!("pixel ({}, {}) is off the display", x, y);
+        };
+        assert_eq!(
+            lit,
+            on,
+            "pixel ({}, {}) is {}",
+            x,
+            y,
+            if on { "off" } else { "on" }
+        );
+    }
+
+    /// Panics unless the top-left corner of the display matches `rows`,
+    /// where `#` is a lit pixel and anything else is unlit
+    #[track_caller]
+    pub fn assert_display(&self, rows: &[&str]) {
+        for (y, row) in rows.iter().enumerate() {
+            for (x, pixel) in row.chars().enumerate() {
+                self.assert_pixel(x, y, pixel == '#');
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Chip8, Chip8Builder, Chip8Error, Quirks};
+
+    /// A machine with `program` loaded, ready to step
+    fn machine(program: &[u8]) -> Chip8Builder {
+        Chip8::builder().with_program(program)
+    }
+
+    fn quirks(wait_for_release: bool, display_wait: bool) -> Quirks {
+        Quirks {
+            wait_for_release,
+            display_wait,
+            ..Quirks::default()
+        }
+    }
+
+    /// Every combination of the quirks, which none of the instructions
+    /// below but FX0A and DXYN should notice
+    fn every_quirk() -> Vec<Quirks> {
+        [(false, false), (false, true), (true, false), (true, true)]
+            .into_iter()
+            .map(|(wait_for_release, display_wait)| quirks(wait_for_release, display_wait))
+            .collect()
+    }
+
+    #[test]
+    fn shift_right_shifts_vx_in_place() {
+        for quirks in every_quirk() {
+            // V0 >>= 1, ignoring V1
+            let mut chip8 = machine(&[0x80, 0x16])
+                .with_quirks(quirks)
+                .with_registers(&[0b1000_0011, 0xF0])
+                .build()
+                .unwrap();
+            chip8.step().unwrap();
+            chip8.assert_registers(&[0b0100_0001, 0xF0]);
+            chip8.assert_register(0xF, 1);
+        }
+    }
+
+    #[test]
+    fn shift_left_shifts_vx_in_place() {
+        for quirks in every_quirk() {
+            // V0 <<= 1, ignoring V1
+            let mut chip8 = machine(&[0x80, 0x1E])
+                .with_quirks(quirks)
+                .with_registers(&[0b0100_0001, 0xFF])
+                .build()
+                .unwrap();
+            chip8.step().unwrap();
+            chip8.assert_registers(&[0b1000_0010, 0xFF]);
+            chip8.assert_register(0xF, 0);
+        }
+    }
+
+    #[test]
+    fn store_leaves_i_unchanged() {
+        for quirks in every_quirk() {
+            // Store V0-V2 at I
+            let mut chip8 = machine(&[0xF2, 0x55])
+                .with_quirks(quirks)
+                .with_registers(&[1, 2, 3, 4])
+                .with_index(0x300)
+                .build()
+                .unwrap();
+            chip8.step().unwrap();
+            chip8.assert_memory(0x300, &[1, 2, 3, 0]);
+            chip8.assert_index(0x300);
+        }
+    }
+
+    #[test]
+    fn restore_leaves_i_unchanged() {
+        for quirks in every_quirk() {
+            // Read V0-V2 from I
+            let mut chip8 = machine(&[0xF2, 0x65])
+                .with_quirks(quirks)
+                .with_memory(0x300, &[5, 6, 7, 8])
+                .with_registers(&[0, 0, 0, 9])
+                .with_index(0x300)
+                .build()
+                .unwrap();
+            chip8.step().unwrap();
+            chip8.assert_registers(&[5, 6, 7, 9]);
+            chip8.assert_index(0x300);
+        }
+    }
+
+    #[test]
+    fn store_past_the_end_of_memory_is_an_error() {
+        let mut chip8 = machine(&[0xF1, 0x55]).with_index(0xFFF).build().unwrap();
+        let err = chip8.step().unwrap_err();
+        assert!(
+            matches!(err, Chip8Error::MemoryOutOfBounds { target: 0x1000, .. }),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn jump_with_offset_adds_v0() {
+        for quirks in every_quirk() {
+            // Jump to 300 + V0, with V3 holding something else
+            let mut chip8 = machine(&[0xB3, 0x00])
+                .with_quirks(quirks)
+                .with_register(0, 0x10)
+                .with_register(3, 0x40)
+                .build()
+                .unwrap();
+            chip8.step().unwrap();
+            chip8.assert_program_counter(0x310);
+        }
+    }
+
+    #[test]
+    fn draw_wraps_past_the_right_and_bottom_edges() {
+        // Draw the font's 0 at (62, 30)
+        let mut chip8 = machine(&[0xF2, 0x29, 0xD0, 0x15])
+            .with_registers(&[62, 30, 0])
+            .build()
+            .unwrap();
+        chip8.step().unwrap();
+        chip8.step().unwrap();
+        // F0 90 90 90 F0, split across the four corners
+        for (y, row) in [(30, "##"), (31, "#."), (0, "#."), (1, "#."), (2, "##")] {
+            for (dx, pixel) in row.chars().enumerate() {
+                chip8.assert_pixel(62 + dx, y, pixel == '#');
+            }
+        }
+        for (y, row) in [(30, "##"), (31, ".#"), (0, ".#"), (1, ".#"), (2, "##")] {
+            for (x, pixel) in row.chars().enumerate() {
+                chip8.assert_pixel(x, y, pixel == '#');
+            }
+        }
+        chip8.assert_register(0xF, 0);
+    }
+
+    #[test]
+    fn draw_wraps_coordinates_off_the_screen() {
+        // Draw the font's 0 at (64 + 1, 32 + 2), which is (1, 2)
+        let mut chip8 = machine(&[0xF2, 0x29, 0xD0, 0x15])
+            .with_registers(&[65, 34, 0])
+            .build()
+            .unwrap();
+        chip8.step().unwrap();
+        chip8.step().unwrap();
+        chip8.assert_pixel(1, 2, true);
+        chip8.assert_pixel(4, 3, true);
+        chip8.assert_pixel(2, 3, false);
+        chip8.assert_pixel(0, 2, false);
+    }
+
+    #[test]
+    fn draw_sets_vf_when_it_turns_pixels_off() {
+        // Draw the font's 0 twice in the same place
+        let mut chip8 = machine(&[0xF2, 0x29, 0xD0, 0x15, 0xD0, 0x15])
+            .build()
+            .unwrap();
+        chip8.step().unwrap();
+        chip8.step().unwrap();
+        chip8.assert_register(0xF, 0);
+        chip8.assert_display(&["####", "#..#", "#..#", "#..#", "####"]);
+        chip8.step().unwrap();
+        chip8.assert_register(0xF, 1);
+        chip8.assert_display(&["....", "....", "....", "....", "...."]);
+    }
+
+    #[test]
+    fn draw_waits_for_the_next_frame_with_display_wait() {
+        for display_wait in [false, true] {
+            // Draw twice
+            let mut chip8 = machine(&[0xF2, 0x29, 0xD0, 0x15, 0xD0, 0x15])
+                .with_quirks(quirks(true, display_wait))
+                .build()
+                .unwrap();
+            chip8.tick_timers();
+            chip8.step().unwrap();
+            chip8.step().unwrap();
+            chip8.assert_pixel(0, 0, true);
+            chip8.step().unwrap();
+            // Waiting leaves PC on the second DXYN until the timers tick
+            let waiting = if display_wait { 0x204 } else { 0x206 };
+            chip8.assert_program_counter(waiting);
+            chip8.assert_pixel(0, 0, display_wait);
+            chip8.tick_timers();
+            chip8.step().ok();
+            chip8.assert_pixel(0, 0, false);
+        }
+    }
+
+    #[test]
+    fn wait_key_finishes_on_press_or_release() {
+        for wait_for_release in [false, true] {
+            // Wait for a key into V0
+            let mut chip8 = machine(&[0xF0, 0x0A])
+                .with_quirks(quirks(wait_for_release, false))
+                .with_keys(&[7])
+                .build()
+                .unwrap();
+            chip8.step().unwrap();
+            if wait_for_release {
+                chip8.assert_program_counter(0x200);
+                assert!(chip8.blocked_on_key);
+                chip8.keys[7] = false;
+                chip8.step().unwrap();
+            }
+            chip8.assert_program_counter(0x202);
+            chip8.assert_register(0, 7);
+        }
+    }
+}
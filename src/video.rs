@@ -0,0 +1,162 @@
+//! Video recording through an ffmpeg child process. Frames and the beep
+//! are sampled at 60 Hz and piped raw: video on ffmpeg's stdin, sound
+//! through a named pipe, so the output is pixel-perfect and in sync.
+
+use crate::config::Palette;
+use crate::frame::SharedFrame;
+use crate::log;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const SAMPLE_RATE: u32 = 44100;
+const SAMPLES_PER_FRAME: u32 = SAMPLE_RATE / 60;
+const BEEP_HZ: u32 = 440;
+const BEEP_VOLUME: i16 = i16::MAX / 4;
+
+pub struct Recording {
+    ffmpeg: Child,
+    stop: Arc<AtomicBool>,
+    capture: JoinHandle<()>,
+}
+
+impl Recording {
+    pub fn start(path: &Path, frame: SharedFrame, palette: Palette) -> io::Result<Self> {
+        let fifo = std::env::temp_dir().join(format!("chip8-audio-{}", std::process::id()));
+        let with_audio = Command::new("mkfifo")
+            .arg(&fifo)
+            .status()
+            .is_ok_and(|status| status.success());
+        if !with_audio {
+            log::warn!("Cannot create a pipe for the sound, recording video only");
+        }
+
+        let height = frame.lock().unwrap().height();
+        let mut command = Command::new("ffmpeg");
+        command
+            .args(["-y", "-loglevel", "error"])
+            .args(["-f", "rawvideo", "-pixel_format", "rgb24"])
+            .args(["-video_size", &format!("64x{}", height)])
+            .args(["-framerate", "60", "-i", "pipe:0"]);
+        if with_audio {
+            let sample_rate = SAMPLE_RATE.to_string();
+            command
+                .args(["-f", "s16le", "-ar", &sample_rate, "-ac", "1", "-i"])
+                .arg(&fifo);
+        }
+        let mut ffmpeg = command
+            .args(["-vf", &format!("scale=640:{}:flags=neighbor", height * 10)])
+            .args(["-pix_fmt", "yuv420p"])
+            .arg(path)
+            .stdin(Stdio::piped())
+            .spawn()?;
+
+        let video = ffmpeg.stdin.take().unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let capture = {
+            let stop = stop.clone();
+            let fifo = with_audio.then_some(fifo);
+            thread::spawn(move || capture(frame, palette, video, fifo, &stop))
+        };
+        Ok(Self {
+            ffmpeg,
+            stop,
+            capture,
+        })
+    }
+
+    /// Stops capturing and waits for ffmpeg to finish writing the file
+    pub fn finish(mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.capture.join().unwrap();
+        match self.ffmpeg.wait() {
+            Ok(status) if !status.success() => log::warn!("ffmpeg failed: {}", status),
+            Err(err) => log::warn!("Cannot wait for ffmpeg: {}", err),
+            Ok(_) => (),
+        }
+    }
+}
+
+fn capture(
+    frame: SharedFrame,
+    palette: Palette,
+    video: ChildStdin,
+    fifo: Option<PathBuf>,
+    stop: &AtomicBool,
+) {
+    let (video_tx, video_rx) = mpsc::channel();
+    let video_writer = thread::spawn(move || write_all(video, video_rx));
+    let (audio_tx, audio_rx) = mpsc::channel();
+    let audio_writer = fifo.clone().map(|fifo| {
+        thread::spawn(move || {
+            // Opening blocks until ffmpeg opens the other end, after which
+            // the name is no longer needed
+            let audio = OpenOptions::new().write(true).open(&fifo);
+            let _ = fs::remove_file(&fifo);
+            match audio {
+                Ok(audio) => write_all(audio, audio_rx),
+                Err(err) => log::warn!("Cannot open the sound pipe: {}", err),
+            }
+        })
+    });
+
+    let frame_time = Duration::from_secs_f64(1.0 / 60.0);
+    let mut next = Instant::now();
+    let mut sample = 0u32;
+    while !stop.load(Ordering::Relaxed) {
+        let (display, beeping) = {
+            let frame = frame.lock().unwrap();
+            (frame.display.clone(), frame.beeping)
+        };
+        let mut rgb = vec![0; display.len() * 3];
+        for (pixel, &on) in rgb.chunks_exact_mut(3).zip(display.iter()) {
+            pixel.copy_from_slice(&palette.shade(on as u8 as f32));
+        }
+        let _ = video_tx.send(rgb);
+
+        let mut pcm = Vec::with_capacity(SAMPLES_PER_FRAME as usize * 2);
+        for _ in 0..SAMPLES_PER_FRAME {
+            let half_periods = sample % SAMPLE_RATE * BEEP_HZ * 2 / SAMPLE_RATE;
+            let high = half_periods & 1 == 0;
+            let value = match (beeping, high) {
+                (false, _) => 0,
+                (true, true) => BEEP_VOLUME,
+                (true, false) => -BEEP_VOLUME,
+            };
+            pcm.extend_from_slice(&value.to_le_bytes());
+            sample = sample.wrapping_add(1);
+        }
+        let _ = audio_tx.send(pcm);
+
+        next += frame_time;
+        thread::sleep(next.saturating_duration_since(Instant::now()));
+    }
+
+    // Closing the pipes tells ffmpeg the recording is over
+    drop(video_tx);
+    drop(audio_tx);
+    video_writer.join().unwrap();
+    if let (Some(audio_writer), Some(fifo)) = (audio_writer, fifo) {
+        if !audio_writer.is_finished() && fifo.exists() {
+            // ffmpeg never opened the sound pipe, so stand in for it to let
+            // the writer go
+            let _ = fs::File::open(&fifo);
+        }
+        audio_writer.join().unwrap();
+    }
+}
+
+fn write_all<W: Write>(mut out: W, chunks: Receiver<Vec<u8>>) {
+    for chunk in chunks {
+        if out.write_all(&chunk).is_err() {
+            // ffmpeg went away; it reports why itself
+            return;
+        }
+    }
+}
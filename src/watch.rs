@@ -0,0 +1,170 @@
+//! Watch expressions for the debugger: small arithmetic over the machine,
+//! like `V0`, `[I+2]` or `VA*16+VB`. Numbers are hex, as everywhere else in
+//! the debugger, and `[...]` reads the byte of memory at an address.
+//! `*` binds tighter than `+` and `-`, which bind tighter than `&` and `|`.
+//! A register or a `[...]` can also be assigned to, for the debugger's `set`.
+
+use chip8::Chip8;
+
+pub enum Expr {
+    Number(i64),
+    Register(usize),
+    Index,
+    ProgramCounter,
+    StackPointer,
+    DelayTimer,
+    SoundTimer,
+    Memory(Box<Expr>),
+    Binary(Box<Expr>, char, Box<Expr>),
+}
+
+impl Expr {
+    pub fn parse(text: &str) -> Option<Expr> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.level(0)?;
+        (parser.pos == parser.tokens.len()).then_some(expr)
+    }
+
+    pub fn eval(&self, chip8: &Chip8) -> i64 {
+        match self {
+            Expr::Number(value) => *value,
+            Expr::Register(x) => chip8.registers[*x] as i64,
+            Expr::Index => chip8.index as i64,
+            Expr::ProgramCounter => chip8.program_counter as i64,
+            Expr::StackPointer => chip8.stack_pointer as i64,
+            Expr::DelayTimer => chip8.delay_timer as i64,
+            Expr::SoundTimer => chip8.sound_timer as i64,
+            Expr::Memory(address) => chip8.peek(address.eval(chip8) as u16) as i64,
+            Expr::Binary(left, op, right) => {
+                let (left, right) = (left.eval(chip8), right.eval(chip8));
+                match op {
+                    '+' => left.wrapping_add(right),
+                    '-' => left.wrapping_sub(right),
+                    '*' => left.wrapping_mul(right),
+                    '&' => left & right,
+                    _ => left | right,
+                }
+            }
+        }
+    }
+
+    /// Stores `value` where this expression reads from, failing if it
+    /// isn't a register or memory or the value doesn't fit
+    pub fn assign(&self, chip8: &mut Chip8, value: i64) -> Result<(), String> {
+        let byte = || u8::try_from(value).map_err(|_| format!("{:X} doesn't fit in a byte", value));
+        let word =
+            || u16::try_from(value).map_err(|_| format!("{:X} doesn't fit in 16 bits", value));
+        match self {
+            Expr::Register(x) => chip8.registers[*x] = byte()?,
+            Expr::Index => chip8.index = word()?,
+            Expr::ProgramCounter => chip8.program_counter = word()?,
+            Expr::StackPointer => match word()? {
+                sp if sp as usize <= chip8.stack.len() => chip8.stack_pointer = sp,
+                _ => return Err(format!("The stack only has {} entries", chip8.stack.len())),
+            },
+            Expr::DelayTimer => chip8.delay_timer = byte()?,
+            Expr::SoundTimer => chip8.sound_timer = byte()?,
+            Expr::Memory(address) => {
+                let address = address.eval(chip8) as u16;
+                chip8.poke(address, byte()?);
+            }
+            Expr::Number(_) | Expr::Binary(..) => {
+                return Err("Only a register or [address] can be set".into())
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Symbol(char),
+}
+
+fn tokenize(text: &str) -> Option<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_alphanumeric() {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_ascii_alphanumeric()) {
+                word.push(c.to_ascii_uppercase());
+                chars.next();
+            }
+            tokens.push(Token::Word(word));
+        } else if "+-*&|[]()".contains(c) {
+            tokens.push(Token::Symbol(c));
+            chars.next();
+        } else {
+            return None;
+        }
+    }
+    Some(tokens)
+}
+
+/// Operators by how loosely they bind
+const LEVELS: [&str; 3] = ["&|", "+-", "*"];
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, symbol: char) -> Option<()> {
+        (self.next()? == Token::Symbol(symbol)).then_some(())
+    }
+
+    fn level(&mut self, level: usize) -> Option<Expr> {
+        if level == LEVELS.len() {
+            return self.atom();
+        }
+        let mut expr = self.level(level + 1)?;
+        while let Some(Token::Symbol(op)) = self.tokens.get(self.pos).cloned() {
+            if !LEVELS[level].contains(op) {
+                break;
+            }
+            self.pos += 1;
+            let right = self.level(level + 1)?;
+            expr = Expr::Binary(Box::new(expr), op, Box::new(right));
+        }
+        Some(expr)
+    }
+
+    fn atom(&mut self) -> Option<Expr> {
+        match self.next()? {
+            Token::Symbol('[') => {
+                let address = self.level(0)?;
+                self.expect(']')?;
+                Some(Expr::Memory(Box::new(address)))
+            }
+            Token::Symbol('(') => {
+                let expr = self.level(0)?;
+                self.expect(')')?;
+                Some(expr)
+            }
+            Token::Word(word) => Some(match word.as_str() {
+                "I" => Expr::Index,
+                "PC" => Expr::ProgramCounter,
+                "SP" => Expr::StackPointer,
+                "DT" => Expr::DelayTimer,
+                "ST" => Expr::SoundTimer,
+                _ => match word.strip_prefix('V') {
+                    Some(x) if x.len() == 1 => Expr::Register(usize::from_str_radix(x, 16).ok()?),
+                    _ => Expr::Number(i64::from_str_radix(&word, 16).ok()?),
+                },
+            }),
+            Token::Symbol(_) => None,
+        }
+    }
+}
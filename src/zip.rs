@@ -0,0 +1,308 @@
+//! Taking ROMs out of zip archives, since ROM collections are almost always
+//! distributed zipped. Only stored and deflated entries are supported, which
+//! is what every zip tool writes by default.
+
+use crate::png;
+
+/// Largest entry to unpack; anything bigger can't be a CHIP-8 ROM
+const MAX_SIZE: usize = 65536;
+
+/// Extensions of the entries picked when no name is given
+const ROM_EXTENSIONS: &[&str] = &[".ch8", ".sc8"];
+
+pub fn is_zip(data: &[u8]) -> bool {
+    data.starts_with(b"PK\x03\x04")
+}
+
+/// Unpacks the entry called `name`, or the first CHIP-8 ROM in the archive
+pub fn extract(data: &[u8], name: Option<&str>) -> Result<Vec<u8>, String> {
+    let entries = entries(data)?;
+    let entry = match name {
+        Some(name) => entries
+            .iter()
+            .find(|entry| entry.name == name)
+            .ok_or_else(|| format!("The archive has no entry called {}", name))?,
+        None => entries
+            .iter()
+            .find(|entry| {
+                let lower = entry.name.to_ascii_lowercase();
+                ROM_EXTENSIONS.iter().any(|ext| lower.ends_with(ext))
+            })
+            .ok_or("The archive has no .ch8 or .sc8 file; add #name to pick an entry")?,
+    };
+
+    let header = data
+        .get(entry.offset..entry.offset + 30)
+        .filter(|header| header.starts_with(b"PK\x03\x04"))
+        .ok_or("The archive is damaged: bad local header")?;
+    let start = entry.offset + 30 + u16_at(header, 26) as usize + u16_at(header, 28) as usize;
+    let compressed = data
+        .get(start..start + entry.compressed_size)
+        .ok_or("The archive is damaged: entry runs past the end")?;
+    let contents = match entry.method {
+        0 => compressed.to_vec(),
+        8 => inflate(compressed)?,
+        method => {
+            return Err(format!(
+                "{} uses compression method {}, only stored and deflated entries are supported",
+                entry.name, method
+            ))
+        }
+    };
+    if png::crc32(&contents) != entry.crc32 {
+        return Err(format!("{} is damaged: CRC mismatch", entry.name));
+    }
+    Ok(contents)
+}
+
+struct Entry {
+    name: String,
+    method: u16,
+    crc32: u32,
+    compressed_size: usize,
+    /// Offset of the local header
+    offset: usize,
+}
+
+fn u16_at(data: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([data[at], data[at + 1]])
+}
+
+fn u32_at(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
+
+/// Reads the central directory, which unlike the local headers always has
+/// the sizes filled in
+fn entries(data: &[u8]) -> Result<Vec<Entry>, String> {
+    // The end record is at least 22 bytes, followed by a comment of up to
+    // 64K
+    let end = (0..data.len().saturating_sub(21))
+        .rev()
+        .take(22 + 65535)
+        .find(|&at| data[at..].starts_with(b"PK\x05\x06"))
+        .ok_or("The archive is damaged: no end of central directory")?;
+    let count = u16_at(data, end + 10) as usize;
+    let mut at = u32_at(data, end + 16) as usize;
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        let header = data
+            .get(at..at + 46)
+            .filter(|header| header.starts_with(b"PK\x01\x02"))
+            .ok_or("The archive is damaged: bad central directory")?;
+        let name_len = u16_at(header, 28) as usize;
+        let extra_len = u16_at(header, 30) as usize;
+        let comment_len = u16_at(header, 32) as usize;
+        let name = data
+            .get(at + 46..at + 46 + name_len)
+            .ok_or("The archive is damaged: bad central directory")?;
+        entries.push(Entry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            method: u16_at(header, 10),
+            crc32: u32_at(header, 16),
+            compressed_size: u32_at(header, 20) as usize,
+            offset: u32_at(header, 42) as usize,
+        });
+        at += 46 + name_len + extra_len + comment_len;
+    }
+    Ok(entries)
+}
+
+/// Base lengths and extra bits of length codes 257 to 285
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+/// Base distances and extra bits of distance codes 0 to 29
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order the code length code lengths of a dynamic block are stored in
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+struct Bits<'a> {
+    data: &'a [u8],
+    /// Position in bits
+    position: usize,
+}
+
+impl Bits<'_> {
+    fn read(&mut self, count: u32) -> Result<u32, String> {
+        let mut value = 0;
+        for i in 0..count {
+            let byte = self
+                .data
+                .get(self.position / 8)
+                .ok_or("The archive is damaged: compressed data ends early")?;
+            value |= ((*byte as u32 >> (self.position % 8)) & 1) << i;
+            self.position += 1;
+        }
+        Ok(value)
+    }
+}
+
+/// Canonical Huffman code, as the number of codes of each length and the
+/// symbols in code order
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut symbols: Vec<u16> = (0..lengths.len() as u16)
+            .filter(|&symbol| lengths[symbol as usize] != 0)
+            .collect();
+        symbols.sort_by_key(|&symbol| lengths[symbol as usize]);
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Result<u16, String> {
+        // Codes of each length are consecutive, starting right after the
+        // shorter ones shifted left by a bit
+        let (mut code, mut first, mut index) = (0u32, 0u32, 0usize);
+        for &count in &self.counts[1..] {
+            code |= bits.read(1)?;
+            let count = count as u32;
+            if code < first + count {
+                return Ok(self.symbols[index + (code - first) as usize]);
+            }
+            index += count as usize;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("The archive is damaged: invalid Huffman code".into())
+    }
+}
+
+/// Decompresses raw DEFLATE data (RFC 1951)
+fn inflate(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut bits = Bits { data, position: 0 };
+    let mut out = Vec::new();
+    loop {
+        let last = bits.read(1)? == 1;
+        match bits.read(2)? {
+            0 => {
+                let start = bits.position.div_ceil(8);
+                let header = data
+                    .get(start..start + 4)
+                    .ok_or("The archive is damaged: compressed data ends early")?;
+                let len = u16_at(header, 0) as usize;
+                let block = data
+                    .get(start + 4..start + 4 + len)
+                    .ok_or("The archive is damaged: compressed data ends early")?;
+                out.extend_from_slice(block);
+                bits.position = (start + 4 + len) * 8;
+            }
+            1 => {
+                let mut lengths = [8; 288];
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                inflate_block(
+                    &mut bits,
+                    &mut out,
+                    &Huffman::new(&lengths),
+                    &Huffman::new(&[5; 30]),
+                )?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut bits)?;
+                inflate_block(&mut bits, &mut out, &literals, &distances)?;
+            }
+            _ => return Err("The archive is damaged: invalid block type".into()),
+        }
+        if out.len() > MAX_SIZE {
+            return Err("The entry is too large to be a CHIP-8 ROM".into());
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn dynamic_codes(bits: &mut Bits) -> Result<(Huffman, Huffman), String> {
+    let literal_count = bits.read(5)? as usize + 257;
+    let distance_count = bits.read(5)? as usize + 1;
+    let code_length_count = bits.read(4)? as usize + 4;
+    let mut code_lengths = [0; 19];
+    for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[symbol] = bits.read(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths);
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (length, repeat) = match code_lengths.decode(bits)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths
+                    .last()
+                    .ok_or("The archive is damaged: repeat with no length before it")?;
+                (previous, 3 + bits.read(2)?)
+            }
+            17 => (0, 3 + bits.read(3)?),
+            _ => (0, 11 + bits.read(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(length, repeat as usize));
+    }
+    if lengths.len() > literal_count + distance_count {
+        return Err("The archive is damaged: too many code lengths".into());
+    }
+    Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ))
+}
+
+fn inflate_block(
+    bits: &mut Bits,
+    out: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), String> {
+    loop {
+        let symbol = literals.decode(bits)? as usize;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            257..=285 => {
+                let code = symbol - 257;
+                let len =
+                    LENGTH_BASE[code] as usize + bits.read(LENGTH_EXTRA[code] as u32)? as usize;
+                let code = distances.decode(bits)? as usize;
+                if code >= 30 {
+                    return Err("The archive is damaged: invalid distance".into());
+                }
+                let distance =
+                    DISTANCE_BASE[code] as usize + bits.read(DISTANCE_EXTRA[code] as u32)? as usize;
+                let start = out
+                    .len()
+                    .checked_sub(distance)
+                    .ok_or("The archive is damaged: distance past the start")?;
+                // The copy can overlap what it's producing
+                for i in 0..len {
+                    out.push(out[start + i]);
+                }
+                if out.len() > MAX_SIZE {
+                    return Err("The entry is too large to be a CHIP-8 ROM".into());
+                }
+            }
+            _ => return Err("The archive is damaged: invalid literal".into()),
+        }
+    }
+}